@@ -0,0 +1,313 @@
+use crate::command::{
+    ACParams, Command, DCParams, DCSweep, Probe, SweepType, SweepValues, SweepVar, TranParams,
+};
+use crate::device;
+use crate::device::Stamp;
+use crate::engine::Engine;
+use crate::parser::check_elems::{check_elems, Finding};
+use crate::parser::Netlist;
+
+pub use crate::command::Source;
+
+// Builds the same elements and commands `parse_spice_file` reads from a native
+// netlist. Nodes are given in the order they'd be written on the element's line.
+#[derive(Default)]
+pub struct Circuit {
+    elems: Vec<Box<dyn Stamp>>,
+    cmds: Vec<Command>,
+}
+
+impl Circuit {
+    pub fn new() -> Self {
+        Circuit::default()
+    }
+
+    pub fn resistor(self, name: &str, n1: &str, n2: &str, val: f64) -> Self {
+        self.element(device::res::Res {
+            name: String::from(name),
+            nodes: vec![String::from(n1), String::from(n2)],
+            val,
+        })
+    }
+
+    pub fn capacitor(self, name: &str, n1: &str, n2: &str, val: f64) -> Self {
+        self.element(device::cap::Cap {
+            name: String::from(name),
+            nodes: vec![String::from(n2), String::from(n1)],
+            val,
+            u_curr: None,
+            i_curr: None,
+            u_prev: None,
+            ic: None,
+        })
+    }
+
+    pub fn inductor(self, name: &str, n1: &str, n2: &str, val: f64) -> Self {
+        self.element(device::ind::Ind {
+            name: String::from(name),
+            nodes: vec![String::from(n2), String::from(n1)],
+            val,
+            u_curr: None,
+            i_curr: None,
+            i_prev: None,
+            ic: None,
+        })
+    }
+
+    pub fn vsource(self, name: &str, n1: &str, n2: &str, source: impl Into<Source>) -> Self {
+        let source = source.into();
+
+        self.element(device::vdd::Vdd {
+            name: String::from(name),
+            nodes: vec![String::from(n2), String::from(n1)],
+            val: source.dc,
+            ac_mag: source.ac_mag,
+            ac_phase: source.ac_phase,
+            tran_fn: source.tran_fn,
+        })
+    }
+
+    pub fn isource(self, name: &str, n1: &str, n2: &str, source: impl Into<Source>) -> Self {
+        let source = source.into();
+
+        self.element(device::idd::Idd {
+            name: String::from(name),
+            nodes: vec![String::from(n2), String::from(n1)],
+            val: source.dc,
+            ac_mag: source.ac_mag,
+            ac_phase: source.ac_phase,
+            tran_fn: source.tran_fn,
+        })
+    }
+
+    pub fn vcvs(self, name: &str, nodes: [&str; 4], gain: f64) -> Self {
+        self.element(device::vcvs::VCVS {
+            name: String::from(name),
+            nodes: nodes.iter().map(|n| String::from(*n)).collect(),
+            val: gain,
+        })
+    }
+
+    pub fn vccs(self, name: &str, nodes: [&str; 4], gm: f64) -> Self {
+        self.element(device::vccs::VCCS {
+            name: String::from(name),
+            nodes: nodes.iter().map(|n| String::from(*n)).collect(),
+            val: gm,
+        })
+    }
+
+    pub fn cccs(self, name: &str, n1: &str, n2: &str, ctrl: &str, gain: f64) -> Self {
+        self.element(device::cccs::CCCS {
+            name: String::from(name),
+            nodes: vec![String::from(n1), String::from(n2)],
+            ctrl: String::from(ctrl),
+            val: gain,
+        })
+    }
+
+    pub fn ccvs(self, name: &str, n1: &str, n2: &str, ctrl: &str, r: f64) -> Self {
+        self.element(device::ccvs::CCVS {
+            name: String::from(name),
+            nodes: vec![String::from(n1), String::from(n2)],
+            ctrl: String::from(ctrl),
+            val: r,
+        })
+    }
+
+    // Cathode first, as in a native netlist
+    pub fn diode(
+        self,
+        name: &str,
+        cathode: &str,
+        anode: &str,
+        params: device::diode::model::Params,
+    ) -> Self {
+        self.element(device::diode::Diode {
+            name: String::from(name),
+            nodes: vec![String::from(anode), String::from(cathode)],
+            params,
+            vd_old: 0.0,
+        })
+    }
+
+    pub fn npn(self, name: &str, nodes: [&str; 3], params: device::npn::model::Params) -> Self {
+        self.element(device::npn::NPN {
+            name: String::from(name),
+            nodes: nodes.iter().map(|n| String::from(*n)).collect(),
+            params,
+            vbe_old: 0.0,
+            vbc_old: 0.0,
+        })
+    }
+
+    // Drain, gate and source. `KP` isn't scaled, there's no `W` or `L` here.
+    pub fn nmos(self, name: &str, nodes: [&str; 3], params: device::nmos::model::Params) -> Self {
+        self.element(device::nmos::NMOS {
+            name: String::from(name),
+            nodes: nodes.iter().map(|n| String::from(*n)).collect(),
+            params,
+            vgs_old: 0.0,
+            vds_old: 0.0,
+        })
+    }
+
+    // Any other element, e.g. one with initial conditions or a device of your own
+    pub fn element(mut self, elem: impl Stamp + 'static) -> Self {
+        self.elems.push(Box::new(elem));
+        self
+    }
+
+    pub fn op(self) -> Self {
+        self.command(Command::Op)
+    }
+
+    pub fn dc(self, elem: &str, start: f64, stop: f64, step: f64) -> Self {
+        self.command(Command::DC(DCParams {
+            sweeps: vec![DCSweep {
+                var: SweepVar::Elem(String::from(elem)),
+                values: SweepValues::Step { start, stop, step },
+            }],
+        }))
+    }
+
+    pub fn tran(self, stop: f64, step: f64) -> Self {
+        self.command(Command::Tran(TranParams {
+            start: 0.0,
+            stop,
+            step,
+            tmax: None,
+            uic: false,
+        }))
+    }
+
+    pub fn ac(self, sweep: SweepType, points: usize, start: f64, stop: f64) -> Self {
+        self.command(Command::AC(ACParams {
+            sweep,
+            points,
+            start,
+            stop,
+        }))
+    }
+
+    // The current through an element, or into a transistor's terminal, as in `I(Q1,c)`
+    pub fn probe(self, elem: &str, terminal: Option<&str>) -> Self {
+        self.command(Command::Probe(vec![Probe {
+            elem: String::from(elem),
+            terminal: terminal.map(str::to_lowercase),
+        }]))
+    }
+
+    // Analyses and `.options`, `.ic` or `.nodeset`, like the dot commands of a netlist
+    pub fn command(mut self, cmd: Command) -> Self {
+        self.cmds.push(cmd);
+        self
+    }
+
+    // Same as `Engine::check`, before anything is built
+    pub fn check(&self) -> Vec<Finding> {
+        check_elems(&self.elems)
+    }
+
+    pub fn build(self) -> Netlist {
+        (self.elems, self.cmds)
+    }
+
+    pub fn engine(self) -> Engine<'static> {
+        Engine::new(self.elems, self.cmds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::dialect::Dialect;
+    use crate::parser::models::{self, Model};
+    use crate::parser::parse_spice_file;
+    use crate::spice_fn::SpiceFn;
+
+    fn assert_same_elems(built: &[Box<dyn Stamp>], parsed: &[Box<dyn Stamp>]) {
+        assert_eq!(built.len(), parsed.len());
+        for (b, p) in built.iter().zip(parsed.iter()) {
+            assert_eq!(b.get_name(), p.get_name());
+            assert_eq!(b.get_nodes(), p.get_nodes());
+        }
+    }
+
+    #[test]
+    fn test_same_as_netlist() {
+        let t_model = match models::builtin_models()["t_model"] {
+            Model::NMOS(p) => p,
+            _ => unreachable!(),
+        };
+        let (elems, _) = Circuit::new()
+            .vsource("V01", "1", "0", 0.8)
+            .vsource("V02", "2", "0", 5.0)
+            .resistor("R23", "2", "3", 1000.0)
+            .nmos("M310", ["3", "1", "0"], t_model)
+            .build();
+        let (parsed, _) = parse_spice_file("test/nmos_test.sp", Dialect::Native).unwrap();
+
+        assert_same_elems(&elems, &parsed);
+        assert_eq!(elems[2].get_value(), parsed[2].get_value());
+    }
+
+    #[test]
+    fn test_source_same_as_netlist() {
+        let (elems, cmds) = Circuit::new()
+            .vsource("V01", "1", "0", Source::dc(0.0).ac(1.0, 0.0))
+            .resistor("R12", "1", "2", 1000.0)
+            .capacitor("C20", "2", "0", 10e-12)
+            .ac(SweepType::Dec, 5, 1e6, 1e9)
+            .build();
+        let (parsed, parsed_cmds) = parse_spice_file("test/rc_ac.sp", Dialect::Native).unwrap();
+
+        assert_same_elems(&elems, &parsed);
+        assert_eq!(cmds.len(), parsed_cmds.len());
+        assert_eq!(cmds[0].to_string(), parsed_cmds[0].to_string());
+    }
+
+    #[test]
+    fn test_engine() {
+        let mut engine = Circuit::new()
+            .vsource("V1", "1", "0", 4.0)
+            .resistor("R12", "1", "2", 2200.0)
+            .resistor("R20", "2", "0", 2200.0)
+            .op()
+            .engine();
+
+        let op = engine.run_op().unwrap();
+
+        assert!((op.get("2")[0] - 2.0).abs() < 1e-6);
+        assert_eq!(engine.analyses.len(), 1);
+    }
+
+    #[test]
+    fn test_check() {
+        let circuit = Circuit::new()
+            .vsource("V1", "1", "0", 4.0)
+            .resistor("R12", "1", "2", 0.0)
+            .capacitor("C23", "2", "3", 1e-9);
+
+        let messages = circuit
+            .check()
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(messages[0], "error: R12 has a zero resistance");
+        assert_eq!(circuit.engine().check().len(), messages.len());
+    }
+
+    #[test]
+    fn test_source_tran() {
+        let source = Source::tran(SpiceFn::Sine(crate::spice_fn::SineParams {
+            offset: 1.0,
+            amplitude: 2.0,
+            freq: 1e3,
+            delay: 0.0,
+            damping: 0.0,
+        }));
+
+        assert_eq!(source.dc, 1.0);
+        assert!(source.tran_fn.is_some());
+    }
+}
@@ -1,21 +1,240 @@
-#[derive(Debug)]
+use std::fmt;
+
+use crate::integration::Method;
+use crate::spice_fn::SpiceFn;
+
+#[derive(Debug, Clone)]
 pub enum Command {
+    Options(OptionsParams),
+    // Node voltages the transient starts from
+    IC(Vec<(String, f64)>),
+    // Newton's initial guess for the operating point
+    NodeSet(Vec<(String, f64)>),
+    // Element currents added to the results of `.op`, `.dc` and `.tran`
+    Probe(Vec<Probe>),
     Op,
     DC(DCParams),
     Tran(TranParams),
+    AC(ACParams),
+}
+
+// Names the analysis above its results
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Op => write!(f, "op"),
+            Command::DC(p) => {
+                write!(f, "dc")?;
+                for sweep in p.sweeps.iter() {
+                    write!(f, " {} {}", sweep.var, sweep.values)?;
+                }
+                Ok(())
+            }
+            Command::Tran(p) => {
+                write!(f, "tran step={:e} stop={:e}", p.step, p.stop)?;
+                if p.start > 0.0 {
+                    write!(f, " start={:e}", p.start)?;
+                }
+                if let Some(tmax) = p.tmax {
+                    write!(f, " tmax={:e}", tmax)?;
+                }
+                if p.uic {
+                    write!(f, " uic")?;
+                }
+                Ok(())
+            }
+            Command::AC(p) => write!(
+                f,
+                "ac {} points={} start={:e} stop={:e}",
+                p.sweep, p.points, p.start, p.stop
+            ),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+// Column label, as in `I(R1)` or `I(Q1,c)`
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.terminal {
+            Some(t) => write!(f, "I({},{})", self.elem, t),
+            None => write!(f, "I({})", self.elem),
+        }
+    }
+}
+
+impl fmt::Display for SweepVar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SweepVar::Elem(name) => write!(f, "{}", name),
+            SweepVar::Temp => write!(f, "temp"),
+            SweepVar::Param(name) => write!(f, "param {}", name),
+        }
+    }
+}
+
+impl fmt::Display for SweepValues {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SweepValues::Step { start, stop, step } => {
+                write!(f, "start={} stop={} step={}", start, stop, step)
+            }
+            SweepValues::Points {
+                sweep,
+                points,
+                start,
+                stop,
+            } => write!(
+                f,
+                "{} points={} start={} stop={}",
+                sweep, points, start, stop
+            ),
+        }
+    }
 }
 
-#[derive(Debug)]
+impl fmt::Display for SweepType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SweepType::Dec => write!(f, "dec"),
+            SweepType::Oct => write!(f, "oct"),
+            SweepType::Lin => write!(f, "lin"),
+        }
+    }
+}
+
+// Settings from `.options`, the ones left out keep their defaults
+#[derive(Debug, Clone, Default)]
+pub struct OptionsParams {
+    pub reltol: Option<f64>,
+    pub vntol: Option<f64>,
+    pub abstol: Option<f64>,
+    pub itl1: Option<u64>,
+    pub itl4: Option<u64>,
+    pub trtol: Option<f64>,
+    pub gmin: Option<f64>,
+    pub tmax: Option<f64>,
+    pub method: Option<Method>,
+    // Operating point fallbacks, 0 turns them off
+    pub gmin_steps: Option<usize>,
+    pub src_steps: Option<usize>,
+    // Log compression of every Newton step on top of the device limiting
+    pub damping: Option<bool>,
+    pub interp: Option<bool>,
+    pub temp: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub elem: String,
+    // Lowercase, only for elements with more than two terminals
+    pub terminal: Option<String>,
+}
+
+#[derive(Debug, Clone)]
 pub struct DCParams {
-    pub source: String,
+    // The first sweep changes fastest, each later one steps all the ones before it
+    pub sweeps: Vec<DCSweep>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DCSweep {
+    pub var: SweepVar,
+    pub values: SweepValues,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SweepVar {
+    // The value of a source, resistor, capacitor, inductor or controlled source
+    Elem(String),
+    Temp,
+    Param(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SweepValues {
+    // Both ends included
+    Step {
+        start: f64,
+        stop: f64,
+        step: f64,
+    },
+    Points {
+        sweep: SweepType,
+        points: usize,
+        start: f64,
+        stop: f64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct TranParams {
+    // Results before it are left out, the simulation always starts at 0
     pub start: f64,
     pub stop: f64,
     pub step: f64,
+    // Largest time step, overrides `.options tmax`
+    pub tmax: Option<f64>,
+    // Start from the initial conditions instead of the operating point
+    pub uic: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SweepType {
+    Dec,
+    Oct,
+    Lin,
 }
 
 #[derive(Debug, Clone)]
-pub struct TranParams {
+pub struct ACParams {
+    pub sweep: SweepType,
+    // Per decade or octave, total for a linear sweep
+    pub points: usize,
     pub start: f64,
     pub stop: f64,
-    pub step: f64,
+}
+
+// Value of an independent source, a plain number converts to a DC value
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub dc: f64,
+    pub ac_mag: f64,
+    // Degrees
+    pub ac_phase: f64,
+    pub tran_fn: Option<SpiceFn>,
+}
+
+impl Source {
+    pub fn dc(val: f64) -> Self {
+        Source {
+            dc: val,
+            ac_mag: 0.0,
+            ac_phase: 0.0,
+            tran_fn: None,
+        }
+    }
+
+    // The function at t = 0 sets the operating point, like a source without `DC`
+    pub fn tran(tran_fn: SpiceFn) -> Self {
+        Source {
+            dc: tran_fn.eval(&0.0),
+            tran_fn: Some(tran_fn),
+            ..Source::dc(0.0)
+        }
+    }
+
+    pub fn ac(self, mag: f64, phase: f64) -> Self {
+        Source {
+            ac_mag: mag,
+            ac_phase: phase,
+            ..self
+        }
+    }
+}
+
+impl From<f64> for Source {
+    fn from(val: f64) -> Self {
+        Source::dc(val)
+    }
 }
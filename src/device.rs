@@ -1,16 +1,28 @@
 use ndarray::prelude::*;
+use num_complex::Complex64;
 
+use crate::integration::TimeStep;
 use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
 
 pub mod cap;
+pub mod cccs;
+pub mod ccvs;
 pub mod diode;
 pub mod idd;
 pub mod ind;
+pub mod limiting;
 pub mod nmos;
 pub mod npn;
 pub mod res;
+pub mod temperature;
+pub mod vccs;
+pub mod vcvs;
 pub mod vdd;
 
+// Nonlinear device currents, evaluated on the full solution vector
+pub type NonlinearFn = Box<dyn Fn(&Array1<f64>) -> f64>;
+
 // MNA sgroups from Circuit Simulation Book
 pub enum GType {
     G1,
@@ -29,54 +41,92 @@ pub trait Stamp {
         self.gtype()
     }
 
-    fn get_value(&self) -> f64;
+    // None for devices described by a model rather than a single value,
+    // setting one does nothing then
+    fn get_value(&self) -> Option<f64>;
 
     fn set_value(&mut self, value: f64);
 
+    // Element whose branch current controls this one, for F and H sources
+    fn get_ctrl_source(&self) -> Option<&str> {
+        None
+    }
+
+    // Independent sources, ramped up from zero by source stepping
+    fn is_source(&self) -> bool {
+        false
+    }
+
+    // Circuit temperature in degrees Celsius
+    fn set_temp(&mut self, _temp: f64) {}
+
     fn has_tran(&self) -> bool {
         false
     }
 
     fn eval_tran(&mut self, _t: &f64) {}
 
-    fn linear_stamp(&self, _nodes: &NodeCollection, _a: &mut Array2<f64>, _b: &mut Array1<f64>) {}
+    // Next time the transient source has a corner, the transient lands on it
+    fn next_breakpoint(&self, _t: &f64) -> Option<f64> {
+        None
+    }
+
+    // Terminal names for `I(<elem>,<terminal>)`, none for two terminal elements
+    fn terminals(&self) -> &'static [&'static str] {
+        &[]
+    }
 
-    fn undo_linear_stamp(
+    // Current into each terminal, or for two terminal elements the one through it:
+    // from the first node to the second, from + to - through voltage sources, into
+    // the node a current source drives and from anode to cathode. `x` is a DC
+    // solution, capacitors don't conduct and inductors are shorts.
+    fn currents(&self, _nodes: &NodeCollection, _x: &Array1<f64>) -> Option<Vec<f64>> {
+        None
+    }
+
+    // Same during a transient, after `update_state` has seen `x`
+    fn tran_currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        self.currents(nodes, x)
+    }
+
+    fn linear_stamp(
         &self,
         _nodes: &NodeCollection,
-        _a: &mut Array2<f64>,
+        _a: &mut SparseMatrix<f64>,
         _b: &mut Array1<f64>,
     ) {
     }
 
-    fn linear_startup_stamp(
+    fn undo_linear_stamp(
         &self,
-        nodes: &NodeCollection,
-        a: &mut Array2<f64>,
-        b: &mut Array1<f64>,
+        _nodes: &NodeCollection,
+        _a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
     ) {
-        self.linear_stamp(nodes, a, b);
     }
 
-    fn undo_linear_startup_stamp(
+    fn linear_startup_stamp(
         &self,
         nodes: &NodeCollection,
-        a: &mut Array2<f64>,
+        a: &mut SparseMatrix<f64>,
         b: &mut Array1<f64>,
     ) {
-        self.undo_linear_stamp(nodes, a, b);
+        self.linear_stamp(nodes, a, b);
     }
 
     fn init_state(&mut self, _nodes: &NodeCollection, _x: &Array1<f64>) {}
 
-    fn update_state(&mut self, _nodes: &NodeCollection, _x: &Array1<f64>, _h: &f64) {}
+    // Element `IC=` values, only used when the transient skips the operating point
+    fn init_state_ic(&mut self) {}
+
+    fn update_state(&mut self, _nodes: &NodeCollection, _x: &Array1<f64>, _step: &TimeStep) {}
 
     fn dynamic_stamp(
         &self,
         _nodes: &NodeCollection,
         _x: &Array1<f64>,
-        _h: &f64,
-        _a: &mut Array2<f64>,
+        _step: &TimeStep,
+        _a: &mut SparseMatrix<f64>,
         _b: &mut Array1<f64>,
     ) {
     }
@@ -85,12 +135,23 @@ pub trait Stamp {
         &self,
         _nodes: &NodeCollection,
         _x: &Array1<f64>,
-        _h: &f64,
-        _a: &mut Array2<f64>,
+        _step: &TimeStep,
+        _a: &mut SparseMatrix<f64>,
         _b: &mut Array1<f64>,
     ) {
     }
 
+    // Frequency dependent admittances and AC excitations. The conductances come
+    // from the linear and nonlinear stamps at the operating point.
+    fn ac_stamp(
+        &self,
+        _nodes: &NodeCollection,
+        _omega: &f64,
+        _a: &mut SparseMatrix<Complex64>,
+        _b: &mut Array1<Complex64>,
+    ) {
+    }
+
     fn count_nonlinear_funcs(&self) -> usize {
         0
     }
@@ -98,16 +159,18 @@ pub trait Stamp {
     fn nonlinear_funcs(
         &self,
         _nodes: &NodeCollection,
-        _h_mat: &mut Array2<f64>,
-        _g_vec: &mut Vec<Box<dyn Fn(&Array1<f64>) -> f64>>,
+        _h_mat: &mut SparseMatrix<f64>,
+        _g_vec: &mut Vec<NonlinearFn>,
     ) {
     }
 
+    // Devices may limit their junction voltages against the ones they were
+    // linearized at in the last Newton iteration
     fn nonlinear_stamp(
-        &self,
+        &mut self,
         _nodes: &NodeCollection,
         _x: &Array1<f64>,
-        _a: &mut Array2<f64>,
+        _a: &mut SparseMatrix<f64>,
         _b: &mut Array1<f64>,
     ) {
     }
@@ -1,7 +1,10 @@
 use ndarray::prelude::*;
+use num_complex::Complex64;
 
 use crate::device::{GType, Stamp};
+use crate::integration::TimeStep;
 use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
 
 mod model;
 
@@ -12,6 +15,8 @@ pub struct Cap {
     pub val: f64,
     pub u_curr: Option<f64>,
     pub i_curr: Option<f64>,
+    pub u_prev: Option<f64>,
+    pub ic: Option<f64>,
 }
 
 impl Stamp for Cap {
@@ -27,14 +32,22 @@ impl Stamp for Cap {
         GType::G1
     }
 
-    fn get_value(&self) -> f64 {
-        self.val
+    fn get_value(&self) -> Option<f64> {
+        Some(self.val)
     }
 
     fn set_value(&mut self, value: f64) {
         self.val = value;
     }
 
+    fn currents(&self, _nodes: &NodeCollection, _x: &Array1<f64>) -> Option<Vec<f64>> {
+        Some(vec![0.0])
+    }
+
+    fn tran_currents(&self, _nodes: &NodeCollection, _x: &Array1<f64>) -> Option<Vec<f64>> {
+        Some(vec![self.i_curr.unwrap_or(0.0)])
+    }
+
     fn init_state(&mut self, nodes: &NodeCollection, x: &Array1<f64>) {
         let vneg_idx = nodes.get_idx(&self.nodes[0]);
         let vpos_idx = nodes.get_idx(&self.nodes[1]);
@@ -44,9 +57,17 @@ impl Stamp for Cap {
 
         self.u_curr = Some(vpos - vneg);
         self.i_curr = Some(0.0);
+        self.u_prev = self.u_curr;
     }
 
-    fn update_state(&mut self, nodes: &NodeCollection, x: &Array1<f64>, h: &f64) {
+    fn init_state_ic(&mut self) {
+        if let Some(u) = self.ic {
+            self.u_curr = Some(u);
+            self.u_prev = Some(u);
+        }
+    }
+
+    fn update_state(&mut self, nodes: &NodeCollection, x: &Array1<f64>, step: &TimeStep) {
         let vneg_idx = nodes.get_idx(&self.nodes[0]);
         let vpos_idx = nodes.get_idx(&self.nodes[1]);
 
@@ -54,23 +75,25 @@ impl Stamp for Cap {
         let vneg = vneg_idx.map_or(0.0, |i| x[i]);
 
         let c = model::Model {
-            vpos: vpos,
-            vneg: vneg,
+            vpos,
+            vneg,
             val: self.val,
             u_old: self.u_curr.expect("Cap voltage history not initialized"),
             i_old: self.i_curr.expect("Cap current history not initialized"),
+            u_prev: self.u_prev.expect("Cap voltage history not initialized"),
         };
 
+        self.u_prev = self.u_curr;
         self.u_curr = Some(c.u_new());
-        self.i_curr = Some(c.i_new(h));
+        self.i_curr = Some(c.i_new(step));
     }
 
     fn dynamic_stamp(
         &self,
         nodes: &NodeCollection,
         x: &Array1<f64>,
-        h: &f64,
-        a: &mut Array2<f64>,
+        step: &TimeStep,
+        a: &mut SparseMatrix<f64>,
         b: &mut Array1<f64>,
     ) {
         let vneg_idx = nodes.get_idx(&self.nodes[0]);
@@ -80,15 +103,16 @@ impl Stamp for Cap {
         let vneg = vneg_idx.map_or(0.0, |i| x[i]);
 
         let c = model::Model {
-            vpos: vpos,
-            vneg: vneg,
+            vpos,
+            vneg,
             val: self.val,
             u_old: self.u_curr.expect("Cap voltage history not initialized"),
             i_old: self.i_curr.expect("Cap current history not initialized"),
+            u_prev: self.u_prev.expect("Cap voltage history not initialized"),
         };
 
-        let g_eq = c.g_eq(h);
-        let i_eq = c.i_eq(h);
+        let g_eq = c.g_eq(step);
+        let i_eq = c.i_eq(step);
 
         if let Some(i) = vpos_idx {
             a[(i, i)] += g_eq;
@@ -108,8 +132,8 @@ impl Stamp for Cap {
         &self,
         nodes: &NodeCollection,
         x: &Array1<f64>,
-        h: &f64,
-        a: &mut Array2<f64>,
+        step: &TimeStep,
+        a: &mut SparseMatrix<f64>,
         b: &mut Array1<f64>,
     ) {
         let vneg_idx = nodes.get_idx(&self.nodes[0]);
@@ -119,15 +143,16 @@ impl Stamp for Cap {
         let vneg = vneg_idx.map_or(0.0, |i| x[i]);
 
         let c = model::Model {
-            vpos: vpos,
-            vneg: vneg,
+            vpos,
+            vneg,
             val: self.val,
             u_old: self.u_curr.expect("Cap voltage history not initialized"),
             i_old: self.i_curr.expect("Cap current history not initialized"),
+            u_prev: self.u_prev.expect("Cap voltage history not initialized"),
         };
 
-        let g_eq = c.g_eq(h);
-        let i_eq = c.i_eq(h);
+        let g_eq = c.g_eq(step);
+        let i_eq = c.i_eq(step);
 
         if let Some(i) = vpos_idx {
             a[(i, i)] -= g_eq;
@@ -142,11 +167,36 @@ impl Stamp for Cap {
             a[(j, i)] += g_eq;
         }
     }
+
+    fn ac_stamp(
+        &self,
+        nodes: &NodeCollection,
+        omega: &f64,
+        a: &mut SparseMatrix<Complex64>,
+        _b: &mut Array1<Complex64>,
+    ) {
+        let y = Complex64::new(0.0, omega * self.val);
+
+        let n0_idx = nodes.get_idx(&self.nodes[0]);
+        let n1_idx = nodes.get_idx(&self.nodes[1]);
+
+        if let Some(i) = n0_idx {
+            a[(i, i)] += y;
+        }
+        if let Some(i) = n1_idx {
+            a[(i, i)] += y;
+        }
+        if let (Some(i), Some(j)) = (n0_idx, n1_idx) {
+            a[(i, j)] -= y;
+            a[(j, i)] -= y;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::integration::Method;
 
     fn parse_cap(cap: &Cap) -> NodeCollection {
         NodeCollection::from_elems(&[Box::new(cap.clone())])
@@ -159,6 +209,8 @@ mod tests {
             val: 1e-6,
             u_curr: Some(3.0),
             i_curr: Some(0.0),
+            u_prev: Some(3.0),
+            ic: None,
         }
     }
 
@@ -166,12 +218,12 @@ mod tests {
     fn test_dynamic_stamp() {
         let cap = test_cap(&["1", "2"]);
         let nodes = parse_cap(&cap);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
         let x = array![1.0, 2.0];
-        let h = 1e-8;
+        let step = TimeStep::new(Method::Trapezoidal, 1e-8, 1e-8);
 
-        cap.dynamic_stamp(&nodes, &x, &h, &mut a, &mut b);
+        cap.dynamic_stamp(&nodes, &x, &step, &mut a, &mut b);
 
         let n1 = nodes.get_idx("1").unwrap();
         let n2 = nodes.get_idx("2").unwrap();
@@ -189,15 +241,70 @@ mod tests {
     fn test_undo_dynamic_stamp() {
         let cap = test_cap(&["1", "2"]);
         let nodes = parse_cap(&cap);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
         let x = array![1.0, 2.0];
-        let h = 1e-8;
+        let step = TimeStep::new(Method::Trapezoidal, 1e-8, 1e-8);
 
-        cap.dynamic_stamp(&nodes, &x, &h, &mut a, &mut b);
-        cap.undo_dynamic_stamp(&nodes, &x, &h, &mut a, &mut b);
+        cap.dynamic_stamp(&nodes, &x, &step, &mut a, &mut b);
+        cap.undo_dynamic_stamp(&nodes, &x, &step, &mut a, &mut b);
 
         assert_eq!(a, array![[0.0, 0.0], [0.0, 0.0]]);
         assert_eq!(b, array![0.0, 0.0]);
     }
+
+    #[test]
+    fn test_update_state_methods() {
+        let x = array![4.0, 0.0];
+        let cases = [
+            // C dv / h
+            (Method::BackwardEuler, 1.0),
+            // 2 C dv / h - i_old
+            (Method::Trapezoidal, 2.0),
+            // C (3/2 v - 2 v_old + 1/2 v_prev) / h
+            (Method::Gear2, 1.5),
+        ];
+
+        for (method, i_expected) in cases {
+            let mut cap = test_cap(&["0", "1"]);
+            let nodes = parse_cap(&cap);
+
+            cap.update_state(&nodes, &x, &TimeStep::new(method, 1e-6, 1e-6));
+
+            assert_eq!(cap.u_curr, Some(4.0));
+            assert_eq!(cap.u_prev, Some(3.0));
+            assert!(
+                (cap.i_curr.unwrap() - i_expected).abs() < 1e-12,
+                "{:?}",
+                method
+            );
+        }
+    }
+
+    #[test]
+    fn test_init_state_ic() {
+        let mut cap = test_cap(&["0", "1"]);
+        cap.init_state_ic();
+        assert_eq!(cap.u_curr, Some(3.0));
+
+        cap.ic = Some(-1.0);
+        cap.init_state_ic();
+
+        assert_eq!(cap.u_curr, Some(-1.0));
+        assert_eq!(cap.u_prev, Some(-1.0));
+    }
+
+    #[test]
+    fn test_ac_stamp() {
+        let cap = test_cap(&["1", "2"]);
+        let nodes = parse_cap(&cap);
+        let mut a = SparseMatrix::zeros((2, 2));
+        let mut b = Array1::zeros(2);
+
+        cap.ac_stamp(&nodes, &1e3, &mut a, &mut b);
+
+        let y = Complex64::new(0.0, 1e-3);
+        assert_eq!(a, array![[y, -y], [-y, y]]);
+        assert_eq!(b, Array1::zeros(2));
+    }
 }
@@ -1,3 +1,5 @@
+use crate::integration::TimeStep;
+
 #[derive(Debug)]
 pub struct Model {
     pub vpos: f64,
@@ -5,22 +7,26 @@ pub struct Model {
     pub val: f64,
     pub u_old: f64,
     pub i_old: f64,
+    // Voltage the step before `u_old`
+    pub u_prev: f64,
 }
 
 impl Model {
-    pub fn g_eq(&self, h: &f64) -> f64 {
-        2.0 * self.val / h
+    pub fn g_eq(&self, step: &TimeStep) -> f64 {
+        step.coeffs().a0 * self.val / step.h
     }
 
-    pub fn i_eq(&self, h: &f64) -> f64 {
-        -(self.i_old + self.g_eq(h) * self.u_old)
+    pub fn i_eq(&self, step: &TimeStep) -> f64 {
+        let c = step.coeffs();
+
+        self.val * (c.a1 * self.u_old + c.a2 * self.u_prev) / step.h + c.b1 * self.i_old
     }
 
     pub fn u_new(&self) -> f64 {
         self.vpos - self.vneg
     }
 
-    pub fn i_new(&self, h: &f64) -> f64 {
-        return self.g_eq(h) * (self.u_new() - self.u_old) - self.i_old;
+    pub fn i_new(&self, step: &TimeStep) -> f64 {
+        self.g_eq(step) * self.u_new() + self.i_eq(step)
     }
 }
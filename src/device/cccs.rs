@@ -0,0 +1,143 @@
+use ndarray::prelude::*;
+
+use crate::device::{GType, Stamp};
+use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
+
+// F element, gain times the current through `ctrl`, flowing from n+ to n-
+// through the source
+#[derive(Debug, Clone)]
+pub struct CCCS {
+    pub name: String,
+    // n+, n-
+    pub nodes: Vec<String>,
+    pub ctrl: String,
+    pub val: f64,
+}
+
+impl CCCS {
+    fn stamp(&self, nodes: &NodeCollection, a: &mut SparseMatrix<f64>, sign: f64) {
+        let vpos_idx = nodes.get_idx(&self.nodes[0]);
+        let vneg_idx = nodes.get_idx(&self.nodes[1]);
+        let ic_idx = nodes
+            .get_idx(&self.ctrl)
+            .expect("Couldn't find node label for controlling source.");
+
+        if let Some(i) = vpos_idx {
+            a[(i, ic_idx)] += sign * self.val;
+        }
+        if let Some(i) = vneg_idx {
+            a[(i, ic_idx)] -= sign * self.val;
+        }
+    }
+}
+
+impl Stamp for CCCS {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_nodes(&self) -> &Vec<String> {
+        &self.nodes
+    }
+
+    fn gtype(&self) -> GType {
+        GType::G1
+    }
+
+    fn get_value(&self) -> Option<f64> {
+        Some(self.val)
+    }
+
+    fn set_value(&mut self, value: f64) {
+        self.val = value;
+    }
+
+    fn get_ctrl_source(&self) -> Option<&str> {
+        Some(&self.ctrl)
+    }
+
+    fn currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        nodes.get_idx(&self.ctrl).map(|i| vec![self.val * x[i]])
+    }
+
+    fn linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
+    ) {
+        self.stamp(nodes, a, 1.0);
+    }
+
+    fn undo_linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
+    ) {
+        self.stamp(nodes, a, -1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::vdd::Vdd;
+
+    fn test_cccs(nodes: &[&str]) -> CCCS {
+        CCCS {
+            name: String::from("F1"),
+            nodes: nodes.iter().map(|s| s.to_string()).collect(),
+            ctrl: String::from("V1"),
+            val: 2.0,
+        }
+    }
+
+    fn parse_cccs(cccs: &CCCS) -> NodeCollection {
+        let vsense = Vdd {
+            name: String::from("V1"),
+            nodes: vec![String::from("0"), String::from("3")],
+            val: 0.0,
+            ac_mag: 0.0,
+            ac_phase: 0.0,
+            tran_fn: None,
+        };
+
+        NodeCollection::from_elems(&[Box::new(cccs.clone()), Box::new(vsense)])
+    }
+
+    #[test]
+    fn test_linear_stamp() {
+        let cccs = test_cccs(&["1", "2"]);
+        let nodes = parse_cccs(&cccs);
+        let mut a = SparseMatrix::zeros((4, 4));
+        let mut b = Array1::zeros(4);
+
+        cccs.linear_stamp(&nodes, &mut a, &mut b);
+
+        let n1 = nodes.get_idx("1").unwrap();
+        let n2 = nodes.get_idx("2").unwrap();
+        let v1 = nodes.get_idx("V1").unwrap();
+
+        let mut a_model = SparseMatrix::zeros((nodes.len(), nodes.len()));
+        a_model[(n1, v1)] = 2.0;
+        a_model[(n2, v1)] = -2.0;
+
+        assert_eq!(a, a_model);
+        assert_eq!(b, Array1::zeros(4));
+    }
+
+    #[test]
+    fn test_undo_linear_stamp() {
+        let cccs = test_cccs(&["1", "0"]);
+        let nodes = parse_cccs(&cccs);
+        let mut a = SparseMatrix::zeros((3, 3));
+        let mut b = Array1::zeros(3);
+
+        cccs.linear_stamp(&nodes, &mut a, &mut b);
+        cccs.undo_linear_stamp(&nodes, &mut a, &mut b);
+
+        assert_eq!(a, SparseMatrix::zeros((3, 3)));
+    }
+}
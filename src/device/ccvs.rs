@@ -0,0 +1,149 @@
+use ndarray::prelude::*;
+
+use crate::device::{GType, Stamp};
+use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
+
+// H element, V(n+, n-) = transresistance times the current through `ctrl`
+#[derive(Debug, Clone)]
+pub struct CCVS {
+    pub name: String,
+    // n+, n-
+    pub nodes: Vec<String>,
+    pub ctrl: String,
+    pub val: f64,
+}
+
+impl CCVS {
+    fn stamp(&self, nodes: &NodeCollection, a: &mut SparseMatrix<f64>, sign: f64) {
+        let vpos_idx = nodes.get_idx(&self.nodes[0]);
+        let vneg_idx = nodes.get_idx(&self.nodes[1]);
+        let is_idx = nodes
+            .get_idx(&self.name)
+            .expect("Couldn't find node label for source.");
+        let ic_idx = nodes
+            .get_idx(&self.ctrl)
+            .expect("Couldn't find node label for controlling source.");
+
+        if let Some(i) = vpos_idx {
+            a[(is_idx, i)] += sign;
+            a[(i, is_idx)] += sign;
+        }
+        if let Some(i) = vneg_idx {
+            a[(is_idx, i)] -= sign;
+            a[(i, is_idx)] -= sign;
+        }
+        a[(is_idx, ic_idx)] -= sign * self.val;
+    }
+}
+
+impl Stamp for CCVS {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_nodes(&self) -> &Vec<String> {
+        &self.nodes
+    }
+
+    fn gtype(&self) -> GType {
+        GType::G2
+    }
+
+    fn get_value(&self) -> Option<f64> {
+        Some(self.val)
+    }
+
+    fn set_value(&mut self, value: f64) {
+        self.val = value;
+    }
+
+    fn get_ctrl_source(&self) -> Option<&str> {
+        Some(&self.ctrl)
+    }
+
+    fn currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        Some(vec![nodes.get_idx(&self.name).map_or(0.0, |i| x[i])])
+    }
+
+    fn linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
+    ) {
+        self.stamp(nodes, a, 1.0);
+    }
+
+    fn undo_linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
+    ) {
+        self.stamp(nodes, a, -1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::vdd::Vdd;
+
+    fn test_ccvs(nodes: &[&str]) -> CCVS {
+        CCVS {
+            name: String::from("H1"),
+            nodes: nodes.iter().map(|s| s.to_string()).collect(),
+            ctrl: String::from("V1"),
+            val: 1e3,
+        }
+    }
+
+    fn parse_ccvs(ccvs: &CCVS) -> NodeCollection {
+        let vsense = Vdd {
+            name: String::from("V1"),
+            nodes: vec![String::from("0"), String::from("2")],
+            val: 0.0,
+            ac_mag: 0.0,
+            ac_phase: 0.0,
+            tran_fn: None,
+        };
+
+        NodeCollection::from_elems(&[Box::new(ccvs.clone()), Box::new(vsense)])
+    }
+
+    #[test]
+    fn test_linear_stamp() {
+        let ccvs = test_ccvs(&["1", "0"]);
+        let nodes = parse_ccvs(&ccvs);
+        let mut a = SparseMatrix::zeros((4, 4));
+        let mut b = Array1::zeros(4);
+
+        ccvs.linear_stamp(&nodes, &mut a, &mut b);
+
+        let n1 = nodes.get_idx("1").unwrap();
+        let h1 = nodes.get_idx("H1").unwrap();
+        let v1 = nodes.get_idx("V1").unwrap();
+
+        let mut a_model = SparseMatrix::zeros((nodes.len(), nodes.len()));
+        a_model[(n1, h1)] = 1.0;
+        a_model[(h1, n1)] = 1.0;
+        a_model[(h1, v1)] = -1e3;
+
+        assert_eq!(a, a_model);
+        assert_eq!(b, Array1::zeros(4));
+    }
+
+    #[test]
+    fn test_undo_linear_stamp() {
+        let ccvs = test_ccvs(&["1", "3"]);
+        let nodes = parse_ccvs(&ccvs);
+        let mut a = SparseMatrix::zeros((5, 5));
+        let mut b = Array1::zeros(5);
+
+        ccvs.linear_stamp(&nodes, &mut a, &mut b);
+        ccvs.undo_linear_stamp(&nodes, &mut a, &mut b);
+
+        assert_eq!(a, SparseMatrix::zeros((5, 5)));
+    }
+}
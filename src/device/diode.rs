@@ -1,14 +1,19 @@
 use ndarray::prelude::*;
 
-use crate::device::{GType, Stamp};
+use crate::device::limiting;
+use crate::device::{GType, NonlinearFn, Stamp};
 use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
 
-mod model;
+pub mod model;
 
 #[derive(Debug, Clone)]
 pub struct Diode {
     pub name: String,
     pub nodes: Vec<String>,
+    pub params: model::Params,
+    // Junction voltage the last Newton iteration was linearized at
+    pub vd_old: f64,
 }
 
 impl Stamp for Diode {
@@ -24,12 +29,24 @@ impl Stamp for Diode {
         GType::G1
     }
 
-    fn get_value(&self) -> f64 {
-        unimplemented!()
+    fn get_value(&self) -> Option<f64> {
+        None
     }
 
-    fn set_value(&mut self, _value: f64) {
-        unimplemented!()
+    fn set_value(&mut self, _value: f64) {}
+
+    fn set_temp(&mut self, temp: f64) {
+        self.params.temp = temp;
+    }
+
+    fn currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        let d = model::Model {
+            vpos: nodes.get_idx(&self.nodes[0]).map_or(0.0, |i| x[i]),
+            vneg: nodes.get_idx(&self.nodes[1]).map_or(0.0, |i| x[i]),
+            params: self.params,
+        };
+
+        Some(vec![d.i()])
     }
 
     fn count_nonlinear_funcs(&self) -> usize {
@@ -39,8 +56,8 @@ impl Stamp for Diode {
     fn nonlinear_funcs(
         &self,
         nodes: &NodeCollection,
-        h_mat: &mut Array2<f64>,
-        g_vec: &mut Vec<Box<dyn Fn(&Array1<f64>) -> f64>>,
+        h_mat: &mut SparseMatrix<f64>,
+        g_vec: &mut Vec<NonlinearFn>,
     ) {
         let vpos_idx = nodes.get_idx(&self.nodes[0]);
         let vneg_idx = nodes.get_idx(&self.nodes[1]);
@@ -52,28 +69,40 @@ impl Stamp for Diode {
             h_mat[(i, g_vec.len())] = -1.0;
         }
 
+        let params = self.params;
+
         g_vec.push(Box::new(move |x: &Array1<f64>| {
             let d = model::Model {
                 vpos: vpos_idx.map_or(0.0, |i| x[i]),
                 vneg: vneg_idx.map_or(0.0, |i| x[i]),
+                params,
             };
             d.i()
         }));
     }
 
     fn nonlinear_stamp(
-        &self,
+        &mut self,
         nodes: &NodeCollection,
         x: &Array1<f64>,
-        a: &mut Array2<f64>,
+        a: &mut SparseMatrix<f64>,
         b: &mut Array1<f64>,
     ) {
         let vpos_idx = nodes.get_idx(&self.nodes[0]);
         let vneg_idx = nodes.get_idx(&self.nodes[1]);
 
+        let vpos = vpos_idx.map_or(0.0, |i| x[i]);
+        let vneg = vneg_idx.map_or(0.0, |i| x[i]);
+
+        let n_vt = self.params.n_vt();
+        let vcrit = limiting::vcrit(n_vt, self.params.is_t());
+        let vd = limiting::pnjlim(vpos - vneg, self.vd_old, n_vt, vcrit);
+        self.vd_old = vd;
+
         let d = model::Model {
-            vpos: vpos_idx.map_or(0.0, |i| x[i]),
-            vneg: vneg_idx.map_or(0.0, |i| x[i]),
+            vpos: vneg + vd,
+            vneg,
+            params: self.params,
         };
         let g_eq = d.g_eq();
         let i_eq = d.i_eq();
@@ -106,9 +135,11 @@ mod tests {
         let dio = Diode {
             name: String::from("R1"),
             nodes: vec![String::from("1"), String::from("2")],
+            params: model::Params::default(),
+            vd_old: 0.0,
         };
         let nodes = parse_dio(&dio);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         dio.linear_stamp(&nodes, &mut a, &mut b);
@@ -122,9 +153,11 @@ mod tests {
         let dio = Diode {
             name: String::from("R1"),
             nodes: vec![String::from("1"), String::from("2")],
+            params: model::Params::default(),
+            vd_old: 0.0,
         };
         let nodes = parse_dio(&dio);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         dio.linear_stamp(&nodes, &mut a, &mut b);
@@ -139,9 +172,11 @@ mod tests {
         let dio = Diode {
             name: String::from("D1"),
             nodes: vec![String::from("1"), String::from("2")],
+            params: model::Params::default(),
+            vd_old: 0.0,
         };
         let nodes = parse_dio(&dio);
-        let mut h = Array2::zeros((2, 1));
+        let mut h = SparseMatrix::zeros((2, 1));
         let mut g = Vec::new();
 
         dio.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -154,9 +189,11 @@ mod tests {
         let dio = Diode {
             name: String::from("D1"),
             nodes: vec![String::from("0"), String::from("1")],
+            params: model::Params::default(),
+            vd_old: 0.0,
         };
         let nodes = parse_dio(&dio);
-        let mut h = Array2::zeros((1, 1));
+        let mut h = SparseMatrix::zeros((1, 1));
         let mut g = Vec::new();
 
         dio.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -173,9 +210,11 @@ mod tests {
         let dio = Diode {
             name: String::from("D1"),
             nodes: vec![String::from("1"), String::from("0")],
+            params: model::Params::default(),
+            vd_old: 0.0,
         };
         let nodes = parse_dio(&dio);
-        let mut h = Array2::zeros((1, 1));
+        let mut h = SparseMatrix::zeros((1, 1));
         let mut g = Vec::new();
 
         dio.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -192,9 +231,11 @@ mod tests {
         let dio = Diode {
             name: String::from("D1"),
             nodes: vec![String::from("1"), String::from("2")],
+            params: model::Params::default(),
+            vd_old: 0.0,
         };
         let nodes = parse_dio(&dio);
-        let mut h = Array2::zeros((2, 1));
+        let mut h = SparseMatrix::zeros((2, 1));
         let mut g = Vec::new();
 
         dio.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -202,7 +243,7 @@ mod tests {
         let n1 = nodes.get_idx("1").unwrap();
         let n2 = nodes.get_idx("2").unwrap();
 
-        let mut h_model = Array2::zeros((2, 1));
+        let mut h_model = SparseMatrix::zeros((2, 1));
         h_model[(n1, 0)] = 1.0;
         h_model[(n2, 0)] = -1.0;
 
@@ -218,13 +259,15 @@ mod tests {
 
     #[test]
     fn test_nonlinear_stamp_node_0_gnd() {
-        let dio = Diode {
+        let mut dio = Diode {
             name: String::from("D1"),
             nodes: vec![String::from("0"), String::from("1")],
+            params: model::Params::default(),
+            vd_old: 0.0,
         };
         let nodes = parse_dio(&dio);
         let x = array![1.0];
-        let mut a = Array2::zeros((1, 1));
+        let mut a = SparseMatrix::zeros((1, 1));
         let mut b = Array1::zeros(1);
 
         dio.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
@@ -235,13 +278,15 @@ mod tests {
 
     #[test]
     fn test_nonlinear_stamp_node_1_gnd() {
-        let dio = Diode {
+        let mut dio = Diode {
             name: String::from("D1"),
             nodes: vec![String::from("1"), String::from("0")],
+            params: model::Params::default(),
+            vd_old: 0.0,
         };
         let nodes = parse_dio(&dio);
         let x = array![1.0];
-        let mut a = Array2::zeros((1, 1));
+        let mut a = SparseMatrix::zeros((1, 1));
         let mut b = Array1::zeros(1);
 
         dio.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
@@ -252,13 +297,15 @@ mod tests {
 
     #[test]
     fn test_nonlinear_stamp_two_nodes() {
-        let dio = Diode {
+        let mut dio = Diode {
             name: String::from("D1"),
             nodes: vec![String::from("1"), String::from("2")],
+            params: model::Params::default(),
+            vd_old: 0.0,
         };
         let nodes = parse_dio(&dio);
         let x = array![1.0, 2.0];
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         dio.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
@@ -273,4 +320,24 @@ mod tests {
         assert!(b[n1] > 0.0);
         assert!(b[n2] < 0.0);
     }
+
+    #[test]
+    fn test_nonlinear_stamp_limits_junction() {
+        let mut dio = Diode {
+            name: String::from("D1"),
+            nodes: vec![String::from("1"), String::from("0")],
+            params: model::Params::default(),
+            vd_old: 0.0,
+        };
+        let nodes = parse_dio(&dio);
+        let x = array![10.0];
+        let mut a = SparseMatrix::zeros((1, 1));
+        let mut b = Array1::zeros(1);
+
+        dio.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
+
+        assert!(dio.vd_old < 1.0);
+        assert!(a[(0, 0)].is_finite());
+        assert!(b[0].is_finite());
+    }
 }
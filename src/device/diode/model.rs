@@ -1,20 +1,62 @@
+use crate::device::temperature::{saturation_current, thermal_voltage, TNOM};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    pub is: f64,
+    pub n: f64,
+    // Set from the circuit, not the model card
+    pub temp: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            is: 1.0e-14,
+            n: 1.0,
+            temp: TNOM,
+        }
+    }
+}
+
+impl Params {
+    pub const NAMES: &'static [&'static str] = &["is", "n"];
+
+    pub fn set(&mut self, name: &str, value: f64) -> bool {
+        match name.to_lowercase().as_str() {
+            "is" => self.is = value,
+            "n" => self.n = value,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn n_vt(&self) -> f64 {
+        self.n * thermal_voltage(self.temp)
+    }
+
+    pub fn is_t(&self) -> f64 {
+        saturation_current(self.is, self.n, self.temp)
+    }
+}
+
 #[derive(Debug)]
 pub struct Model {
     pub vpos: f64,
     pub vneg: f64,
+    pub params: Params,
 }
 
-const ISAT: f64 = 1.0e-12;
-const ETA: f64 = 1.0;
-const VT: f64 = 26e-3;
-
 impl Model {
+    fn n_vt(&self) -> f64 {
+        self.params.n_vt()
+    }
+
     pub fn i(&self) -> f64 {
-        ISAT * ((self.vpos - self.vneg) / (ETA * VT)).exp_m1()
+        self.params.is_t() * ((self.vpos - self.vneg) / self.n_vt()).exp_m1()
     }
 
     pub fn g_eq(&self) -> f64 {
-        ISAT / (ETA * VT) * ((self.vpos - self.vneg) / (ETA * VT)).exp()
+        self.params.is_t() / self.n_vt() * ((self.vpos - self.vneg) / self.n_vt()).exp()
     }
 
     pub fn i_eq(&self) -> f64 {
@@ -1,7 +1,9 @@
 use ndarray::prelude::*;
+use num_complex::Complex64;
 
 use crate::device::{GType, Stamp};
 use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
 use crate::spice_fn::SpiceFn;
 
 #[derive(Debug, Clone)]
@@ -9,6 +11,9 @@ pub struct Idd {
     pub name: String,
     pub nodes: Vec<String>,
     pub val: f64,
+    pub ac_mag: f64,
+    // Degrees
+    pub ac_phase: f64,
     pub tran_fn: Option<SpiceFn>,
 }
 
@@ -25,14 +30,22 @@ impl Stamp for Idd {
         GType::G1
     }
 
-    fn get_value(&self) -> f64 {
-        self.val
+    fn get_value(&self) -> Option<f64> {
+        Some(self.val)
     }
 
     fn set_value(&mut self, value: f64) {
         self.val = value;
     }
 
+    fn is_source(&self) -> bool {
+        true
+    }
+
+    fn currents(&self, _nodes: &NodeCollection, _x: &Array1<f64>) -> Option<Vec<f64>> {
+        Some(vec![self.val])
+    }
+
     fn has_tran(&self) -> bool {
         self.tran_fn.is_some()
     }
@@ -43,7 +56,16 @@ impl Stamp for Idd {
         }
     }
 
-    fn linear_stamp(&self, nodes: &NodeCollection, _a: &mut Array2<f64>, b: &mut Array1<f64>) {
+    fn next_breakpoint(&self, t: &f64) -> Option<f64> {
+        self.tran_fn.as_ref().and_then(|f| f.next_breakpoint(t))
+    }
+
+    fn linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        _a: &mut SparseMatrix<f64>,
+        b: &mut Array1<f64>,
+    ) {
         let vneg_node = nodes.get_idx(&self.nodes[0]);
         let vpos_node = nodes.get_idx(&self.nodes[1]);
         let val = self.val;
@@ -56,7 +78,12 @@ impl Stamp for Idd {
         }
     }
 
-    fn undo_linear_stamp(&self, nodes: &NodeCollection, _a: &mut Array2<f64>, b: &mut Array1<f64>) {
+    fn undo_linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        _a: &mut SparseMatrix<f64>,
+        b: &mut Array1<f64>,
+    ) {
         let vneg_node = nodes.get_idx(&self.nodes[0]);
         let vpos_node = nodes.get_idx(&self.nodes[1]);
         let val = self.val;
@@ -68,6 +95,25 @@ impl Stamp for Idd {
             b[i] += val;
         }
     }
+
+    fn ac_stamp(
+        &self,
+        nodes: &NodeCollection,
+        _omega: &f64,
+        _a: &mut SparseMatrix<Complex64>,
+        b: &mut Array1<Complex64>,
+    ) {
+        let vneg_node = nodes.get_idx(&self.nodes[0]);
+        let vpos_node = nodes.get_idx(&self.nodes[1]);
+        let val = Complex64::from_polar(self.ac_mag, self.ac_phase.to_radians());
+
+        if let Some(i) = vpos_node {
+            b[i] += val;
+        }
+        if let Some(i) = vneg_node {
+            b[i] -= val;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -83,6 +129,8 @@ mod tests {
             name: String::from("I1"),
             nodes: nodes.iter().map(|s| s.to_string()).collect(),
             val: 1e-3,
+            ac_mag: 0.0,
+            ac_phase: 0.0,
             tran_fn: None,
         }
     }
@@ -91,7 +139,7 @@ mod tests {
     fn test_linear_stamp_idd_node_0_gnd() {
         let idd = test_idd(&["1", "0"]);
         let nodes = parse_idd(&idd);
-        let mut a = Array2::zeros((1, 1));
+        let mut a = SparseMatrix::zeros((1, 1));
         let mut b = Array1::zeros(1);
 
         idd.linear_stamp(&nodes, &mut a, &mut b);
@@ -104,7 +152,7 @@ mod tests {
     fn test_linear_stamp_idd_node_1_gnd() {
         let idd = test_idd(&["0", "1"]);
         let nodes = parse_idd(&idd);
-        let mut a = Array2::zeros((1, 1));
+        let mut a = SparseMatrix::zeros((1, 1));
         let mut b = Array1::zeros(1);
 
         idd.linear_stamp(&nodes, &mut a, &mut b);
@@ -117,7 +165,7 @@ mod tests {
     fn test_linear_stamp_idd_to_nodes() {
         let idd = test_idd(&["1", "2"]);
         let nodes = parse_idd(&idd);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         idd.linear_stamp(&nodes, &mut a, &mut b);
@@ -137,7 +185,7 @@ mod tests {
     fn test_undo_linear_stamp() {
         let idd = test_idd(&["1", "2"]);
         let nodes = parse_idd(&idd);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         idd.linear_stamp(&nodes, &mut a, &mut b);
@@ -151,7 +199,7 @@ mod tests {
     fn test_count_nonlinear_funcs() {
         let idd = test_idd(&["1", "2"]);
         let nodes = parse_idd(&idd);
-        let mut h = Array2::zeros((2, 1));
+        let mut h = SparseMatrix::zeros((2, 1));
         let mut g = Vec::new();
 
         idd.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -163,7 +211,7 @@ mod tests {
     fn test_nonlinear_funcs_() {
         let idd = test_idd(&["1", "2"]);
         let nodes = parse_idd(&idd);
-        let mut h = Array2::zeros((2, 1));
+        let mut h = SparseMatrix::zeros((2, 1));
         let mut g = Vec::new();
 
         idd.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -174,10 +222,10 @@ mod tests {
 
     #[test]
     fn test_nonlinear_stamp() {
-        let idd = test_idd(&["1", "2"]);
+        let mut idd = test_idd(&["1", "2"]);
         let nodes = parse_idd(&idd);
         let x = array![1.0, 2.0];
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         idd.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
@@ -185,4 +233,22 @@ mod tests {
         assert_eq!(a, array![[0.0, 0.0], [0.0, 0.0]]);
         assert_eq!(b, array![0.0, 0.0]);
     }
+
+    #[test]
+    fn test_ac_stamp() {
+        let idd = Idd {
+            ac_mag: 1.0,
+            ..test_idd(&["1", "2"])
+        };
+        let nodes = parse_idd(&idd);
+        let mut a = SparseMatrix::zeros((2, 2));
+        let mut b = Array1::zeros(2);
+
+        idd.ac_stamp(&nodes, &1e3, &mut a, &mut b);
+
+        let n1 = nodes.get_idx("1").unwrap();
+        let n2 = nodes.get_idx("2").unwrap();
+        assert_eq!(b[n1], Complex64::new(-1.0, 0.0));
+        assert_eq!(b[n2], Complex64::new(1.0, 0.0));
+    }
 }
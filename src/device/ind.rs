@@ -1,7 +1,10 @@
 use ndarray::prelude::*;
+use num_complex::Complex64;
 
 use crate::device::{GType, Stamp};
+use crate::integration::TimeStep;
 use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
 
 mod model;
 
@@ -12,6 +15,8 @@ pub struct Ind {
     pub val: f64,
     pub u_curr: Option<f64>,
     pub i_curr: Option<f64>,
+    pub i_prev: Option<f64>,
+    pub ic: Option<f64>,
 }
 
 impl Stamp for Ind {
@@ -31,14 +36,24 @@ impl Stamp for Ind {
         GType::G2
     }
 
-    fn get_value(&self) -> f64 {
-        self.val
+    fn get_value(&self) -> Option<f64> {
+        Some(self.val)
     }
 
     fn set_value(&mut self, value: f64) {
         self.val = value;
     }
 
+    // Only the operating point has a branch for it. The branch current flows from
+    // the second node to the first, subtracted from 0 so none prints as 0, not -0.
+    fn currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        Some(vec![nodes.get_idx(&self.name).map_or(0.0, |i| -x[i])])
+    }
+
+    fn tran_currents(&self, _nodes: &NodeCollection, _x: &Array1<f64>) -> Option<Vec<f64>> {
+        Some(vec![-self.i_curr.unwrap_or(0.0)])
+    }
+
     fn init_state(&mut self, nodes: &NodeCollection, x: &Array1<f64>) {
         let is_idx = nodes
             .get_idx(&self.name)
@@ -46,9 +61,19 @@ impl Stamp for Ind {
 
         self.u_curr = Some(0.0);
         self.i_curr = Some(x[is_idx]);
+        self.i_prev = self.i_curr;
     }
 
-    fn update_state(&mut self, nodes: &NodeCollection, x: &Array1<f64>, h: &f64) {
+    fn init_state_ic(&mut self) {
+        // `IC=` flows from the first node written to the second, against the
+        // branch current
+        if let Some(i) = self.ic {
+            self.i_curr = Some(-i);
+            self.i_prev = Some(-i);
+        }
+    }
+
+    fn update_state(&mut self, nodes: &NodeCollection, x: &Array1<f64>, step: &TimeStep) {
         let vpos_idx = nodes.get_idx(&self.nodes[0]);
         let vneg_idx = nodes.get_idx(&self.nodes[1]);
 
@@ -61,16 +86,18 @@ impl Stamp for Ind {
             val: self.val,
             u_old: self.u_curr.expect("Ind voltage history not initialized"),
             i_old: self.i_curr.expect("Ind current history not initialized"),
+            i_prev: self.i_prev.expect("Ind current history not initialized"),
         };
 
+        self.i_prev = self.i_curr;
         self.u_curr = Some(l.u_new());
-        self.i_curr = Some(l.i_new(h));
+        self.i_curr = Some(l.i_new(step));
     }
 
     fn linear_startup_stamp(
         &self,
         nodes: &NodeCollection,
-        a: &mut Array2<f64>,
+        a: &mut SparseMatrix<f64>,
         b: &mut Array1<f64>,
     ) {
         let vpos_idx = nodes.get_idx(&self.nodes[0]);
@@ -91,36 +118,12 @@ impl Stamp for Ind {
         }
     }
 
-    fn undo_linear_startup_stamp(
-        &self,
-        nodes: &NodeCollection,
-        a: &mut Array2<f64>,
-        b: &mut Array1<f64>,
-    ) {
-        let vpos_idx = nodes.get_idx(&self.nodes[0]);
-        let vneg_idx = nodes.get_idx(&self.nodes[1]);
-        let is_idx = nodes
-            .get_idx(&self.name)
-            .expect("Couldn't find node label for inductor");
-
-        b[is_idx] = 0.0;
-
-        if let Some(i) = vpos_idx {
-            a[(is_idx, i)] -= 1.0;
-            a[(i, is_idx)] -= 1.0;
-        }
-        if let Some(i) = vneg_idx {
-            a[(is_idx, i)] -= 1.0;
-            a[(i, is_idx)] -= 1.0;
-        }
-    }
-
     fn dynamic_stamp(
         &self,
         nodes: &NodeCollection,
         x: &Array1<f64>,
-        h: &f64,
-        a: &mut Array2<f64>,
+        step: &TimeStep,
+        a: &mut SparseMatrix<f64>,
         b: &mut Array1<f64>,
     ) {
         let vpos_idx = nodes.get_idx(&self.nodes[0]);
@@ -135,10 +138,11 @@ impl Stamp for Ind {
             val: self.val,
             u_old: self.u_curr.expect("Cap voltage history not initialized"),
             i_old: self.i_curr.expect("Cap current history not initialized"),
+            i_prev: self.i_prev.expect("Cap current history not initialized"),
         };
 
-        let g_eq = l.g_eq(h);
-        let i_eq = l.i_eq(h);
+        let g_eq = l.g_eq(step);
+        let i_eq = l.i_eq(step);
 
         if let Some(i) = vpos_idx {
             a[(i, i)] += g_eq;
@@ -158,8 +162,8 @@ impl Stamp for Ind {
         &self,
         nodes: &NodeCollection,
         x: &Array1<f64>,
-        h: &f64,
-        a: &mut Array2<f64>,
+        step: &TimeStep,
+        a: &mut SparseMatrix<f64>,
         b: &mut Array1<f64>,
     ) {
         let vpos_idx = nodes.get_idx(&self.nodes[0]);
@@ -174,10 +178,11 @@ impl Stamp for Ind {
             val: self.val,
             u_old: self.u_curr.expect("Cap voltage history not initialized"),
             i_old: self.i_curr.expect("Cap current history not initialized"),
+            i_prev: self.i_prev.expect("Cap current history not initialized"),
         };
 
-        let g_eq = l.g_eq(h);
-        let i_eq = l.i_eq(h);
+        let g_eq = l.g_eq(step);
+        let i_eq = l.i_eq(step);
 
         if let Some(i) = vpos_idx {
             a[(i, i)] -= g_eq;
@@ -192,11 +197,36 @@ impl Stamp for Ind {
             a[(j, i)] += g_eq;
         }
     }
+
+    fn ac_stamp(
+        &self,
+        nodes: &NodeCollection,
+        omega: &f64,
+        a: &mut SparseMatrix<Complex64>,
+        _b: &mut Array1<Complex64>,
+    ) {
+        let y = Complex64::new(0.0, omega * self.val).inv();
+
+        let n0_idx = nodes.get_idx(&self.nodes[0]);
+        let n1_idx = nodes.get_idx(&self.nodes[1]);
+
+        if let Some(i) = n0_idx {
+            a[(i, i)] += y;
+        }
+        if let Some(i) = n1_idx {
+            a[(i, i)] += y;
+        }
+        if let (Some(i), Some(j)) = (n0_idx, n1_idx) {
+            a[(i, j)] -= y;
+            a[(j, i)] -= y;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::integration::Method;
 
     fn parse_ind(ind: &Ind) -> NodeCollection {
         NodeCollection::from_elems(&[Box::new(ind.clone())])
@@ -209,6 +239,8 @@ mod tests {
             val: 1e-3,
             u_curr: Some(0.0),
             i_curr: Some(1e-3),
+            i_prev: Some(1e-3),
+            ic: None,
         }
     }
 
@@ -216,12 +248,12 @@ mod tests {
     fn test_dynamic_stamp() {
         let ind = test_ind(&["1", "2"]);
         let nodes = parse_ind(&ind);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
         let x = array![1.0, 2.0];
-        let h = 1e-8;
+        let step = TimeStep::new(Method::Trapezoidal, 1e-8, 1e-8);
 
-        ind.dynamic_stamp(&nodes, &x, &h, &mut a, &mut b);
+        ind.dynamic_stamp(&nodes, &x, &step, &mut a, &mut b);
 
         let n1 = nodes.get_idx("1").unwrap();
         let n2 = nodes.get_idx("2").unwrap();
@@ -239,15 +271,83 @@ mod tests {
     fn test_undo_dynamic_stamp() {
         let ind = test_ind(&["1", "2"]);
         let nodes = parse_ind(&ind);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
         let x = array![1.0, 2.0];
-        let h = 1e-8;
+        let step = TimeStep::new(Method::Trapezoidal, 1e-8, 1e-8);
 
-        ind.dynamic_stamp(&nodes, &x, &h, &mut a, &mut b);
-        ind.undo_dynamic_stamp(&nodes, &x, &h, &mut a, &mut b);
+        ind.dynamic_stamp(&nodes, &x, &step, &mut a, &mut b);
+        ind.undo_dynamic_stamp(&nodes, &x, &step, &mut a, &mut b);
 
         assert_eq!(a, array![[0.0, 0.0], [0.0, 0.0]]);
         assert_eq!(b, array![0.0, 0.0]);
     }
+
+    #[test]
+    fn test_update_state_methods() {
+        let x = array![1.0, 0.0];
+        let cases = [
+            // i_old + h v / L
+            (Method::BackwardEuler, 2e-3),
+            // i_old + h (v + v_old) / 2L
+            (Method::Trapezoidal, 1.5e-3),
+            // (2 i_old - 1/2 i_prev + h v / L) / (3/2)
+            (Method::Gear2, 1e-3 + 1e-3 / 1.5),
+        ];
+
+        for (method, i_expected) in cases {
+            let mut ind = test_ind(&["1", "0"]);
+            let nodes = parse_ind(&ind);
+
+            ind.update_state(&nodes, &x, &TimeStep::new(method, 1e-6, 1e-6));
+
+            assert_eq!(ind.u_curr, Some(1.0));
+            assert_eq!(ind.i_prev, Some(1e-3));
+            assert!(
+                (ind.i_curr.unwrap() - i_expected).abs() < 1e-15,
+                "{:?}",
+                method
+            );
+        }
+    }
+
+    #[test]
+    fn test_init_state_ic() {
+        let mut ind = test_ind(&["0", "1"]);
+        ind.init_state_ic();
+        assert_eq!(ind.i_curr, Some(1e-3));
+
+        ind.ic = Some(2e-3);
+        ind.init_state_ic();
+
+        assert_eq!(ind.i_curr, Some(-2e-3));
+        assert_eq!(ind.i_prev, Some(-2e-3));
+    }
+
+    #[test]
+    fn test_ac_stamp() {
+        let ind = test_ind(&["1", "0"]);
+        let nodes = parse_ind(&ind);
+        let mut a = SparseMatrix::zeros((1, 1));
+        let mut b = Array1::zeros(1);
+
+        ind.ac_stamp(&nodes, &1e3, &mut a, &mut b);
+
+        // 1 / (j w L) for a 1 mH inductor
+        assert!((a[(0, 0)] - Complex64::new(0.0, -1.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_currents() {
+        let ind = test_ind(&["0", "1"]);
+        let nodes = NodeCollection::from_startup_elems(&[Box::new(ind.clone())]);
+        let branch = nodes.get_idx("L1").unwrap();
+        let mut x = Array1::zeros(2);
+        x[branch] = 3e-3;
+
+        // From the node written first, against the branch current
+        assert_eq!(ind.currents(&nodes, &x), Some(vec![-3e-3]));
+        assert_eq!(ind.tran_currents(&nodes, &x), Some(vec![-1e-3]));
+        assert_eq!(ind.currents(&parse_ind(&ind), &x), Some(vec![0.0]));
+    }
 }
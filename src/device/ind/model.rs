@@ -1,3 +1,5 @@
+use crate::integration::TimeStep;
+
 #[derive(Debug)]
 pub struct Model {
     pub vpos: f64,
@@ -5,22 +7,26 @@ pub struct Model {
     pub val: f64,
     pub u_old: f64,
     pub i_old: f64,
+    // Current the step before `i_old`
+    pub i_prev: f64,
 }
 
 impl Model {
-    pub fn g_eq(&self, h: &f64) -> f64 {
-        h / (2.0 * self.val)
+    pub fn g_eq(&self, step: &TimeStep) -> f64 {
+        step.h / (step.coeffs().a0 * self.val)
     }
 
-    pub fn i_eq(&self, h: &f64) -> f64 {
-        self.i_old + self.g_eq(h) * self.u_old
+    pub fn i_eq(&self, step: &TimeStep) -> f64 {
+        let c = step.coeffs();
+
+        -(c.a1 * self.i_old + c.a2 * self.i_prev) / c.a0 - c.b1 * self.g_eq(step) * self.u_old
     }
 
     pub fn u_new(&self) -> f64 {
         self.vpos - self.vneg
     }
 
-    pub fn i_new(&self, h: &f64) -> f64 {
-        self.g_eq(h) * (self.u_new() + self.u_old) + self.i_old
+    pub fn i_new(&self, step: &TimeStep) -> f64 {
+        self.g_eq(step) * self.u_new() + self.i_eq(step)
     }
 }
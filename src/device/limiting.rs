@@ -0,0 +1,131 @@
+// Newton step limiting of junction voltages as done in SPICE3. Each device
+// keeps the voltage it was last linearized at and moves from there.
+
+// Above this voltage the junction current grows faster than Newton can follow
+pub fn vcrit(vt: f64, is: f64) -> f64 {
+    vt * (vt / (std::f64::consts::SQRT_2 * is)).ln()
+}
+
+// pn junctions, the new voltage follows the log of the current instead of the
+// exponential of the voltage
+pub fn pnjlim(vnew: f64, vold: f64, vt: f64, vcrit: f64) -> f64 {
+    if vnew <= vcrit || (vnew - vold).abs() <= 2.0 * vt {
+        return vnew;
+    }
+
+    if vold > 0.0 {
+        let arg = 1.0 + (vnew - vold) / vt;
+        if arg > 0.0 {
+            vold + vt * arg.ln()
+        } else {
+            vcrit
+        }
+    } else {
+        vt * (vnew / vt).ln()
+    }
+}
+
+// MOSFET gate voltages, steps shrink as the device gets close to threshold
+pub fn fetlim(vnew: f64, vold: f64, vto: f64) -> f64 {
+    let vtsthi = (2.0 * (vold - vto)).abs() + 2.0;
+    let vtstlo = vtsthi / 2.0 + 2.0;
+    let vtox = vto + 3.5;
+    let delv = vnew - vold;
+
+    if vold >= vto {
+        if vold >= vtox {
+            if delv <= 0.0 {
+                // Going off
+                if vnew >= vtox {
+                    if -delv > vtstlo {
+                        return vold - vtstlo;
+                    }
+                    vnew
+                } else {
+                    vnew.max(vto + 2.0)
+                }
+            } else if delv >= vtsthi {
+                vold + vtsthi
+            } else {
+                vnew
+            }
+        } else if delv <= 0.0 {
+            // Middle region
+            vnew.max(vto - 0.5)
+        } else {
+            vnew.min(vto + 4.0)
+        }
+    } else if delv <= 0.0 {
+        // Off and going further off
+        if -delv > vtsthi {
+            vold - vtsthi
+        } else {
+            vnew
+        }
+    } else if vnew <= vto + 0.5 {
+        if delv > vtstlo {
+            vold + vtstlo
+        } else {
+            vnew
+        }
+    } else {
+        vto + 0.5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VT: f64 = 26e-3;
+
+    #[test]
+    fn test_vcrit() {
+        let v = vcrit(VT, 1e-14);
+
+        assert!(0.7 < v && v < 0.9);
+    }
+
+    #[test]
+    fn test_pnjlim_small_step() {
+        assert_eq!(pnjlim(0.75, 0.74, VT, 0.7), 0.75);
+    }
+
+    #[test]
+    fn test_pnjlim_below_vcrit() {
+        assert_eq!(pnjlim(-10.0, 0.7, VT, 0.7), -10.0);
+    }
+
+    #[test]
+    fn test_pnjlim_from_off() {
+        let v = pnjlim(5.0, 0.0, VT, 0.7);
+
+        assert!((v - VT * (5.0 / VT).ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_pnjlim_from_on() {
+        let v = pnjlim(5.0, 0.7, VT, 0.7);
+
+        assert!(0.7 < v && v < 0.9);
+    }
+
+    #[test]
+    fn test_fetlim_turning_on() {
+        // Can't jump past a little above threshold in one step
+        assert_eq!(fetlim(5.0, 0.0, 1.0), 1.5);
+        assert_eq!(fetlim(1.2, 0.0, 1.0), 1.2);
+    }
+
+    #[test]
+    fn test_fetlim_on() {
+        assert_eq!(fetlim(20.0, 5.0, 1.0), 15.0);
+        assert_eq!(fetlim(6.0, 5.0, 1.0), 6.0);
+    }
+
+    #[test]
+    fn test_fetlim_turning_off() {
+        assert_eq!(fetlim(-5.0, 5.0, 1.0), 3.0);
+        assert_eq!(fetlim(-5.0, 2.0, 1.0), 0.5);
+    }
+}
@@ -1,14 +1,38 @@
 use ndarray::prelude::*;
 
-use crate::device::{GType, Stamp};
+use crate::device::limiting;
+use crate::device::{GType, NonlinearFn, Stamp};
 use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
 
-mod model;
+pub mod model;
 
 #[derive(Debug, Clone)]
 pub struct NMOS {
     pub name: String,
     pub nodes: Vec<String>,
+    pub params: model::Params,
+    // Voltages the last Newton iteration was linearized at, as written
+    pub vgs_old: f64,
+    pub vds_old: f64,
+}
+
+fn get_model(
+    vd_idx: Option<usize>,
+    vg_idx: Option<usize>,
+    vs_idx: Option<usize>,
+    params: model::Params,
+    x: &Array1<f64>,
+) -> model::Model {
+    let mut vd = vd_idx.map_or(0.0, |i| x[i]);
+    let vg = vg_idx.map_or(0.0, |i| x[i]);
+    let mut vs = vs_idx.map_or(0.0, |i| x[i]);
+
+    if vs > vd {
+        (vs, vd) = (vd, vs);
+    }
+
+    model::Model { vd, vg, vs, params }
 }
 
 impl Stamp for NMOS {
@@ -24,12 +48,33 @@ impl Stamp for NMOS {
         GType::G1
     }
 
-    fn get_value(&self) -> f64 {
-        unimplemented!()
+    fn get_value(&self) -> Option<f64> {
+        None
+    }
+
+    fn set_value(&mut self, _value: f64) {}
+
+    fn terminals(&self) -> &'static [&'static str] {
+        &["d", "g", "s"]
     }
 
-    fn set_value(&mut self, _value: f64) {
-        unimplemented!()
+    fn currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        let vd_idx = nodes.get_idx(&self.nodes[0]);
+        let vs_idx = nodes.get_idx(&self.nodes[2]);
+        let m = get_model(
+            vd_idx,
+            nodes.get_idx(&self.nodes[1]),
+            vs_idx,
+            self.params,
+            x,
+        );
+
+        // The model's drain is the source terminal when biased in reverse
+        if vs_idx.map_or(0.0, |i| x[i]) > vd_idx.map_or(0.0, |i| x[i]) {
+            Some(vec![m.is(), m.ig(), m.id()])
+        } else {
+            Some(vec![m.id(), m.ig(), m.is()])
+        }
     }
 
     fn count_nonlinear_funcs(&self) -> usize {
@@ -39,8 +84,8 @@ impl Stamp for NMOS {
     fn nonlinear_funcs(
         &self,
         nodes: &NodeCollection,
-        h_mat: &mut Array2<f64>,
-        g_vec: &mut Vec<Box<dyn Fn(&Array1<f64>) -> f64>>,
+        h_mat: &mut SparseMatrix<f64>,
+        g_vec: &mut Vec<NonlinearFn>,
     ) {
         let vd_idx = nodes.get_idx(&self.nodes[0]);
         let vg_idx = nodes.get_idx(&self.nodes[1]);
@@ -56,58 +101,64 @@ impl Stamp for NMOS {
             h_mat[(i, g_vec.len() + 2)] = 1.0;
         }
 
-        fn get_model(
-            vd_idx: Option<usize>,
-            vg_idx: Option<usize>,
-            vs_idx: Option<usize>,
-            x: &Array1<f64>,
-        ) -> model::Model {
-            let mut vd = vd_idx.map_or(0.0, |i| x[i]);
-            let vg = vg_idx.map_or(0.0, |i| x[i]);
-            let mut vs = vs_idx.map_or(0.0, |i| x[i]);
-
-            if vs > vd {
-                (vs, vd) = (vd, vs);
-            }
-
-            model::Model { vd, vg, vs }
-        }
+        let params = self.params;
 
         g_vec.push(Box::new(move |x: &Array1<f64>| {
-            let m = get_model(vd_idx, vg_idx, vs_idx, x);
+            let m = get_model(vd_idx, vg_idx, vs_idx, params, x);
             m.id()
         }));
         g_vec.push(Box::new(move |x: &Array1<f64>| {
-            let m = get_model(vd_idx, vg_idx, vs_idx, x);
+            let m = get_model(vd_idx, vg_idx, vs_idx, params, x);
             m.ig()
         }));
         g_vec.push(Box::new(move |x: &Array1<f64>| {
-            let m = get_model(vd_idx, vg_idx, vs_idx, x);
+            let m = get_model(vd_idx, vg_idx, vs_idx, params, x);
             m.is()
         }));
     }
 
     fn nonlinear_stamp(
-        &self,
+        &mut self,
         nodes: &NodeCollection,
         x: &Array1<f64>,
-        a: &mut Array2<f64>,
+        a: &mut SparseMatrix<f64>,
         b: &mut Array1<f64>,
     ) {
         let mut vd_idx = nodes.get_idx(&self.nodes[0]);
         let vg_idx = nodes.get_idx(&self.nodes[1]);
         let mut vs_idx = nodes.get_idx(&self.nodes[2]);
 
-        let mut vd = vd_idx.map_or(0.0, |i| x[i]);
         let vg = vg_idx.map_or(0.0, |i| x[i]);
-        let mut vs = vs_idx.map_or(0.0, |i| x[i]);
+        let vs = vs_idx.map_or(0.0, |i| x[i]);
+        let mut vgs = vg - vs;
+        let mut vds = vd_idx.map_or(0.0, |i| x[i]) - vs;
+
+        // Limit the gate voltage against whichever terminal acted as the source
+        let vto = self.params.vto;
+        if self.vds_old >= 0.0 {
+            let vgd = vgs - vds;
+            vgs = limiting::fetlim(vgs, self.vgs_old, vto);
+            vds = vgs - vgd;
+        } else {
+            let vgd = limiting::fetlim(vgs - vds, self.vgs_old - self.vds_old, vto);
+            vds = vgs - vgd;
+        }
+        (self.vgs_old, self.vds_old) = (vgs, vds);
+
+        let mut vs = vg - vgs;
+        let mut vd = vs + vds;
 
         if vs > vd {
             (vd, vs) = (vs, vd);
             (vd_idx, vs_idx) = (vs_idx, vd_idx);
         }
 
-        let m = model::Model { vd, vg, vs };
+        let m = model::Model {
+            vd,
+            vg,
+            vs,
+            params: self.params,
+        };
 
         let gds = m.gds();
         let gm = m.gm();
@@ -142,14 +193,25 @@ mod tests {
         NodeCollection::from_elems(&[Box::new(m.clone())])
     }
 
+    fn test_params() -> model::Params {
+        model::Params {
+            kp: 0.5e-3,
+            vto: 0.6,
+            lambda: 0.01,
+        }
+    }
+
     #[test]
     fn test_linear_stamp() {
         let m = NMOS {
             name: String::from("M1"),
             nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: test_params(),
+            vgs_old: 0.0,
+            vds_old: 0.0,
         };
         let nodes = parse_nmos(&m);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         m.linear_stamp(&nodes, &mut a, &mut b);
@@ -163,9 +225,12 @@ mod tests {
         let m = NMOS {
             name: String::from("M1"),
             nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: test_params(),
+            vgs_old: 0.0,
+            vds_old: 0.0,
         };
         let nodes = parse_nmos(&m);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         m.linear_stamp(&nodes, &mut a, &mut b);
@@ -180,9 +245,12 @@ mod tests {
         let m = NMOS {
             name: String::from("M1"),
             nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: test_params(),
+            vgs_old: 0.0,
+            vds_old: 0.0,
         };
         let nodes = parse_nmos(&m);
-        let mut h = Array2::zeros((3, 3));
+        let mut h = SparseMatrix::zeros((3, 3));
         let mut g = Vec::new();
 
         m.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -195,9 +263,12 @@ mod tests {
         let m = NMOS {
             name: String::from("M1"),
             nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: test_params(),
+            vgs_old: 0.0,
+            vds_old: 0.0,
         };
         let nodes = parse_nmos(&m);
-        let mut h = Array2::zeros((3, 3));
+        let mut h = SparseMatrix::zeros((3, 3));
         let mut g = Vec::new();
 
         m.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -206,7 +277,7 @@ mod tests {
         let n2 = nodes.get_idx("2").unwrap();
         let n3 = nodes.get_idx("3").unwrap();
 
-        let mut h_model = Array2::zeros(h.raw_dim());
+        let mut h_model = SparseMatrix::zeros((3, 3));
         h_model[(n1, 0)] = 1.0;
         h_model[(n2, 1)] = 1.0;
         h_model[(n3, 2)] = 1.0;
@@ -225,10 +296,38 @@ mod tests {
     }
 
     #[test]
-    fn test_nonlinear_stamp_three_nodes() {
+    fn test_currents_reverse_bias() {
         let m = NMOS {
             name: String::from("M1"),
             nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: test_params(),
+            vgs_old: 0.0,
+            vds_old: 0.0,
+        };
+        let nodes = parse_nmos(&m);
+
+        let mut x = Array1::zeros(3);
+        x[nodes.get_idx("1").unwrap()] = 2.0;
+        x[nodes.get_idx("2").unwrap()] = 3.0;
+        let forward = m.currents(&nodes, &x).unwrap();
+
+        // Swapping drain and source voltages reverses the channel current
+        x[nodes.get_idx("1").unwrap()] = 0.0;
+        x[nodes.get_idx("3").unwrap()] = 2.0;
+        let reverse = m.currents(&nodes, &x).unwrap();
+
+        assert!(forward[0] > 0.0);
+        assert_eq!(reverse, vec![-forward[0], 0.0, -forward[2]]);
+    }
+
+    #[test]
+    fn test_nonlinear_stamp_three_nodes() {
+        let mut m = NMOS {
+            name: String::from("M1"),
+            nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: test_params(),
+            vgs_old: 0.0,
+            vds_old: 0.0,
         };
         let nodes = parse_nmos(&m);
 
@@ -241,7 +340,7 @@ mod tests {
         x[n2] = 1.0;
         x[n3] = 0.0;
 
-        let mut a = Array2::zeros((3, 3));
+        let mut a = SparseMatrix::zeros((3, 3));
         let mut b = Array1::zeros(3);
 
         m.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
@@ -249,7 +348,7 @@ mod tests {
         assert!(a[(n1, n1)] > 0.0);
         assert!(a[(n1, n2)] > 0.0);
         assert!(a[(n1, n3)] < 0.0);
-        assert_eq!(a.slice(s!(n2, ..)), array![0.0, 0.0, 0.0]);
+        assert_eq!(a.to_dense().slice(s!(n2, ..)), array![0.0, 0.0, 0.0]);
         assert!(a[(n3, n1)] < 0.0);
         assert!(a[(n3, n2)] < 0.0);
         assert!(a[(n3, n3)] > 0.0);
@@ -258,4 +357,30 @@ mod tests {
         assert_eq!(b[n2], 0.0);
         assert!(b[n3] < 0.0);
     }
+
+    #[test]
+    fn test_nonlinear_stamp_limits_vgs() {
+        let mut m = NMOS {
+            name: String::from("M1"),
+            nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: test_params(),
+            vgs_old: 0.0,
+            vds_old: 0.0,
+        };
+        let nodes = parse_nmos(&m);
+
+        let mut x = Array1::zeros(3);
+        x[nodes.get_idx("1").unwrap()] = 10.0;
+        x[nodes.get_idx("2").unwrap()] = 10.0;
+
+        let mut a = SparseMatrix::zeros((3, 3));
+        let mut b = Array1::zeros(3);
+
+        m.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
+
+        // Turning on from off stops just above threshold, the gate-drain
+        // voltage is kept
+        assert!((m.vgs_old - 1.1).abs() < 1e-12);
+        assert!((m.vds_old - 1.1).abs() < 1e-12);
+    }
 }
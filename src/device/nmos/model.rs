@@ -1,8 +1,40 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    pub kp: f64,
+    pub vto: f64,
+    pub lambda: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            kp: 2.0e-5,
+            vto: 0.0,
+            lambda: 0.0,
+        }
+    }
+}
+
+impl Params {
+    pub const NAMES: &'static [&'static str] = &["kp", "vto", "lambda"];
+
+    pub fn set(&mut self, name: &str, value: f64) -> bool {
+        match name.to_lowercase().as_str() {
+            "kp" => self.kp = value,
+            "vto" => self.vto = value,
+            "lambda" => self.lambda = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
 #[derive(Debug)]
 pub struct Model {
     pub vd: f64,
     pub vg: f64,
     pub vs: f64,
+    pub params: Params,
 }
 
 #[derive(Debug)]
@@ -12,10 +44,6 @@ pub enum State {
     Saturated,
 }
 
-const BETA: f64 = 0.5e-3;
-const VT: f64 = 0.6;
-const LAMBDA: f64 = 0.01;
-
 impl Model {
     pub fn vgs(&self) -> f64 {
         self.vg - self.vs
@@ -28,12 +56,13 @@ impl Model {
     pub fn state(&self) -> State {
         let vgs = self.vgs();
         let vds = self.vds();
+        let vt = self.params.vto;
 
-        if vgs <= VT {
+        if vgs <= vt {
             State::CutOff
-        } else if 0.0 <= vds && vds <= vgs - VT {
+        } else if 0.0 <= vds && vds <= vgs - vt {
             State::Linear
-        } else if 0.0 <= vgs - VT && vgs - VT <= vds {
+        } else if 0.0 <= vgs - vt && vgs - vt <= vds {
             State::Saturated
         } else {
             unreachable!()
@@ -41,11 +70,17 @@ impl Model {
     }
 
     pub fn id(&self) -> f64 {
+        let Params {
+            kp: beta,
+            vto: vt,
+            lambda,
+        } = self.params;
+
         match self.state() {
             State::CutOff => 0.0,
-            State::Linear => BETA * ((self.vgs() - VT) * self.vds() - 0.5 * self.vds().powi(2)),
+            State::Linear => beta * ((self.vgs() - vt) * self.vds() - 0.5 * self.vds().powi(2)),
             State::Saturated => {
-                0.5 * BETA * (self.vgs() - VT) * (self.vgs() - VT) * (1.0 + LAMBDA * self.vds())
+                0.5 * beta * (self.vgs() - vt) * (self.vgs() - vt) * (1.0 + lambda * self.vds())
             }
         }
     }
@@ -59,18 +94,30 @@ impl Model {
     }
 
     pub fn gds(&self) -> f64 {
+        let Params {
+            kp: beta,
+            vto: vt,
+            lambda,
+        } = self.params;
+
         match self.state() {
             State::CutOff => 0.0,
-            State::Linear => BETA * (self.vgs() - VT - self.vds()),
-            State::Saturated => 0.5 * BETA * LAMBDA * (self.vgs() - VT).powi(2),
+            State::Linear => beta * (self.vgs() - vt - self.vds()),
+            State::Saturated => 0.5 * beta * lambda * (self.vgs() - vt).powi(2),
         }
     }
 
     pub fn gm(&self) -> f64 {
+        let Params {
+            kp: beta,
+            vto: vt,
+            lambda,
+        } = self.params;
+
         match self.state() {
             State::CutOff => 0.0,
-            State::Linear => BETA * self.vds(),
-            State::Saturated => BETA * (self.vgs() - VT) * (1.0 + LAMBDA * self.vds()),
+            State::Linear => beta * self.vds(),
+            State::Saturated => beta * (self.vgs() - vt) * (1.0 + lambda * self.vds()),
         }
     }
 
@@ -1,14 +1,35 @@
 use ndarray::prelude::*;
 
-use crate::device::{GType, Stamp};
+use crate::device::limiting;
+use crate::device::{GType, NonlinearFn, Stamp};
 use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
 
-mod model;
+pub mod model;
 
 #[derive(Debug, Clone)]
 pub struct NPN {
     pub name: String,
     pub nodes: Vec<String>,
+    pub params: model::Params,
+    // Junction voltages the last Newton iteration was linearized at
+    pub vbe_old: f64,
+    pub vbc_old: f64,
+}
+
+fn get_model(
+    vc_idx: Option<usize>,
+    vb_idx: Option<usize>,
+    ve_idx: Option<usize>,
+    params: model::Params,
+    x: &Array1<f64>,
+) -> model::Model {
+    model::Model {
+        vc: vc_idx.map_or(0.0, |i| x[i]),
+        vb: vb_idx.map_or(0.0, |i| x[i]),
+        ve: ve_idx.map_or(0.0, |i| x[i]),
+        params,
+    }
 }
 
 impl Stamp for NPN {
@@ -24,12 +45,30 @@ impl Stamp for NPN {
         GType::G1
     }
 
-    fn get_value(&self) -> f64 {
-        unimplemented!()
+    fn get_value(&self) -> Option<f64> {
+        None
+    }
+
+    fn set_value(&mut self, _value: f64) {}
+
+    fn set_temp(&mut self, temp: f64) {
+        self.params.temp = temp;
     }
 
-    fn set_value(&mut self, _value: f64) {
-        unimplemented!()
+    fn terminals(&self) -> &'static [&'static str] {
+        &["c", "b", "e"]
+    }
+
+    fn currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        let q = get_model(
+            nodes.get_idx(&self.nodes[0]),
+            nodes.get_idx(&self.nodes[1]),
+            nodes.get_idx(&self.nodes[2]),
+            self.params,
+            x,
+        );
+
+        Some(vec![q.ic(), q.ib(), q.ie()])
     }
 
     fn count_nonlinear_funcs(&self) -> usize {
@@ -39,8 +78,8 @@ impl Stamp for NPN {
     fn nonlinear_funcs(
         &self,
         nodes: &NodeCollection,
-        h_mat: &mut Array2<f64>,
-        g_vec: &mut Vec<Box<dyn Fn(&Array1<f64>) -> f64>>,
+        h_mat: &mut SparseMatrix<f64>,
+        g_vec: &mut Vec<NonlinearFn>,
     ) {
         let vc_idx = nodes.get_idx(&self.nodes[0]);
         let vb_idx = nodes.get_idx(&self.nodes[1]);
@@ -56,38 +95,27 @@ impl Stamp for NPN {
             h_mat[(i, g_vec.len() + 2)] = 1.0;
         }
 
-        fn get_model(
-            vc_idx: Option<usize>,
-            vb_idx: Option<usize>,
-            ve_idx: Option<usize>,
-            x: &Array1<f64>,
-        ) -> model::Model {
-            model::Model {
-                vc: vc_idx.map_or(0.0, |i| x[i]),
-                vb: vb_idx.map_or(0.0, |i| x[i]),
-                ve: ve_idx.map_or(0.0, |i| x[i]),
-            }
-        }
+        let params = self.params;
 
         g_vec.push(Box::new(move |x: &Array1<f64>| {
-            let q = get_model(vc_idx, vb_idx, ve_idx, x);
+            let q = get_model(vc_idx, vb_idx, ve_idx, params, x);
             q.ic()
         }));
         g_vec.push(Box::new(move |x: &Array1<f64>| {
-            let q = get_model(vc_idx, vb_idx, ve_idx, x);
+            let q = get_model(vc_idx, vb_idx, ve_idx, params, x);
             q.ib()
         }));
         g_vec.push(Box::new(move |x: &Array1<f64>| {
-            let q = get_model(vc_idx, vb_idx, ve_idx, x);
+            let q = get_model(vc_idx, vb_idx, ve_idx, params, x);
             q.ie()
         }));
     }
 
     fn nonlinear_stamp(
-        &self,
+        &mut self,
         nodes: &NodeCollection,
         x: &Array1<f64>,
-        a: &mut Array2<f64>,
+        a: &mut SparseMatrix<f64>,
         b: &mut Array1<f64>,
     ) {
         let vc_idx = nodes.get_idx(&self.nodes[0]);
@@ -98,7 +126,17 @@ impl Stamp for NPN {
         let vb = vb_idx.map_or(0.0, |i| x[i]);
         let ve = ve_idx.map_or(0.0, |i| x[i]);
 
-        let q = model::Model { vc, vb, ve };
+        let p = &self.params;
+        let vbe = limiting::pnjlim(vb - ve, self.vbe_old, p.vte(), p.vcrit_be());
+        let vbc = limiting::pnjlim(vb - vc, self.vbc_old, p.vtc(), p.vcrit_bc());
+        (self.vbe_old, self.vbc_old) = (vbe, vbc);
+
+        let q = model::Model {
+            vc: vb - vbc,
+            vb,
+            ve: vb - vbe,
+            params: self.params,
+        };
 
         let gee = q.gee();
         let gec = q.gec();
@@ -147,9 +185,12 @@ mod tests {
         let q = NPN {
             name: String::from("M1"),
             nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: model::Params::default(),
+            vbe_old: 0.0,
+            vbc_old: 0.0,
         };
         let nodes = parse_npn(&q);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         q.linear_stamp(&nodes, &mut a, &mut b);
@@ -163,9 +204,12 @@ mod tests {
         let q = NPN {
             name: String::from("M1"),
             nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: model::Params::default(),
+            vbe_old: 0.0,
+            vbc_old: 0.0,
         };
         let nodes = parse_npn(&q);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         q.linear_stamp(&nodes, &mut a, &mut b);
@@ -180,9 +224,12 @@ mod tests {
         let q = NPN {
             name: String::from("Q1"),
             nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: model::Params::default(),
+            vbe_old: 0.0,
+            vbc_old: 0.0,
         };
         let nodes = parse_npn(&q);
-        let mut h = Array2::zeros((3, 3));
+        let mut h = SparseMatrix::zeros((3, 3));
         let mut g = Vec::new();
 
         q.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -195,9 +242,12 @@ mod tests {
         let q = NPN {
             name: String::from("Q1"),
             nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: model::Params::default(),
+            vbe_old: 0.0,
+            vbc_old: 0.0,
         };
         let nodes = parse_npn(&q);
-        let mut h = Array2::zeros((3, 3));
+        let mut h = SparseMatrix::zeros((3, 3));
         let mut g = Vec::new();
 
         q.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -206,7 +256,7 @@ mod tests {
         let n2 = nodes.get_idx("2").unwrap();
         let n3 = nodes.get_idx("3").unwrap();
 
-        let mut h_model = Array2::zeros(h.raw_dim());
+        let mut h_model = SparseMatrix::zeros((3, 3));
         h_model[(n1, 0)] = 1.0;
         h_model[(n2, 1)] = 1.0;
         h_model[(n3, 2)] = 1.0;
@@ -226,9 +276,12 @@ mod tests {
 
     #[test]
     fn test_nonlinear_stamp_three_nodes() {
-        let q = NPN {
+        let mut q = NPN {
             name: String::from("Q1"),
             nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: model::Params::default(),
+            vbe_old: 0.0,
+            vbc_old: 0.0,
         };
         let nodes = parse_npn(&q);
 
@@ -241,7 +294,7 @@ mod tests {
         x[n2] = 1.0;
         x[n3] = 0.0;
 
-        let mut a = Array2::zeros((3, 3));
+        let mut a = SparseMatrix::zeros((3, 3));
         let mut b = Array1::zeros(3);
 
         q.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
@@ -253,4 +306,50 @@ mod tests {
         assert!(b[n2] > 0.0);
         assert!(b[n3] < 0.0);
     }
+
+    #[test]
+    fn test_nonlinear_stamp_limits_junctions() {
+        let mut q = NPN {
+            name: String::from("Q1"),
+            nodes: vec![String::from("1"), String::from("2"), String::from("3")],
+            params: model::Params::default(),
+            vbe_old: 0.0,
+            vbc_old: 0.0,
+        };
+        let nodes = parse_npn(&q);
+
+        let mut x = Array1::zeros(3);
+        x[nodes.get_idx("1").unwrap()] = 10.0;
+        x[nodes.get_idx("2").unwrap()] = 10.0;
+
+        let mut a = SparseMatrix::zeros((3, 3));
+        let mut b = Array1::zeros(3);
+
+        q.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
+
+        assert!(q.vbe_old < 1.0);
+        assert_eq!(q.vbc_old, 0.0);
+        assert!(b.iter().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn test_currents() {
+        let q = NPN {
+            name: String::from("Q1"),
+            nodes: vec![String::from("1"), String::from("2"), String::from("0")],
+            params: model::Params::default(),
+            vbe_old: 0.0,
+            vbc_old: 0.0,
+        };
+        let nodes = parse_npn(&q);
+        let mut x = Array1::zeros(2);
+        x[nodes.get_idx("1").unwrap()] = 2.0;
+        x[nodes.get_idx("2").unwrap()] = 0.7;
+
+        let i = q.currents(&nodes, &x).unwrap();
+
+        assert_eq!(q.terminals(), ["c", "b", "e"]);
+        assert!(i[0] > 0.0 && i[1] > 0.0 && i[2] < 0.0);
+        assert!((i[0] + i[1] + i[2]).abs() < 1e-15);
+    }
 }
@@ -1,17 +1,91 @@
+use crate::device::limiting;
+use crate::device::temperature::{saturation_current, thermal_voltage, TNOM};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    pub is: f64,
+    pub bf: f64,
+    pub br: f64,
+    pub nf: f64,
+    pub nr: f64,
+    // Set from the circuit, not the model card
+    pub temp: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            is: 1.0e-16,
+            bf: 100.0,
+            br: 1.0,
+            nf: 1.0,
+            nr: 1.0,
+            temp: TNOM,
+        }
+    }
+}
+
+impl Params {
+    pub const NAMES: &'static [&'static str] = &["is", "bf", "br", "nf", "nr"];
+
+    pub fn set(&mut self, name: &str, value: f64) -> bool {
+        match name.to_lowercase().as_str() {
+            "is" => self.is = value,
+            "bf" => self.bf = value,
+            "br" => self.br = value,
+            "nf" => self.nf = value,
+            "nr" => self.nr = value,
+            _ => return false,
+        }
+        true
+    }
+
+    // Ebers-Moll parameters derived from the transport model ones
+    fn af(&self) -> f64 {
+        self.bf / (1.0 + self.bf)
+    }
+
+    fn ar(&self) -> f64 {
+        self.br / (1.0 + self.br)
+    }
+
+    fn is_t(&self) -> f64 {
+        saturation_current(self.is, 1.0, self.temp)
+    }
+
+    fn ies(&self) -> f64 {
+        self.is_t() / self.af()
+    }
+
+    fn ics(&self) -> f64 {
+        self.is_t() / self.ar()
+    }
+
+    pub fn vte(&self) -> f64 {
+        self.nf * thermal_voltage(self.temp)
+    }
+
+    pub fn vtc(&self) -> f64 {
+        self.nr * thermal_voltage(self.temp)
+    }
+
+    pub fn vcrit_be(&self) -> f64 {
+        limiting::vcrit(self.vte(), self.ies())
+    }
+
+    pub fn vcrit_bc(&self) -> f64 {
+        limiting::vcrit(self.vtc(), self.ics())
+    }
+}
+
 #[derive(Debug)]
 pub struct Model {
     pub vc: f64,
     pub vb: f64,
     pub ve: f64,
+    pub params: Params,
 }
 
-const IES: f64 = 2e-14;
-const ICS: f64 = 99e-14;
-const VTE: f64 = 26e-3;
-const VTC: f64 = 26e-3;
-const AR: f64 = 0.02;
-const AF: f64 = 0.99;
-
 impl Model {
     pub fn vbe(&self) -> f64 {
         self.vb - self.ve
@@ -22,11 +96,15 @@ impl Model {
     }
 
     pub fn ie(&self) -> f64 {
-        -IES * (self.vbe() / VTE).exp_m1() + AR * ICS * (self.vbc() / VTC).exp_m1()
+        let p = &self.params;
+        -p.ies() * (self.vbe() / p.vte()).exp_m1()
+            + p.ar() * p.ics() * (self.vbc() / p.vtc()).exp_m1()
     }
 
     pub fn ic(&self) -> f64 {
-        AF * IES * (self.vbe() / VTE).exp_m1() - ICS * (self.vbc() / VTC).exp_m1()
+        let p = &self.params;
+        p.af() * p.ies() * (self.vbe() / p.vte()).exp_m1()
+            - p.ics() * (self.vbc() / p.vtc()).exp_m1()
     }
 
     pub fn ib(&self) -> f64 {
@@ -34,16 +112,20 @@ impl Model {
     }
 
     pub fn gee(&self) -> f64 {
-        IES / VTE * (self.vbe() / VTE).exp()
+        let p = &self.params;
+        p.ies() / p.vte() * (self.vbe() / p.vte()).exp()
     }
     pub fn gec(&self) -> f64 {
-        AR * ICS / VTC * (self.vbc() / VTC).exp()
+        let p = &self.params;
+        p.ar() * p.ics() / p.vtc() * (self.vbc() / p.vtc()).exp()
     }
     pub fn gce(&self) -> f64 {
-        AF * IES / VTE * (self.vbe() / VTE).exp()
+        let p = &self.params;
+        p.af() * p.ies() / p.vte() * (self.vbe() / p.vte()).exp()
     }
     pub fn gcc(&self) -> f64 {
-        ICS / VTC * (self.vbc() / VTC).exp()
+        let p = &self.params;
+        p.ics() / p.vtc() * (self.vbc() / p.vtc()).exp()
     }
 
     pub fn ie_eq(&self) -> f64 {
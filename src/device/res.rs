@@ -2,6 +2,7 @@ use ndarray::prelude::*;
 
 use crate::device::{GType, Stamp};
 use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
 
 #[derive(Debug, Clone)]
 pub struct Res {
@@ -23,15 +24,27 @@ impl Stamp for Res {
         GType::G1
     }
 
-    fn get_value(&self) -> f64 {
-        self.val
+    fn get_value(&self) -> Option<f64> {
+        Some(self.val)
     }
 
     fn set_value(&mut self, value: f64) {
         self.val = value;
     }
 
-    fn linear_stamp(&self, nodes: &NodeCollection, a: &mut Array2<f64>, _b: &mut Array1<f64>) {
+    fn currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        let v1 = nodes.get_idx(&self.nodes[0]).map_or(0.0, |i| x[i]);
+        let v2 = nodes.get_idx(&self.nodes[1]).map_or(0.0, |i| x[i]);
+
+        Some(vec![(v1 - v2) / self.val])
+    }
+
+    fn linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
+    ) {
         let g = 1.0 / self.val;
 
         let vneg_node = nodes.get_idx(&self.nodes[0]);
@@ -49,7 +62,12 @@ impl Stamp for Res {
         }
     }
 
-    fn undo_linear_stamp(&self, nodes: &NodeCollection, a: &mut Array2<f64>, _b: &mut Array1<f64>) {
+    fn undo_linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
+    ) {
         let g = 1.0 / self.val;
 
         let vneg_node = nodes.get_idx(&self.nodes[0]);
@@ -84,7 +102,7 @@ mod tests {
             val: 1e3,
         };
         let nodes = parse_res(&res);
-        let mut a = Array2::zeros((1, 1));
+        let mut a = SparseMatrix::zeros((1, 1));
         let mut b = Array1::zeros(1);
 
         res.linear_stamp(&nodes, &mut a, &mut b);
@@ -101,7 +119,7 @@ mod tests {
             val: 1e3,
         };
         let nodes = parse_res(&res);
-        let mut a = Array2::zeros((1, 1));
+        let mut a = SparseMatrix::zeros((1, 1));
         let mut b = Array1::zeros(1);
 
         res.linear_stamp(&nodes, &mut a, &mut b);
@@ -118,7 +136,7 @@ mod tests {
             val: 1e3,
         };
         let nodes = parse_res(&res);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         res.linear_stamp(&nodes, &mut a, &mut b);
@@ -126,7 +144,7 @@ mod tests {
         let n1 = nodes.get_idx("1").unwrap();
         let n2 = nodes.get_idx("2").unwrap();
 
-        let mut a_model = Array2::zeros((nodes.len(), nodes.len()));
+        let mut a_model = SparseMatrix::zeros((nodes.len(), nodes.len()));
         a_model[(n1, n1)] = 1e-3;
         a_model[(n1, n2)] = -1e-3;
         a_model[(n2, n1)] = -1e-3;
@@ -144,13 +162,13 @@ mod tests {
             val: 1e3,
         };
         let nodes = parse_res(&res);
-        let mut a = Array2::zeros((3, 3));
+        let mut a = SparseMatrix::zeros((3, 3));
         let mut b = Array1::zeros(3);
 
         res.linear_stamp(&nodes, &mut a, &mut b);
         res.undo_linear_stamp(&nodes, &mut a, &mut b);
 
-        assert_eq!(a, Array2::zeros((3, 3)));
+        assert_eq!(a, SparseMatrix::zeros((3, 3)));
         assert_eq!(b, Array1::zeros(3));
     }
 
@@ -162,7 +180,7 @@ mod tests {
             val: 1e3,
         };
         let nodes = parse_res(&res);
-        let mut h = Array2::zeros((1, 2));
+        let mut h = SparseMatrix::zeros((1, 2));
         let mut g = Vec::new();
 
         res.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -178,7 +196,7 @@ mod tests {
             val: 1e3,
         };
         let nodes = parse_res(&res);
-        let mut h = Array2::zeros((2, 1));
+        let mut h = SparseMatrix::zeros((2, 1));
         let mut g = Vec::new();
 
         res.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -189,19 +207,32 @@ mod tests {
 
     #[test]
     fn test_nonlinear_stamp() {
-        let res = Res {
+        let mut res = Res {
             name: String::from("R1"),
             nodes: vec![String::from("1"), String::from("2")],
             val: 1e-3,
         };
         let nodes = parse_res(&res);
         let x = array![1.0, 2.0];
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         res.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
 
-        assert_eq!(a, Array2::zeros((2, 2)));
+        assert_eq!(a, SparseMatrix::zeros((2, 2)));
         assert_eq!(b, Array1::zeros(2));
     }
+
+    #[test]
+    fn test_currents() {
+        let res = Res {
+            name: String::from("R1"),
+            nodes: vec![String::from("1"), String::from("0")],
+            val: 1e3,
+        };
+        let nodes = parse_res(&res);
+
+        assert_eq!(res.currents(&nodes, &array![2.0]), Some(vec![2e-3]));
+        assert!(res.terminals().is_empty());
+    }
 }
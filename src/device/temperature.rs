@@ -0,0 +1,40 @@
+// Temperature dependence of pn junctions, model parameters are given at TNOM
+
+// Degrees Celsius
+pub const TNOM: f64 = 27.0;
+
+const VT_NOM: f64 = 26e-3;
+const KELVIN: f64 = 273.15;
+// Energy gap in eV and saturation current temperature exponent, SPICE defaults
+const EG: f64 = 1.11;
+const XTI: f64 = 3.0;
+
+pub fn thermal_voltage(temp: f64) -> f64 {
+    VT_NOM * (temp + KELVIN) / (TNOM + KELVIN)
+}
+
+// Saturation current at `temp` of a junction with emission coefficient `n`
+pub fn saturation_current(is: f64, n: f64, temp: f64) -> f64 {
+    let ratio = (temp + KELVIN) / (TNOM + KELVIN);
+
+    is * ratio.powf(XTI / n) * (EG / (n * thermal_voltage(temp)) * (ratio - 1.0)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nominal() {
+        assert_eq!(thermal_voltage(TNOM), VT_NOM);
+        assert_eq!(saturation_current(1e-14, 1.0, TNOM), 1e-14);
+    }
+
+    #[test]
+    fn test_saturation_current_doubles() {
+        // Roughly doubles every 5 degrees around room temperature
+        let ratio = saturation_current(1e-14, 1.0, TNOM + 5.0) / 1e-14;
+
+        assert!(1.8 < ratio && ratio < 2.4);
+    }
+}
@@ -0,0 +1,135 @@
+use ndarray::prelude::*;
+
+use crate::device::{GType, Stamp};
+use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
+
+// G element, gain * V(nc+, nc-) flowing from n+ to n- through the source
+#[derive(Debug, Clone)]
+pub struct VCCS {
+    pub name: String,
+    // n+, n-, nc+, nc-
+    pub nodes: Vec<String>,
+    pub val: f64,
+}
+
+impl VCCS {
+    fn stamp(&self, nodes: &NodeCollection, a: &mut SparseMatrix<f64>, sign: f64) {
+        let vpos_idx = nodes.get_idx(&self.nodes[0]);
+        let vneg_idx = nodes.get_idx(&self.nodes[1]);
+        let cpos_idx = nodes.get_idx(&self.nodes[2]);
+        let cneg_idx = nodes.get_idx(&self.nodes[3]);
+        let gm = sign * self.val;
+
+        if let (Some(i), Some(j)) = (vpos_idx, cpos_idx) {
+            a[(i, j)] += gm;
+        }
+        if let (Some(i), Some(j)) = (vpos_idx, cneg_idx) {
+            a[(i, j)] -= gm;
+        }
+        if let (Some(i), Some(j)) = (vneg_idx, cpos_idx) {
+            a[(i, j)] -= gm;
+        }
+        if let (Some(i), Some(j)) = (vneg_idx, cneg_idx) {
+            a[(i, j)] += gm;
+        }
+    }
+}
+
+impl Stamp for VCCS {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_nodes(&self) -> &Vec<String> {
+        &self.nodes
+    }
+
+    fn gtype(&self) -> GType {
+        GType::G1
+    }
+
+    fn get_value(&self) -> Option<f64> {
+        Some(self.val)
+    }
+
+    fn set_value(&mut self, value: f64) {
+        self.val = value;
+    }
+
+    fn currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        let vc_pos = nodes.get_idx(&self.nodes[2]).map_or(0.0, |i| x[i]);
+        let vc_neg = nodes.get_idx(&self.nodes[3]).map_or(0.0, |i| x[i]);
+
+        Some(vec![self.val * (vc_pos - vc_neg)])
+    }
+
+    fn linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
+    ) {
+        self.stamp(nodes, a, 1.0);
+    }
+
+    fn undo_linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
+    ) {
+        self.stamp(nodes, a, -1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_vccs(nodes: &[&str]) -> VCCS {
+        VCCS {
+            name: String::from("G1"),
+            nodes: nodes.iter().map(|s| s.to_string()).collect(),
+            val: 1e-3,
+        }
+    }
+
+    fn parse_vccs(vccs: &VCCS) -> NodeCollection {
+        NodeCollection::from_elems(&[Box::new(vccs.clone())])
+    }
+
+    #[test]
+    fn test_linear_stamp() {
+        let vccs = test_vccs(&["1", "2", "3", "0"]);
+        let nodes = parse_vccs(&vccs);
+        let mut a = SparseMatrix::zeros((3, 3));
+        let mut b = Array1::zeros(3);
+
+        vccs.linear_stamp(&nodes, &mut a, &mut b);
+
+        let n1 = nodes.get_idx("1").unwrap();
+        let n2 = nodes.get_idx("2").unwrap();
+        let n3 = nodes.get_idx("3").unwrap();
+
+        let mut a_model = SparseMatrix::zeros((nodes.len(), nodes.len()));
+        a_model[(n1, n3)] = 1e-3;
+        a_model[(n2, n3)] = -1e-3;
+
+        assert_eq!(a, a_model);
+        assert_eq!(b, Array1::zeros(3));
+    }
+
+    #[test]
+    fn test_undo_linear_stamp() {
+        let vccs = test_vccs(&["1", "2", "3", "4"]);
+        let nodes = parse_vccs(&vccs);
+        let mut a = SparseMatrix::zeros((4, 4));
+        let mut b = Array1::zeros(4);
+
+        vccs.linear_stamp(&nodes, &mut a, &mut b);
+        vccs.undo_linear_stamp(&nodes, &mut a, &mut b);
+
+        assert_eq!(a, SparseMatrix::zeros((4, 4)));
+    }
+}
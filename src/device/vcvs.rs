@@ -0,0 +1,139 @@
+use ndarray::prelude::*;
+
+use crate::device::{GType, Stamp};
+use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
+
+// E element, V(n+, n-) = gain * V(nc+, nc-)
+#[derive(Debug, Clone)]
+pub struct VCVS {
+    pub name: String,
+    // n+, n-, nc+, nc-
+    pub nodes: Vec<String>,
+    pub val: f64,
+}
+
+impl VCVS {
+    fn stamp(&self, nodes: &NodeCollection, a: &mut SparseMatrix<f64>, sign: f64) {
+        let vpos_idx = nodes.get_idx(&self.nodes[0]);
+        let vneg_idx = nodes.get_idx(&self.nodes[1]);
+        let cpos_idx = nodes.get_idx(&self.nodes[2]);
+        let cneg_idx = nodes.get_idx(&self.nodes[3]);
+        let is_idx = nodes
+            .get_idx(&self.name)
+            .expect("Couldn't find node label for source.");
+
+        if let Some(i) = vpos_idx {
+            a[(is_idx, i)] += sign;
+            a[(i, is_idx)] += sign;
+        }
+        if let Some(i) = vneg_idx {
+            a[(is_idx, i)] -= sign;
+            a[(i, is_idx)] -= sign;
+        }
+        if let Some(i) = cpos_idx {
+            a[(is_idx, i)] -= sign * self.val;
+        }
+        if let Some(i) = cneg_idx {
+            a[(is_idx, i)] += sign * self.val;
+        }
+    }
+}
+
+impl Stamp for VCVS {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_nodes(&self) -> &Vec<String> {
+        &self.nodes
+    }
+
+    fn gtype(&self) -> GType {
+        GType::G2
+    }
+
+    fn get_value(&self) -> Option<f64> {
+        Some(self.val)
+    }
+
+    fn set_value(&mut self, value: f64) {
+        self.val = value;
+    }
+
+    fn currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        Some(vec![nodes.get_idx(&self.name).map_or(0.0, |i| x[i])])
+    }
+
+    fn linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
+    ) {
+        self.stamp(nodes, a, 1.0);
+    }
+
+    fn undo_linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        _b: &mut Array1<f64>,
+    ) {
+        self.stamp(nodes, a, -1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_vcvs(nodes: &[&str]) -> VCVS {
+        VCVS {
+            name: String::from("E1"),
+            nodes: nodes.iter().map(|s| s.to_string()).collect(),
+            val: 10.0,
+        }
+    }
+
+    fn parse_vcvs(vcvs: &VCVS) -> NodeCollection {
+        NodeCollection::from_elems(&[Box::new(vcvs.clone())])
+    }
+
+    #[test]
+    fn test_linear_stamp() {
+        let vcvs = test_vcvs(&["1", "0", "2", "3"]);
+        let nodes = parse_vcvs(&vcvs);
+        let mut a = SparseMatrix::zeros((4, 4));
+        let mut b = Array1::zeros(4);
+
+        vcvs.linear_stamp(&nodes, &mut a, &mut b);
+
+        let n1 = nodes.get_idx("1").unwrap();
+        let n2 = nodes.get_idx("2").unwrap();
+        let n3 = nodes.get_idx("3").unwrap();
+        let e1 = nodes.get_idx("E1").unwrap();
+
+        let mut a_model = SparseMatrix::zeros((nodes.len(), nodes.len()));
+        a_model[(n1, e1)] = 1.0;
+        a_model[(e1, n1)] = 1.0;
+        a_model[(e1, n2)] = -10.0;
+        a_model[(e1, n3)] = 10.0;
+
+        assert_eq!(a, a_model);
+        assert_eq!(b, Array1::zeros(4));
+    }
+
+    #[test]
+    fn test_undo_linear_stamp() {
+        let vcvs = test_vcvs(&["1", "2", "3", "4"]);
+        let nodes = parse_vcvs(&vcvs);
+        let mut a = SparseMatrix::zeros((5, 5));
+        let mut b = Array1::zeros(5);
+
+        vcvs.linear_stamp(&nodes, &mut a, &mut b);
+        vcvs.undo_linear_stamp(&nodes, &mut a, &mut b);
+
+        assert_eq!(a, SparseMatrix::zeros((5, 5)));
+    }
+}
@@ -1,7 +1,9 @@
 use ndarray::prelude::*;
+use num_complex::Complex64;
 
 use crate::device::{GType, Stamp};
 use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
 use crate::spice_fn::SpiceFn;
 
 #[derive(Debug, Clone)]
@@ -9,6 +11,9 @@ pub struct Vdd {
     pub name: String,
     pub nodes: Vec<String>,
     pub val: f64,
+    pub ac_mag: f64,
+    // Degrees
+    pub ac_phase: f64,
     pub tran_fn: Option<SpiceFn>,
 }
 
@@ -25,14 +30,22 @@ impl Stamp for Vdd {
         GType::G2
     }
 
-    fn get_value(&self) -> f64 {
-        self.val
+    fn get_value(&self) -> Option<f64> {
+        Some(self.val)
     }
 
     fn set_value(&mut self, value: f64) {
         self.val = value;
     }
 
+    fn is_source(&self) -> bool {
+        true
+    }
+
+    fn currents(&self, nodes: &NodeCollection, x: &Array1<f64>) -> Option<Vec<f64>> {
+        Some(vec![nodes.get_idx(&self.name).map_or(0.0, |i| x[i])])
+    }
+
     fn has_tran(&self) -> bool {
         self.tran_fn.is_some()
     }
@@ -43,7 +56,11 @@ impl Stamp for Vdd {
         }
     }
 
-    fn linear_stamp(&self, nodes: &NodeCollection, a: &mut Array2<f64>, b: &mut Array1<f64>) {
+    fn next_breakpoint(&self, t: &f64) -> Option<f64> {
+        self.tran_fn.as_ref().and_then(|f| f.next_breakpoint(t))
+    }
+
+    fn linear_stamp(&self, nodes: &NodeCollection, a: &mut SparseMatrix<f64>, b: &mut Array1<f64>) {
         let vneg_idx = nodes.get_idx(&self.nodes[0]);
         let vpos_idx = nodes.get_idx(&self.nodes[1]);
         let is_idx = nodes
@@ -63,7 +80,12 @@ impl Stamp for Vdd {
         }
     }
 
-    fn undo_linear_stamp(&self, nodes: &NodeCollection, a: &mut Array2<f64>, b: &mut Array1<f64>) {
+    fn undo_linear_stamp(
+        &self,
+        nodes: &NodeCollection,
+        a: &mut SparseMatrix<f64>,
+        b: &mut Array1<f64>,
+    ) {
         let vneg_idx = nodes.get_idx(&self.nodes[0]);
         let vpos_idx = nodes.get_idx(&self.nodes[1]);
         let is_idx = nodes
@@ -83,6 +105,20 @@ impl Stamp for Vdd {
         }
     }
 
+    fn ac_stamp(
+        &self,
+        nodes: &NodeCollection,
+        _omega: &f64,
+        _a: &mut SparseMatrix<Complex64>,
+        b: &mut Array1<Complex64>,
+    ) {
+        let is_idx = nodes
+            .get_idx(&self.name)
+            .expect("Couldn't find node label for source.");
+
+        b[is_idx] = Complex64::from_polar(self.ac_mag, self.ac_phase.to_radians());
+    }
+
     fn count_nonlinear_funcs(&self) -> usize {
         0
     }
@@ -101,6 +137,8 @@ mod tests {
             name: String::from("V1"),
             nodes: nodes.iter().map(|s| s.to_string()).collect(),
             val: 1e-3,
+            ac_mag: 0.0,
+            ac_phase: 0.0,
             tran_fn: None,
         }
     }
@@ -109,7 +147,7 @@ mod tests {
     fn test_linear_stamp_vdd_node_0_gnd() {
         let vdd = test_vdd(&["0", "1"]);
         let nodes = parse_vdd(&vdd);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         vdd.linear_stamp(&nodes, &mut a, &mut b);
@@ -117,7 +155,7 @@ mod tests {
         let n1 = nodes.get_idx("1").unwrap();
         let v1 = nodes.get_idx("V1").unwrap();
 
-        let mut a_model = Array2::zeros((nodes.len(), nodes.len()));
+        let mut a_model = SparseMatrix::zeros((nodes.len(), nodes.len()));
         let mut b_model = Array1::zeros(nodes.len());
         a_model[(n1, v1)] = 1.0;
         a_model[(v1, n1)] = 1.0;
@@ -132,7 +170,7 @@ mod tests {
     fn test_linear_stamp_vdd_node_1_gnd() {
         let vdd = test_vdd(&["1", "0"]);
         let nodes = parse_vdd(&vdd);
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         vdd.linear_stamp(&nodes, &mut a, &mut b);
@@ -140,7 +178,7 @@ mod tests {
         let n1 = nodes.get_idx("1").unwrap();
         let v1 = nodes.get_idx("V1").unwrap();
 
-        let mut a_model = Array2::zeros((nodes.len(), nodes.len()));
+        let mut a_model = SparseMatrix::zeros((nodes.len(), nodes.len()));
         let mut b_model = Array1::zeros(nodes.len());
         a_model[(n1, v1)] = -1.0;
         a_model[(v1, n1)] = -1.0;
@@ -155,7 +193,7 @@ mod tests {
     fn test_linear_stamp_vdd_to_nodes() {
         let vdd = test_vdd(&["1", "2"]);
         let nodes = parse_vdd(&vdd);
-        let mut a = Array2::zeros((3, 3));
+        let mut a = SparseMatrix::zeros((3, 3));
         let mut b = Array1::zeros(3);
 
         vdd.linear_stamp(&nodes, &mut a, &mut b);
@@ -164,7 +202,7 @@ mod tests {
         let n2 = nodes.get_idx("2").unwrap();
         let v1 = nodes.get_idx("V1").unwrap();
 
-        let mut a_model = Array2::zeros((nodes.len(), nodes.len()));
+        let mut a_model = SparseMatrix::zeros((nodes.len(), nodes.len()));
         let mut b_model = Array1::zeros(nodes.len());
         a_model[(n1, v1)] = -1.0;
         a_model[(v1, n1)] = -1.0;
@@ -178,17 +216,25 @@ mod tests {
         assert_eq!(b, b_model);
     }
 
+    #[test]
+    fn test_currents_without_branch() {
+        let vdd = test_vdd(&["1", "0"]);
+        let nodes = NodeCollection::from_elems(&[]);
+
+        assert_eq!(vdd.currents(&nodes, &Array1::zeros(0)), Some(vec![0.0]));
+    }
+
     #[test]
     fn test_undo_linear_stamp() {
         let vdd = test_vdd(&["1", "2"]);
         let nodes = parse_vdd(&vdd);
-        let mut a = Array2::zeros((3, 3));
+        let mut a = SparseMatrix::zeros((3, 3));
         let mut b = Array1::zeros(3);
 
         vdd.linear_stamp(&nodes, &mut a, &mut b);
         vdd.undo_linear_stamp(&nodes, &mut a, &mut b);
 
-        assert_eq!(a, Array2::zeros((3, 3)));
+        assert_eq!(a, SparseMatrix::zeros((3, 3)));
         assert_eq!(b, Array1::zeros(3));
     }
 
@@ -196,7 +242,7 @@ mod tests {
     fn test_count_nonlinear_funcs() {
         let vdd = test_vdd(&["1", "2"]);
         let nodes = parse_vdd(&vdd);
-        let mut h = Array2::zeros((2, 1));
+        let mut h = SparseMatrix::zeros((2, 1));
         let mut g = Vec::new();
 
         vdd.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -208,7 +254,7 @@ mod tests {
     fn test_nonlinear_funcs_() {
         let vdd = test_vdd(&["1", "2"]);
         let nodes = parse_vdd(&vdd);
-        let mut h = Array2::zeros((2, 1));
+        let mut h = SparseMatrix::zeros((2, 1));
         let mut g = Vec::new();
 
         vdd.nonlinear_funcs(&nodes, &mut h, &mut g);
@@ -219,10 +265,10 @@ mod tests {
 
     #[test]
     fn test_nonlinear_stamp() {
-        let vdd = test_vdd(&["1", "2"]);
+        let mut vdd = test_vdd(&["1", "2"]);
         let nodes = parse_vdd(&vdd);
         let x = array![1.0, 2.0];
-        let mut a = Array2::zeros((2, 2));
+        let mut a = SparseMatrix::zeros((2, 2));
         let mut b = Array1::zeros(2);
 
         vdd.nonlinear_stamp(&nodes, &x, &mut a, &mut b);
@@ -230,4 +276,22 @@ mod tests {
         assert_eq!(a, array![[0.0, 0.0], [0.0, 0.0]]);
         assert_eq!(b, array![0.0, 0.0]);
     }
+
+    #[test]
+    fn test_ac_stamp() {
+        let vdd = Vdd {
+            ac_mag: 2.0,
+            ac_phase: 90.0,
+            ..test_vdd(&["0", "1"])
+        };
+        let nodes = parse_vdd(&vdd);
+        let mut a = SparseMatrix::zeros((2, 2));
+        let mut b = Array1::zeros(2);
+
+        vdd.ac_stamp(&nodes, &1e3, &mut a, &mut b);
+
+        let v1 = nodes.get_idx("V1").unwrap();
+        assert!((b[v1] - Complex64::new(0.0, 2.0)).norm() < 1e-12);
+        assert_eq!(a, SparseMatrix::zeros((2, 2)));
+    }
 }
@@ -3,44 +3,80 @@ use std::collections::HashMap;
 use ndarray::prelude::*;
 
 use crate::command;
+use crate::device::vdd::Vdd;
 use crate::device::Stamp;
-use crate::engine::error::NotConvergedError;
+use crate::engine::continuation::Strategy;
+use crate::engine::error::SimError;
 use crate::engine::mna::MNA;
+use crate::engine::settings::Settings;
 use crate::engine::sim_result::SimResult;
+use crate::engine::sparse_lu::Solver;
 use crate::engine::transient::state_history::StateHistory;
 use crate::engine::transient::T_STEP_MIN;
+use crate::integration::{Method, TimeStep};
+use crate::node::GND;
 use crate::node_collection::NodeCollection;
+use crate::parser::check_elems::{self, Finding};
+use crate::parser::error::ParseError;
+use crate::parser::Rebuild;
+use crate::sparse::SparseMatrix;
 
+mod ac;
+pub mod continuation;
 pub mod error;
-mod gauss_lu;
 mod mna;
 mod newtons_method;
 mod node_vec_norm;
-mod sim_result;
+mod probe;
+pub mod settings;
+pub mod sim_result;
+mod sparse_lu;
+mod sweep;
 mod transient;
 
-pub struct Engine {
+pub struct Engine<'a> {
     pub elems: Vec<Box<dyn Stamp>>,
-    pub op_cmd: Option<command::Command>,
-    pub dc_cmd: Option<command::Command>,
-    pub tran_cmd: Option<command::Command>,
+    // `.op`, `.dc`, `.tran` and `.ac` in the order they were written
+    pub analyses: Vec<command::Command>,
+    // How the last operating point was found
+    pub op_strategy: Option<Strategy>,
+    pub settings: Settings,
+    // Node voltages from `.ic` and `.nodeset`
+    pub ic: HashMap<String, f64>,
+    pub nodeset: HashMap<String, f64>,
+    // Element currents from `.probe`, as extra columns
+    pub probes: Vec<command::Probe>,
+    // Builds the elements again with some `.param` values replaced, for `.dc` sweeps over them
+    pub rebuild: Option<Rebuild<'a>>,
     num_nonlinear_funcs: usize,
 }
 
-impl Engine {
-    pub fn new(mut elems: Vec<Box<dyn Stamp>>, mut cmds: Vec<command::Command>) -> Self {
-        let op_cmd = cmds
-            .iter()
-            .position(|x| matches!(x, command::Command::Op))
-            .map(|i| cmds.remove(i));
-        let dc_cmd = cmds
-            .iter()
-            .position(|x| matches!(x, command::Command::DC(_)))
-            .map(|i| cmds.remove(i));
-        let tran_cmd = cmds
-            .iter()
-            .position(|x| matches!(x, command::Command::Tran(_)))
-            .map(|i| cmds.remove(i));
+impl<'a> Engine<'a> {
+    pub fn new(mut elems: Vec<Box<dyn Stamp>>, cmds: Vec<command::Command>) -> Self {
+        let (analyses, cmds): (Vec<_>, Vec<_>) = cmds.into_iter().partition(|cmd| {
+            matches!(
+                cmd,
+                command::Command::Op
+                    | command::Command::DC(_)
+                    | command::Command::Tran(_)
+                    | command::Command::AC(_)
+            )
+        });
+
+        // Later `.options`, `.ic` and `.nodeset` override earlier ones
+        let mut settings = Settings::default();
+        let mut ic = HashMap::new();
+        let mut nodeset = HashMap::new();
+        let mut probes = Vec::new();
+        for cmd in cmds.into_iter() {
+            match cmd {
+                command::Command::Options(options) => settings.apply(&options),
+                command::Command::IC(values) => ic.extend(values),
+                command::Command::NodeSet(values) => nodeset.extend(values),
+                command::Command::Probe(values) => probes.extend(values),
+                _ => (),
+            }
+        }
 
         let num_nonlinear_funcs = elems.iter().map(|e| e.count_nonlinear_funcs()).sum();
 
@@ -48,103 +84,336 @@ impl Engine {
             if elem.has_tran() {
                 elem.eval_tran(&0.0);
             }
+            elem.set_temp(settings.temp);
         }
 
         Engine {
-            elems: elems,
-            op_cmd: op_cmd,
-            dc_cmd: dc_cmd,
-            tran_cmd: tran_cmd,
+            elems,
+            analyses,
+            op_strategy: None,
+            settings,
+            ic,
+            nodeset,
+            probes,
+            rebuild: None,
             num_nonlinear_funcs,
         }
     }
 
-    pub fn run_op(&mut self) -> Result<SimResult, NotConvergedError> {
-        let nodes = NodeCollection::from_startup_elems(&self.elems);
+    // Mistakes in the topology of the elements, errors first. The analyses don't
+    // check, one with errors fails or gives a solution only the gmin shunts allow.
+    pub fn check(&self) -> Vec<Finding> {
+        check_elems::check_elems(&self.elems)
+    }
 
-        let mut mna = MNA::new(nodes.len(), self.num_nonlinear_funcs);
+    pub fn with_rebuild(
+        mut self,
+        rebuild: impl Fn(&HashMap<String, f64>) -> Result<Vec<Box<dyn Stamp>>, Vec<ParseError>> + 'a,
+    ) -> Self {
+        self.rebuild = Some(Box::new(rebuild));
+        self
+    }
 
-        for elem in self.elems.iter() {
-            elem.linear_startup_stamp(&nodes, &mut mna.a, &mut mna.b);
-            elem.nonlinear_funcs(&nodes, &mut mna.h, &mut mna.g);
-        }
+    pub fn run(&mut self, analysis: &command::Command) -> Result<SimResult, SimError> {
+        let res = match analysis {
+            command::Command::Op => self.run_op(),
+            command::Command::DC(params) => self.run_dc(params),
+            command::Command::Tran(params) => self.run_tran(params),
+            command::Command::AC(params) => self.run_ac(params),
+            _ => {
+                let msg = format!("{} is not an analysis", analysis);
+                return Err(SimError::InvalidParameter(msg));
+            }
+        };
 
-        let mut x = mna.get_x();
-        let n_iters = newtons_method::solve(&nodes, &self.elems, &mut x, &mna)?;
+        res.map_err(|e| e.in_analysis(&analysis.to_string()))
+    }
 
-        for elem in self.elems.iter_mut() {
-            elem.init_state(&nodes, &x);
-        }
+    pub fn run_op(&mut self) -> Result<SimResult, SimError> {
+        let columns = probe::columns(&self.probes, &self.elems)?;
+        let (nodes, x, n_iters) = self.solve_op(&HashMap::new())?;
 
         let mut headers = vec!["n_iters"];
         headers.extend(nodes.keys().map(String::as_str).collect::<Vec<_>>());
+        headers.extend(columns.iter().map(|c| c.label.as_str()));
         let mut res = sim_result::SimResult::new(&headers);
 
         let mut record = HashMap::from([(String::from("n_iters"), n_iters as f64)]);
         for (name, node) in nodes.iter() {
             record.insert(String::from(name), x[node.idx]);
         }
+        let currents = probe::values(&columns, &self.elems, &nodes, &x, false)?;
+        for (col, i) in columns.iter().zip(currents.iter()) {
+            record.insert(col.label.clone(), *i);
+        }
         res.push(record);
 
         Ok(res)
     }
 
-    pub fn run_dc(&mut self) -> Result<SimResult, NotConvergedError> {
-        let dc_params = match &self.dc_cmd {
-            Some(command::Command::DC(x)) => x,
-            _ => panic!("DC simulation wrongly configured."),
-        };
+    // The `.nodeset` voltages are held for a first solve that picks between
+    // stable states, the actual operating point starts from its solution
+    fn solve_op(
+        &mut self,
+        held: &HashMap<String, f64>,
+    ) -> Result<(NodeCollection, Array1<f64>, u64), SimError> {
+        let mut guess = HashMap::new();
+        let mut n_iters = 0;
 
-        let sweep_idx = self
-            .elems
-            .iter()
-            .position(|e| e.get_name() == dc_params.source)
-            .expect("Sweep source not found");
+        if !self.nodeset.is_empty() {
+            let mut nodeset_held = self.nodeset.clone();
+            nodeset_held.extend(held.clone());
+
+            // Only a hint, go on without it if it can't be solved
+            if let Ok((nodes, x, n)) = self.solve_op_held(&nodeset_held, &guess) {
+                guess = nodes.iter().map(|(k, n)| (k.clone(), x[n.idx])).collect();
+                n_iters += n;
+            }
+        }
+
+        let (nodes, x, n) = self.solve_op_held(held, &guess)?;
+
+        Ok((nodes, x, n_iters + n))
+    }
+
+    // Operating point with some nodes held by voltage sources, for `.nodeset`
+    // and for the `.ic` a transient starts from
+    fn solve_op_held(
+        &mut self,
+        held: &HashMap<String, f64>,
+        guess: &HashMap<String, f64>,
+    ) -> Result<(NodeCollection, Array1<f64>, u64), SimError> {
+        let n_elems = self.elems.len();
+        for (node, val) in held.iter() {
+            self.elems.push(Box::new(Vdd {
+                name: format!("hold({})", node),
+                nodes: vec![String::from(GND), node.clone()],
+                val: *val,
+                ac_mag: 0.0,
+                ac_phase: 0.0,
+                tran_fn: None,
+            }));
+        }
+
+        let nodes = NodeCollection::from_startup_elems(&self.elems);
 
-        let nodes = NodeCollection::from_elems(&self.elems);
         let mut mna = MNA::new(nodes.len(), self.num_nonlinear_funcs);
 
         for elem in self.elems.iter() {
-            elem.linear_stamp(&nodes, &mut mna.a, &mut mna.b);
+            elem.linear_startup_stamp(&nodes, &mut mna.a, &mut mna.b);
             elem.nonlinear_funcs(&nodes, &mut mna.h, &mut mna.g);
         }
+        mna::stamp_shunts(&mut mna.a, &nodes, self.settings.gmin);
+
+        let mut x = mna.get_x();
+        for (name, node) in nodes.iter() {
+            if let Some(val) = guess.get(name) {
+                x[node.idx] = *val;
+            }
+        }
+
+        let res = continuation::solve(&nodes, &mut self.elems, &mut x, &mut mna, &self.settings);
+        self.elems.truncate(n_elems);
+        let (n_iters, strategy) = res?;
+        self.op_strategy = Some(strategy);
+
+        for elem in self.elems.iter_mut() {
+            elem.init_state(&nodes, &x);
+        }
+
+        Ok((nodes, x, n_iters))
+    }
+
+    // Skips the operating point, nodes without `.ic` start at 0 V and
+    // inductors without `IC=` at 0 A
+    fn init_uic(&mut self) -> (NodeCollection, Array1<f64>) {
+        let nodes = NodeCollection::from_startup_elems(&self.elems);
+        let mut x = Array1::zeros(nodes.len());
+        for (name, val) in self.ic.iter() {
+            if let Some(i) = nodes.get_idx(name) {
+                x[i] = *val;
+            }
+        }
+
+        for elem in self.elems.iter_mut() {
+            elem.init_state(&nodes, &x);
+            elem.init_state_ic();
+        }
+
+        (nodes, x)
+    }
+
+    pub fn run_dc(&mut self, dc_params: &command::DCParams) -> Result<SimResult, SimError> {
+        let mut sweep_idxs = Vec::new();
+        let mut vals_bkp = Vec::new();
+        for sweep in dc_params.sweeps.iter() {
+            let idx = match &sweep.var {
+                command::SweepVar::Elem(name) => Some(
+                    self.elems
+                        .iter()
+                        .position(|e| e.get_name() == name)
+                        .ok_or_else(|| SimError::UnknownSweepSource(name.clone()))?,
+                ),
+                _ => None,
+            };
+            let val = match idx {
+                Some(i) => Some(self.elems[i].get_value().ok_or_else(|| {
+                    let msg = format!("{} has no value to sweep", self.elems[i].get_name());
+                    SimError::InvalidParameter(msg)
+                })?),
+                None => None,
+            };
+            sweep_idxs.push(idx);
+            vals_bkp.push(val);
+        }
+
+        // Swept parameters can change any value in the netlist, so it's read again
+        // for every point and the original elements put back afterwards
+        let mut original = None;
+        let res = self.dc_sweep(dc_params, &sweep_idxs, &mut original);
+
+        if let Some(elems) = original {
+            self.elems = elems;
+        }
+        for (idx, val) in sweep_idxs.iter().zip(vals_bkp.iter()) {
+            if let (Some(i), Some(val)) = (idx, val) {
+                self.elems[*i].set_value(*val);
+            }
+        }
+        for elem in self.elems.iter_mut() {
+            elem.set_temp(self.settings.temp);
+        }
+
+        res
+    }
+
+    fn dc_sweep(
+        &mut self,
+        dc_params: &command::DCParams,
+        sweep_idxs: &[Option<usize>],
+        original: &mut Option<Vec<Box<dyn Stamp>>>,
+    ) -> Result<SimResult, SimError> {
+        let nodes = NodeCollection::from_elems(&self.elems);
+        let probes = probe::columns(&self.probes, &self.elems)?;
+
+        let columns = dc_params
+            .sweeps
+            .iter()
+            .map(|s| match &s.var {
+                command::SweepVar::Elem(name) | command::SweepVar::Param(name) => {
+                    format!("sweep({})", name)
+                }
+                command::SweepVar::Temp => String::from("sweep(temp)"),
+            })
+            .collect::<Vec<_>>();
 
         let mut headers = vec!["n_iters"];
+        headers.extend(columns.iter().map(String::as_str));
         headers.extend(nodes.keys().map(String::as_str).collect::<Vec<_>>());
+        headers.extend(probes.iter().map(|c| c.label.as_str()));
         let mut res = sim_result::SimResult::new(&headers);
 
-        let mut x = mna.get_x();
+        let values = dc_params
+            .sweeps
+            .iter()
+            .map(|s| sweep::values(&s.values))
+            .collect::<Vec<_>>();
 
-        let val_bkp = self.elems[sweep_idx].get_value();
+        let mut x: Array1<f64> = Array1::zeros(nodes.len());
+        let mut solver = Solver::new();
 
-        let sweep_iter = Array::range(dc_params.start, dc_params.stop, dc_params.step);
+        for point in sweep::grid(&values) {
+            if dc_params
+                .sweeps
+                .iter()
+                .any(|s| matches!(s.var, command::SweepVar::Param(_)))
+            {
+                let params: HashMap<String, f64> = dc_params
+                    .sweeps
+                    .iter()
+                    .zip(point.iter())
+                    .filter_map(|(s, val)| match &s.var {
+                        command::SweepVar::Param(name) => Some((name.clone(), *val)),
+                        _ => None,
+                    })
+                    .collect();
+                let rebuild = self.rebuild.as_ref().ok_or_else(|| {
+                    let mut names = params.keys().cloned().collect::<Vec<_>>();
+                    names.sort();
+                    let msg = format!(
+                        "param {} can't be swept without rebuilding the netlist",
+                        names.join(", ")
+                    );
+                    SimError::InvalidParameter(msg)
+                })?;
+                let rebuilt = rebuild(&params).map_err(|errors| {
+                    let msgs = errors
+                        .iter()
+                        .map(|e| format!("{}:{}: {}", e.file.display(), e.line, e.message))
+                        .collect();
+                    SimError::Rebuild(msgs)
+                })?;
+                let elems = std::mem::replace(&mut self.elems, rebuilt);
+                original.get_or_insert(elems);
 
-        for sweep_val in sweep_iter {
-            self.elems[sweep_idx].undo_linear_stamp(&nodes, &mut mna.a, &mut mna.b);
-            self.elems[sweep_idx].set_value(sweep_val);
-            self.elems[sweep_idx].linear_stamp(&nodes, &mut mna.a, &mut mna.b);
+                for elem in self.elems.iter_mut() {
+                    if elem.has_tran() {
+                        elem.eval_tran(&0.0);
+                    }
+                }
+            }
+
+            let mut temp = self.settings.temp;
+            for ((s, idx), val) in dc_params.sweeps.iter().zip(sweep_idxs).zip(point.iter()) {
+                match (&s.var, idx) {
+                    (_, Some(i)) => self.elems[*i].set_value(*val),
+                    (command::SweepVar::Temp, _) => temp = *val,
+                    _ => (),
+                }
+            }
+            for elem in self.elems.iter_mut() {
+                elem.set_temp(temp);
+            }
 
-            let n_iters = newtons_method::solve(&nodes, &self.elems, &mut x, &mna)?;
+            // Restamped for every point, but the pivots found for the first one are kept
+            let mut mna = MNA::new(nodes.len(), self.num_nonlinear_funcs);
+            mna.solver = solver;
+            for elem in self.elems.iter() {
+                elem.linear_stamp(&nodes, &mut mna.a, &mut mna.b);
+                elem.nonlinear_funcs(&nodes, &mut mna.h, &mut mna.g);
+            }
+            mna::stamp_shunts(&mut mna.a, &nodes, self.settings.gmin);
+
+            let n_iters = newtons_method::solve(
+                &nodes,
+                &mut self.elems,
+                &mut x,
+                &mut mna,
+                &self.settings,
+                self.settings.itl1,
+            )?;
+            solver = mna.solver;
 
             let mut record = HashMap::new();
             for (name, node) in nodes.iter() {
                 record.insert(String::from(name), x[node.idx]);
             }
+            for (col, val) in columns.iter().zip(point.iter()) {
+                record.insert(col.clone(), *val);
+            }
+            let currents = probe::values(&probes, &self.elems, &nodes, &x, false)?;
+            for (col, i) in probes.iter().zip(currents.iter()) {
+                record.insert(col.label.clone(), *i);
+            }
             record.insert(String::from("n_iters"), n_iters as f64);
             res.push(record);
         }
 
-        self.elems[sweep_idx].set_value(val_bkp);
-
         Ok(res)
     }
 
-    pub fn run_tran(&mut self) -> Result<SimResult, NotConvergedError> {
-        let tran_params = match &self.tran_cmd {
-            Some(command::Command::Tran(x)) => x.to_owned(),
-            _ => panic!("DC simulation wrongly configured."),
-        };
-
+    pub fn run_tran(&mut self, tran_params: &command::TranParams) -> Result<SimResult, SimError> {
+        let probes = probe::columns(&self.probes, &self.elems)?;
         let nodes = NodeCollection::from_elems(&self.elems);
         let mut mna = MNA::new(nodes.len(), self.num_nonlinear_funcs);
         let mut x = mna.get_x();
@@ -153,55 +422,609 @@ impl Engine {
             elem.linear_stamp(&nodes, &mut mna.a, &mut mna.b);
             elem.nonlinear_funcs(&nodes, &mut mna.h, &mut mna.g);
         }
+        mna::stamp_shunts(&mut mna.a, &nodes, self.settings.gmin);
 
         // Load Start Up solutions
-        let startup_res = self.run_op()?;
+        let (startup_nodes, startup_x) = match tran_params.uic {
+            true => self.init_uic(),
+            false => {
+                let ic = self.ic.clone();
+                let (nodes, x, _) = self.solve_op(&ic)?;
+                (nodes, x)
+            }
+        };
         for (name, node) in nodes.iter() {
-            x[node.idx] = startup_res.get(name)[0];
+            let idx = startup_nodes.get_idx(name).ok_or_else(|| {
+                SimError::Internal(format!("{} has no operating point to start from", name))
+            })?;
+            x[node.idx] = startup_x[idx];
         }
 
         let mut state_hist = StateHistory::new();
+        // The probed currents over time, kept alongside the solutions
+        let mut probe_hist = StateHistory::new();
+        let probes0 = probe::values(&probes, &self.elems, &nodes, &x, true)?;
+        let step_max = tran_params
+            .tmax
+            .or(self.settings.tmax)
+            .unwrap_or(tran_params.step);
+        let x0 = x.clone();
 
-        let mut t = tran_params.start;
+        let mut t = 0.0;
         let mut h = T_STEP_MIN;
+        let mut h_prev = h;
         let mut next_h;
+        let ctx = transient::Context {
+            nodes: &nodes,
+            settings: &self.settings,
+            step_max,
+        };
+        let mut step;
+        // Backward Euler doesn't need a derivative from before the discontinuity
+        let mut after_breakpoint = true;
+        let mut breakpoint = transient::next_breakpoint(&self.elems, &t, &step_max);
 
         while t < tran_params.stop {
-            (h, next_h) = transient::step(
-                &nodes,
+            // Land on source discontinuities instead of stepping over them
+            let h_breakpoint = breakpoint.map(|b| transient::breakpoint_h(&t, &b, &step_max));
+            if let Some(h_b) = h_breakpoint {
+                h = transient::clip_to_breakpoint(h, h_b);
+            }
+
+            let method = match after_breakpoint {
+                true => Method::BackwardEuler,
+                false => self.settings.method,
+            };
+
+            (step, next_h) = transient::step(
+                &ctx,
                 &mut self.elems,
                 &mut mna,
                 &t,
-                &h,
+                &TimeStep::new(method, h, h_prev),
                 &mut x,
                 &mut state_hist,
-                &tran_params.step,
             )?;
 
-            t += h;
-
             for elem in self.elems.iter_mut() {
-                elem.update_state(&nodes, &x, &h);
+                elem.update_state(&nodes, &x, &step);
+            }
+
+            t += step.h;
+            let currents = probe::values(&probes, &self.elems, &nodes, &x, true)?;
+            probe_hist.push(0, &currents, t);
+            after_breakpoint = Some(step.h) == h_breakpoint;
+
+            if after_breakpoint {
+                state_hist.mark_breakpoint();
+                breakpoint = transient::next_breakpoint(&self.elems, &t, &step_max);
+                next_h = transient::breakpoint_step(&t, breakpoint, &step_max);
             }
 
+            h_prev = step.h;
             h = next_h;
         }
 
+        // Sources back at t = 0 for whatever runs next
+        for elem in self.elems.iter_mut() {
+            if elem.has_tran() {
+                elem.eval_tran(&0.0);
+            }
+        }
+
         let mut headers = vec!["n_iters", "t"];
         headers.extend(nodes.keys().map(String::as_str).collect::<Vec<_>>());
+        headers.extend(probes.iter().map(|c| c.label.as_str()));
         let mut res = sim_result::SimResult::new(&headers);
 
-        for step in state_hist.iter() {
+        let sample = |hist: &StateHistory, initial: &Array1<f64>| match self.settings.interp {
+            true => hist.sample_uniform(
+                initial,
+                tran_params.start,
+                tran_params.stop,
+                tran_params.step,
+            ),
+            false => hist
+                .iter()
+                .filter(|r| r.t >= tran_params.start)
+                .cloned()
+                .collect::<Vec<_>>(),
+        };
+        let records = sample(&state_hist, &x0);
+        let probe_records = sample(&probe_hist, &probes0);
+
+        for (step, currents) in records.iter().zip(probe_records.iter()) {
             let mut record = HashMap::from([
                 (String::from("n_iters"), step.n_iters as f64),
-                (String::from("t"), step.t as f64),
+                (String::from("t"), step.t),
             ]);
             for (name, node) in nodes.iter() {
                 record.insert(String::from(name), step.x[node.idx]);
             }
+            for (col, i) in probes.iter().zip(currents.x.iter()) {
+                record.insert(col.label.clone(), *i);
+            }
             res.push(record);
         }
 
         Ok(res)
     }
+
+    pub fn run_ac(&mut self, ac_params: &command::ACParams) -> Result<SimResult, SimError> {
+        let nodes = NodeCollection::from_elems(&self.elems);
+        let mut x = Array1::zeros(nodes.len());
+
+        let op_res = self.run_op()?;
+        for (name, node) in nodes.iter() {
+            x[node.idx] = op_res.get(name)[0];
+        }
+
+        // Small-signal conductances, linearized around the operating point
+        let mut g_mat = SparseMatrix::zeros((nodes.len(), nodes.len()));
+        let mut b = Array1::zeros(nodes.len());
+        for elem in self.elems.iter_mut() {
+            elem.linear_stamp(&nodes, &mut g_mat, &mut b);
+            elem.nonlinear_stamp(&nodes, &x, &mut g_mat, &mut b);
+        }
+        mna::stamp_shunts(&mut g_mat, &nodes, self.settings.gmin);
+
+        let mag_headers = nodes.keys().map(|n| format!("mag({})", n));
+        let phase_headers = nodes.keys().map(|n| format!("phase({})", n));
+        let mut headers = vec![String::from("f")];
+        headers.extend(mag_headers.zip(phase_headers).flat_map(|(m, p)| [m, p]));
+        let mut res =
+            sim_result::SimResult::new(&headers.iter().map(String::as_str).collect::<Vec<_>>());
+
+        let mut solver = Solver::new();
+
+        for f in ac::frequencies(ac_params) {
+            let omega = 2.0 * std::f64::consts::PI * f;
+            let x_ac = ac::solve(&nodes, &self.elems, &g_mat, &omega, &mut solver)?;
+
+            let mut record = HashMap::from([(String::from("f"), f)]);
+            for (name, node) in nodes.iter() {
+                let v = x_ac[node.idx];
+                record.insert(format!("mag({})", name), v.norm());
+                record.insert(format!("phase({})", name), v.arg().to_degrees());
+            }
+            res.push(record);
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::device::diode::model::Params as DiodeParams;
+    use crate::device::res::Res;
+    use crate::device::vdd::Vdd;
+    use crate::parser::dialect::Dialect;
+    use crate::parser::source::Sources;
+    use crate::parser::{parse_sources_with_rebuild, parse_spice_file};
+
+    #[test]
+    fn test_analyses_in_order() {
+        let (elems, cmds) = parse_spice_file("test/multi_analysis.sp", Dialect::Native).unwrap();
+        let mut engine = Engine::new(elems, cmds);
+
+        let labels = engine
+            .analyses
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [
+                "tran step=1e-9 stop=4e-8",
+                "dc V01 start=0 stop=3 step=1",
+                "op",
+                "dc V01 start=0 stop=1 step=0.5",
+                "tran step=1e-9 stop=2e-8 start=1e-8",
+            ]
+        );
+
+        for analysis in engine.analyses.clone().iter() {
+            assert!(engine.run(analysis).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_tran_no_sliver_before_breakpoint() {
+        // The step landing on each source corner is no shorter than half the one before
+        for file in ["test/rc_pulse.sp", "test/multi_analysis.sp"] {
+            let (elems, cmds) = parse_spice_file(file, Dialect::Native).unwrap();
+            let mut engine = Engine::new(elems, cmds);
+
+            let tran = engine.analyses[0].clone();
+            let times = engine.run(&tran).unwrap().get("t");
+
+            let mut corner = transient::next_breakpoint(&engine.elems, &0.0, &1e-9);
+            while let Some(c) = corner.filter(|c| *c < times[times.len() - 1]) {
+                let i = times.iter().position(|t| *t >= c - 1e-17).unwrap();
+                let (step_in, step_before) = (times[i] - times[i - 1], times[i - 1] - times[i - 2]);
+                assert!(step_in > 0.5 * step_before, "{} at {}", file, c);
+                corner = transient::next_breakpoint(&engine.elems, &c, &1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_settings_keep_old_results() {
+        // Reference results from before the solver settings could be changed. The
+        // junction limiting takes the NPN 42 Newton iterations instead of 26, and
+        // only moves its solution within the convergence tolerances.
+        let (elems, cmds) = parse_spice_file("test/npn_test.sp", Dialect::Native).unwrap();
+        let res = Engine::new(elems, cmds).run_op().unwrap();
+        assert_eq!(res.get("n_iters")[0], 42.0);
+        assert!((res.get("3")[0] - 0.11212054153535668).abs() < 1e-6);
+        assert!((res.get("4")[0] - 0.7087901806474356).abs() < 1e-6);
+        assert!((res.get("V02")[0] + 0.004512311654011617).abs() < 1e-9);
+
+        let (elems, cmds) = parse_spice_file("test/rc_sine.sp", Dialect::Native).unwrap();
+        let mut engine = Engine::new(elems, cmds);
+        let tran = engine.analyses[0].clone();
+        let res = engine.run(&tran).unwrap();
+        assert_eq!(res.get("t").len(), 218);
+        assert!((res.get("2")[217] + 0.4487944432730129).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_damping() {
+        // Damped Newton needs the fallbacks for these, without damping it's enough
+        for (file, strategy) in [
+            ("test/d_gmin.sp", Strategy::GminStepping),
+            ("test/v_divider_hv.sp", Strategy::SourceStepping),
+        ] {
+            let (elems, cmds) = parse_spice_file(file, Dialect::Native).unwrap();
+            let mut engine = Engine::new(elems, cmds);
+            assert!(engine.settings.damping);
+
+            let damped = engine.run_op().unwrap();
+            assert_eq!(engine.op_strategy, Some(strategy));
+
+            engine.settings.damping = false;
+            let undamped = engine.run_op().unwrap();
+            assert_eq!(engine.op_strategy, Some(Strategy::Newton));
+            assert!(undamped.get("n_iters")[0] < damped.get("n_iters")[0]);
+            assert!((undamped.get("2")[0] - damped.get("2")[0]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_gmin_shunt_only_when_set() {
+        let elems: Vec<Box<dyn Stamp>> = vec![
+            Box::new(Vdd {
+                name: String::from("V1"),
+                nodes: vec![String::from("0"), String::from("1")],
+                val: 1.0,
+                ac_mag: 0.0,
+                ac_phase: 0.0,
+                tran_fn: None,
+            }),
+            Box::new(Res {
+                name: String::from("R1"),
+                nodes: vec![String::from("1"), String::from("2")],
+                val: 1e12,
+            }),
+            Box::new(Res {
+                name: String::from("R2"),
+                nodes: vec![String::from("2"), String::from("0")],
+                val: 1e12,
+            }),
+        ];
+        let mut engine = Engine::new(elems, vec![command::Command::Op]);
+
+        assert!((engine.run_op().unwrap().get("2")[0] - 0.5).abs() < 1e-9);
+
+        engine.settings.gmin = 1e-12;
+        assert!((engine.run_op().unwrap().get("2")[0] - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tran_leaves_sources_at_start() {
+        let (elems, cmds) = parse_spice_file("test/multi_analysis.sp", Dialect::Native).unwrap();
+        let mut engine = Engine::new(elems, cmds);
+
+        let tran = engine.analyses[0].clone();
+        let first = engine.run(&tran).unwrap();
+        let second = engine.run(&tran).unwrap();
+
+        assert_eq!(first.get("2"), second.get("2"));
+        assert_eq!(engine.run_op().unwrap().get("1")[0], 0.0);
+    }
+
+    #[test]
+    fn test_dc_nested_sweep() {
+        let (elems, cmds) = parse_spice_file("test/nmos_iv.sp", Dialect::Native).unwrap();
+        let mut engine = Engine::new(elems, cmds);
+
+        let dc = engine.analyses[0].clone();
+        let res = engine.run(&dc).unwrap();
+
+        // Both endpoints included, VDS changing fastest
+        let vds = res.get("sweep(VDS)");
+        let vgs = res.get("sweep(VGS)");
+        assert_eq!(vds.len(), 11 * 4);
+        assert_eq!((vds[10], vgs[10]), (5.0, 0.0));
+        assert_eq!((vds[11], vgs[11]), (0.0, 1.0));
+        assert!((&res.get("2") - &vgs).iter().all(|d| d.abs() < 1e-9));
+
+        // Drain current only flows with the gate above threshold
+        let id = res.get("VDS");
+        assert!(id[10].abs() < 1e-9);
+        assert!(id[43] < id[32]);
+
+        assert_eq!(engine.elems[0].get_value(), Some(0.0));
+        assert_eq!(engine.elems[1].get_value(), Some(0.0));
+    }
+
+    #[test]
+    fn test_dc_temp_param_sweep() {
+        let sources = Sources::load("test/d_temp_sweep.sp", Dialect::Native).unwrap();
+        let ((elems, cmds), rebuild) = parse_sources_with_rebuild(&sources).unwrap();
+        let mut engine = Engine::new(elems, cmds);
+        engine.rebuild = Some(rebuild);
+
+        let before = engine.run_op().unwrap();
+        let dc = engine.analyses[0].clone();
+        let res = engine.run(&dc).unwrap();
+
+        assert_eq!(
+            res.get("sweep(rbias)"),
+            array![1e3, 1e3, 1e3, 1e3, 1e3, 1e4, 1e4, 1e4, 1e4, 1e4, 1e5, 1e5, 1e5, 1e5, 1e5]
+        );
+
+        // The forward voltage drops with temperature and with the bias current
+        let vd = res.get("2");
+        assert!(vd
+            .windows(2)
+            .into_iter()
+            .enumerate()
+            .all(|(i, w)| i % 5 == 4 || w[1] < w[0]));
+        assert!(vd[5] < vd[0]);
+
+        // Back to the netlist as written
+        assert_eq!(engine.elems[1].get_value(), Some(1e3));
+        assert_eq!(engine.run_op().unwrap().get("2"), before.get("2"));
+    }
+
+    #[test]
+    fn test_dc_param_rebuild_error() {
+        let sources = Sources::load("test/dc_param_bad.sp", Dialect::Native).unwrap();
+        let ((elems, cmds), rebuild) = parse_sources_with_rebuild(&sources).unwrap();
+        let mut engine = Engine::new(elems, cmds);
+        engine.rebuild = Some(rebuild);
+
+        let dc = engine.analyses[0].clone();
+        let err = engine.run(&dc).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Netlist can't be rebuilt: test/dc_param_bad.sp:5: PULSE period must be positive, got 0"
+        );
+        assert!(engine.run_op().is_ok());
+    }
+
+    #[test]
+    fn test_dc_param_sweep_circuit() {
+        fn divider(params: &HashMap<String, f64>) -> Circuit {
+            Circuit::new()
+                .vsource("V1", "1", "0", 1.0)
+                .resistor("R1", "1", "2", 1e3)
+                .resistor("R2", "2", "0", params.get("r2").copied().unwrap_or(1e3))
+        }
+        let sweep = command::Command::DC(command::DCParams {
+            sweeps: vec![command::DCSweep {
+                var: command::SweepVar::Param(String::from("r2")),
+                values: command::SweepValues::Step {
+                    start: 1e3,
+                    stop: 3e3,
+                    step: 1e3,
+                },
+            }],
+        });
+
+        let mut engine = divider(&HashMap::new()).command(sweep.clone()).engine();
+        let err = engine.run(&sweep).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid parameter: param r2 can't be swept without rebuilding the netlist"
+        );
+
+        let mut engine = engine.with_rebuild(|params| Ok(divider(params).build().0));
+        let v2 = engine.run(&sweep).unwrap().get("2");
+        let expected = array![0.5, 2.0 / 3.0, 0.75];
+        assert!((&v2 - &expected).iter().all(|d| d.abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_dc_unknown_sweep_source() {
+        let mut engine = Circuit::new()
+            .vsource("V1", "1", "0", 1.0)
+            .resistor("R1", "1", "0", 1e3)
+            .dc("V9", 0.0, 1.0, 0.5)
+            .engine();
+
+        let dc = engine.analyses[0].clone();
+        let err = engine.run(&dc).err().unwrap();
+
+        assert_eq!(err, SimError::UnknownSweepSource(String::from("V9")));
+    }
+
+    #[test]
+    fn test_dc_sweep_without_value() {
+        let mut engine = Circuit::new()
+            .vsource("V1", "1", "0", 1.0)
+            .diode("D1", "0", "1", DiodeParams::default())
+            .dc("D1", 0.0, 1.0, 0.5)
+            .engine();
+
+        let dc = engine.analyses[0].clone();
+        let err = engine.run(&dc).err().unwrap();
+
+        assert_eq!(
+            err,
+            SimError::InvalidParameter(String::from("D1 has no value to sweep"))
+        );
+    }
+
+    #[test]
+    fn test_op_not_converged() {
+        let mut engine = Circuit::new()
+            .vsource("V1", "1", "0", 5.0)
+            .resistor("R1", "1", "2", 1e3)
+            .diode("D1", "0", "2", DiodeParams::default())
+            .op()
+            .engine();
+        engine.settings.itl1 = 1;
+        engine.settings.gmin_steps = 0;
+        engine.settings.src_steps = 0;
+
+        let err = engine.run(&command::Command::Op).err().unwrap();
+
+        match err {
+            SimError::NotConverged {
+                analysis,
+                time,
+                worst_nodes,
+                n_iters,
+            } => {
+                assert_eq!(analysis, "op");
+                assert_eq!(time, None);
+                assert_eq!(n_iters, 1);
+                assert_eq!(worst_nodes[0].0, "1");
+            }
+            _ => panic!("Error is not NotConverged"),
+        }
+    }
+
+    #[test]
+    fn test_op_diverged() {
+        // The current through V1 overflows on the first iteration, and the
+        // voltage solved from it
+        let mut engine = Circuit::new()
+            .vsource("V1", "1", "0", 1e300)
+            .resistor("R1", "1", "0", 1e-10)
+            .op()
+            .engine();
+
+        let err = engine.run(&command::Command::Op).err().unwrap();
+
+        assert_eq!(
+            err,
+            SimError::Diverged {
+                analysis: String::from("op"),
+                time: None,
+                node: String::from("1"),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "op diverged, 1 is no longer a finite number"
+        );
+    }
+
+    #[test]
+    fn test_op_singular_branch() {
+        let (elems, cmds) = parse_spice_file("test/v_loop.sp", Dialect::Native).unwrap();
+        let mut engine = Engine::new(elems, cmds);
+
+        let err = engine.run(&command::Command::Op).err().unwrap();
+
+        assert!(matches!(
+            err,
+            SimError::SingularMatrix { ref node, branch: true, .. } if node == "V1" || node == "V2"
+        ));
+    }
+
+    #[test]
+    fn test_op_singular_floating_node() {
+        let (elems, cmds) = parse_spice_file("test/floating_node.sp", Dialect::Native).unwrap();
+        let mut engine = Engine::new(elems, cmds);
+
+        let err = engine.run(&command::Command::Op).err().unwrap();
+
+        assert_eq!(
+            err,
+            SimError::SingularMatrix {
+                analysis: String::from("op"),
+                node: String::from("3"),
+                branch: false,
+            }
+        );
+
+        // A shunt on every node gives it one
+        engine.settings.gmin = 1e-12;
+        assert!(engine.run(&command::Command::Op).is_ok());
+    }
+
+    #[test]
+    fn test_probe_op_dc() {
+        let (elems, cmds) = parse_spice_file("test/probe.sp", Dialect::Native).unwrap();
+        let mut engine = Engine::new(elems, cmds);
+
+        for analysis in engine.analyses.clone().iter() {
+            let res = engine.run(analysis).unwrap();
+
+            let ic = res.get("I(Q310,c)");
+            let ib = res.get("I(Q310,b)");
+            let ie = res.get("I(Q310,e)");
+            assert!(res.headers().ends_with(&[
+                String::from("I(R23)"),
+                String::from("I(V02)"),
+                String::from("I(Q310,c)"),
+                String::from("I(Q310,b)"),
+                String::from("I(Q310,e)"),
+            ]));
+            // Node currents only balance to within the Newton tolerance
+            for k in 0..ic.len() {
+                assert!((ic[k] + ib[k] + ie[k]).abs() < 1e-9);
+                assert!((res.get("I(R23)")[k] - ic[k]).abs() < 1e-6);
+                assert!((res.get("I(V02)")[k] + ic[k]).abs() < 1e-6);
+                assert!((res.get("V01")[k] + ib[k]).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_probe_tran() {
+        let (elems, cmds) = parse_spice_file("test/probe_tran.sp", Dialect::Native).unwrap();
+        let mut engine = Engine::new(elems, cmds);
+
+        let tran = engine.analyses[0].clone();
+        let res = engine.run(&tran).unwrap();
+
+        let i_r = res.get("I(R12)");
+        let i_l = res.get("I(L23)");
+        let i_c = res.get("I(C30)");
+        let i_d = res.get("I(D30)");
+        assert_eq!(i_r.len(), 21);
+        assert!(i_l.iter().any(|i| *i > 1e-2));
+        assert!(i_d.iter().any(|i| *i > 1e-2));
+        for k in 0..i_r.len() {
+            assert!((res.get("I(V01)")[k] + i_r[k]).abs() < 1e-9);
+            assert!((i_r[k] - i_l[k]).abs() < 1e-6);
+            assert!((i_l[k] - i_c[k] - i_d[k]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_probe_unknown_terminal() {
+        let mut engine = Circuit::new()
+            .vsource("V1", "1", "0", 1.0)
+            .resistor("R1", "1", "0", 1e3)
+            .probe("R1", Some("c"))
+            .op()
+            .engine();
+
+        let err = engine.run(&command::Command::Op).err().unwrap();
+
+        assert_eq!(
+            err,
+            SimError::InvalidParameter(String::from("I(R1,c) doesn't name a terminal of R1"))
+        );
+    }
 }
@@ -0,0 +1,120 @@
+use ndarray::prelude::*;
+use num_complex::Complex64;
+
+use crate::command::ACParams;
+use crate::device::Stamp;
+use crate::engine::error::SimError;
+use crate::engine::sparse_lu::Solver;
+use crate::engine::sweep;
+use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
+
+pub fn frequencies(params: &ACParams) -> Vec<f64> {
+    sweep::points(params.sweep, params.points, params.start, params.stop)
+}
+
+// Solves the small-signal circuit at `omega`, given the conductances of the
+// circuit linearized around its operating point
+pub fn solve(
+    nodes: &NodeCollection,
+    elems: &[Box<dyn Stamp>],
+    g_mat: &SparseMatrix<f64>,
+    omega: &f64,
+    solver: &mut Solver<Complex64>,
+) -> Result<Array1<Complex64>, SimError> {
+    let mut a = g_mat.map(|g| Complex64::new(g, 0.0));
+    let mut b = Array1::zeros(nodes.len());
+    let mut x = Array1::zeros(nodes.len());
+
+    for elem in elems.iter() {
+        elem.ac_stamp(nodes, omega, &mut a, &mut b);
+    }
+
+    solver
+        .solve(&a, &b, &mut x)
+        .map_err(|idx| SimError::singular(nodes, idx))?;
+
+    Ok(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::SweepType;
+    use crate::device::{cap::Cap, res::Res, vdd::Vdd};
+
+    fn params(sweep: SweepType, points: usize, start: f64, stop: f64) -> ACParams {
+        ACParams {
+            sweep,
+            points,
+            start,
+            stop,
+        }
+    }
+
+    #[test]
+    fn test_frequencies_dec() {
+        let f = frequencies(&params(SweepType::Dec, 10, 1.0, 1e3));
+
+        assert_eq!(f.len(), 31);
+        assert_eq!(f[0], 1.0);
+        assert!((f[10] - 10.0).abs() < 1e-12);
+        assert!((f[30] - 1e3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_frequencies_oct() {
+        let f = frequencies(&params(SweepType::Oct, 1, 1.0, 10.0));
+
+        assert_eq!(f, [1.0, 2.0, 4.0, 8.0]);
+    }
+
+    #[test]
+    fn test_frequencies_lin() {
+        let f = frequencies(&params(SweepType::Lin, 5, 100.0, 500.0));
+
+        assert_eq!(f, [100.0, 200.0, 300.0, 400.0, 500.0]);
+        assert_eq!(frequencies(&params(SweepType::Lin, 1, 1.0, 5.0)), [1.0]);
+    }
+
+    #[test]
+    fn test_solve_rc_lowpass() {
+        let elems: Vec<Box<dyn Stamp>> = vec![
+            Box::new(Vdd {
+                name: String::from("V1"),
+                nodes: vec![String::from("0"), String::from("in")],
+                val: 0.0,
+                ac_mag: 1.0,
+                ac_phase: 0.0,
+                tran_fn: None,
+            }),
+            Box::new(Res {
+                name: String::from("R1"),
+                nodes: vec![String::from("in"), String::from("out")],
+                val: 1e3,
+            }),
+            Box::new(Cap {
+                name: String::from("C1"),
+                nodes: vec![String::from("0"), String::from("out")],
+                val: 1e-6,
+                u_curr: None,
+                i_curr: None,
+                u_prev: None,
+                ic: None,
+            }),
+        ];
+        let nodes = NodeCollection::from_elems(&elems);
+        let mut g_mat = SparseMatrix::zeros((nodes.len(), nodes.len()));
+        let mut b = Array1::zeros(nodes.len());
+        for elem in elems.iter() {
+            elem.linear_stamp(&nodes, &mut g_mat, &mut b);
+        }
+
+        // Corner frequency, 1 / (R C)
+        let x = solve(&nodes, &elems, &g_mat, &1e3, &mut Solver::new()).unwrap();
+        let out = x[nodes.get_idx("out").unwrap()];
+
+        assert!((out.norm() - 0.5_f64.sqrt()).abs() < 1e-12);
+        assert!((out.arg().to_degrees() + 45.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+
+use ndarray::prelude::*;
+
+use crate::device::Stamp;
+use crate::engine::error::SimError;
+use crate::engine::mna::{self, MNA};
+use crate::engine::newtons_method;
+use crate::engine::settings::Settings;
+use crate::node_collection::NodeCollection;
+
+// Extra conductance to ground on every node, decreasing geometrically
+const GMIN_START: f64 = 1e-2;
+const GMIN_STOP: f64 = 1e-12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Newton,
+    GminStepping,
+    SourceStepping,
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Strategy::Newton => write!(f, "Newton's method"),
+            Strategy::GminStepping => write!(f, "gmin stepping"),
+            Strategy::SourceStepping => write!(f, "source stepping"),
+        }
+    }
+}
+
+// Plain Newton first, then each continuation method that's enabled. They all
+// start over from x = 0 and return the total number of Newton iterations.
+pub fn solve(
+    nodes: &NodeCollection,
+    elems: &mut Vec<Box<dyn Stamp>>,
+    x: &mut Array1<f64>,
+    mna: &mut MNA,
+    settings: &Settings,
+) -> Result<(u64, Strategy), SimError> {
+    // The error of the last method tried is reported. Matrices can also look
+    // singular when a wild Newton step makes some conductances huge, so those are
+    // retried like any other failure.
+    let mut err = match newtons_method::solve(nodes, elems, x, mna, settings, settings.itl1) {
+        Ok(n_iters) => return Ok((n_iters, Strategy::Newton)),
+        Err(e) => e,
+    };
+
+    if settings.gmin_steps > 0 {
+        x.fill(0.0);
+        match gmin_stepping(nodes, elems, x, mna, settings) {
+            Ok(n_iters) => return Ok((n_iters, Strategy::GminStepping)),
+            Err(e) => err = e,
+        }
+    }
+
+    if settings.src_steps > 0 {
+        x.fill(0.0);
+        match source_stepping(nodes, elems, x, mna, settings) {
+            Ok(n_iters) => return Ok((n_iters, Strategy::SourceStepping)),
+            Err(e) => err = e,
+        }
+    }
+
+    Err(err)
+}
+
+fn gmin_stepping(
+    nodes: &NodeCollection,
+    elems: &mut Vec<Box<dyn Stamp>>,
+    x: &mut Array1<f64>,
+    mna: &mut MNA,
+    settings: &Settings,
+) -> Result<u64, SimError> {
+    let a_bkp = mna.a.clone();
+    let steps = settings.gmin_steps;
+
+    let res = (0..steps).try_fold(0, |n_iters, k| {
+        let gmin = GMIN_START * (GMIN_STOP / GMIN_START).powf(k as f64 / steps as f64);
+
+        mna.a = a_bkp.clone();
+        mna::stamp_shunts(&mut mna.a, nodes, gmin);
+
+        Ok(n_iters + newtons_method::solve(nodes, elems, x, mna, settings, settings.itl1)?)
+    });
+
+    mna.a = a_bkp;
+
+    // The last solve is the actual circuit, without any shunts
+    Ok(res? + newtons_method::solve(nodes, elems, x, mna, settings, settings.itl1)?)
+}
+
+fn source_stepping(
+    nodes: &NodeCollection,
+    elems: &mut Vec<Box<dyn Stamp>>,
+    x: &mut Array1<f64>,
+    mna: &mut MNA,
+    settings: &Settings,
+) -> Result<u64, SimError> {
+    let a_bkp = mna.a.clone();
+    let b_bkp = mna.b.clone();
+    let steps = settings.src_steps;
+    let sources = elems
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.is_source())
+        .filter_map(|(i, e)| e.get_value().map(|val| (i, val)))
+        .collect::<Vec<_>>();
+
+    // With every source at zero the solution is x = 0, ramp them up from there
+    let res = (1..=steps).try_fold(0, |n_iters, k| {
+        let scale = k as f64 / steps as f64;
+
+        for &(i, val) in sources.iter() {
+            elems[i].undo_linear_stamp(nodes, &mut mna.a, &mut mna.b);
+            elems[i].set_value(scale * val);
+            elems[i].linear_stamp(nodes, &mut mna.a, &mut mna.b);
+        }
+
+        Ok(n_iters + newtons_method::solve(nodes, elems, x, mna, settings, settings.itl1)?)
+    });
+
+    for &(i, val) in sources.iter() {
+        elems[i].set_value(val);
+    }
+    mna.a = a_bkp;
+    mna.b = b_bkp;
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::diode::{self, Diode};
+    use crate::device::idd::Idd;
+    use crate::device::res::Res;
+    use crate::device::vdd::Vdd;
+
+    fn res(name: &str, nodes: [&str; 2], val: f64) -> Box<dyn Stamp> {
+        Box::new(Res {
+            name: String::from(name),
+            nodes: nodes.map(String::from).to_vec(),
+            val,
+        })
+    }
+
+    fn diode(name: &str, nodes: [&str; 2]) -> Box<dyn Stamp> {
+        Box::new(Diode {
+            name: String::from(name),
+            nodes: nodes.map(String::from).to_vec(),
+            params: diode::model::Params {
+                is: 1e-12,
+                ..Default::default()
+            },
+            vd_old: 0.0,
+        })
+    }
+
+    // Damped Newton steps are too short to reach 500 V in itl1 iterations, and the
+    // source holds node 1 at 1 kV whatever the shunts are
+    fn high_voltage_divider() -> Vec<Box<dyn Stamp>> {
+        vec![
+            Box::new(Vdd {
+                name: String::from("V1"),
+                nodes: vec![String::from("0"), String::from("1")],
+                val: 1000.0,
+                ac_mag: 0.0,
+                ac_phase: 0.0,
+                tran_fn: None,
+            }),
+            res("R1", ["2", "1"], 1e3),
+            res("R2", ["0", "2"], 1e3),
+        ]
+    }
+
+    // 100 mA forced through a diode in series with 1 Ohm, with a reverse
+    // biased diode across both
+    fn diode_clamp() -> Vec<Box<dyn Stamp>> {
+        vec![
+            Box::new(Idd {
+                name: String::from("I1"),
+                nodes: vec![String::from("1"), String::from("0")],
+                val: 0.1,
+                ac_mag: 0.0,
+                ac_phase: 0.0,
+                tran_fn: None,
+            }),
+            diode("D1", ["1", "0"]),
+            diode("D2", ["2", "1"]),
+            res("R2", ["0", "2"], 1.0),
+        ]
+    }
+
+    fn run(
+        elems: &mut Vec<Box<dyn Stamp>>,
+        gmin_steps: usize,
+        src_steps: usize,
+    ) -> Result<(Strategy, Array1<f64>), SimError> {
+        let nodes = NodeCollection::from_startup_elems(elems);
+        let num_nonlinear_funcs = elems.iter().map(|e| e.count_nonlinear_funcs()).sum();
+        let mut mna = MNA::new(nodes.len(), num_nonlinear_funcs);
+        for elem in elems.iter() {
+            elem.linear_startup_stamp(&nodes, &mut mna.a, &mut mna.b);
+            elem.nonlinear_funcs(&nodes, &mut mna.h, &mut mna.g);
+        }
+
+        let mut x = mna.get_x();
+        let settings = Settings {
+            gmin_steps,
+            src_steps,
+            damping: true,
+            ..Settings::default()
+        };
+        let (_, strategy) = solve(&nodes, elems, &mut x, &mut mna, &settings)?;
+
+        // Whatever found it, it has to solve the original circuit
+        assert!(mna.get_err(&x).iter().all(|e| e.abs() < 1e-6));
+
+        Ok((strategy, x))
+    }
+
+    #[test]
+    fn test_newton_first() {
+        let mut elems = high_voltage_divider();
+        elems[0].set_value(1.0);
+
+        let (strategy, _) = run(&mut elems, 10, 10).unwrap();
+
+        assert_eq!(strategy, Strategy::Newton);
+    }
+
+    #[test]
+    fn test_gmin_stepping() {
+        let mut elems = diode_clamp();
+
+        let (strategy, _) = run(&mut elems, 10, 10).unwrap();
+
+        assert_eq!(strategy, Strategy::GminStepping);
+    }
+
+    #[test]
+    fn test_source_stepping() {
+        let mut elems = high_voltage_divider();
+
+        let (strategy, x) = run(&mut elems, 10, 100).unwrap();
+
+        assert_eq!(strategy, Strategy::SourceStepping);
+        assert!(x.iter().any(|v| (v - 500.0).abs() < 1e-3));
+        assert_eq!(elems[0].get_value(), Some(1000.0));
+    }
+
+    #[test]
+    fn test_all_disabled() {
+        let mut elems = diode_clamp();
+
+        assert!(run(&mut elems, 0, 0).is_err());
+    }
+}
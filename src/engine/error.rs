@@ -1,10 +1,236 @@
 use std::fmt;
 
-#[derive(Debug, Clone)]
-pub struct NotConvergedError;
+use ndarray::Array1;
 
-impl fmt::Display for NotConvergedError {
+use crate::node::NodeType;
+use crate::node_collection::NodeCollection;
+
+// Nodes listed with a non-convergence error, the ones that moved the most
+const WORST_NODES: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimError {
+    // Newton's method ran out of iterations. `worst_nodes` are the unknowns with
+    // the largest last step, node voltages or source branch currents.
+    NotConverged {
+        analysis: String,
+        time: Option<f64>,
+        worst_nodes: Vec<(String, f64)>,
+        n_iters: u64,
+    },
+    // A Newton iteration gave an update or residual that isn't a number, `node`
+    // is the first unknown it happened for
+    Diverged {
+        analysis: String,
+        time: Option<f64>,
+        node: String,
+    },
+    // No unique solution, `node` is the unknown that couldn't be pivoted on. A
+    // branch current for voltage sources and inductors, a node voltage otherwise.
+    SingularMatrix {
+        analysis: String,
+        node: String,
+        branch: bool,
+    },
+    // The transient step was halved below the minimum without converging
+    TimestepTooSmall {
+        analysis: String,
+        time: f64,
+    },
+    UnknownSweepSource(String),
+    InvalidParameter(String),
+    // The netlist built again with swept `.param` values has errors
+    Rebuild(Vec<String>),
+    // Something the engine should never get into, whatever the circuit
+    Internal(String),
+}
+
+impl SimError {
+    pub fn not_converged(worst_nodes: Vec<(String, f64)>, n_iters: u64) -> Self {
+        let mut worst_nodes = worst_nodes;
+        worst_nodes.sort_by(|(_, a), (_, b)| b.abs().total_cmp(&a.abs()));
+        worst_nodes.truncate(WORST_NODES);
+
+        SimError::NotConverged {
+            analysis: String::new(),
+            time: None,
+            worst_nodes,
+            n_iters,
+        }
+    }
+
+    // `vec` has an entry for every unknown, at least one of them not finite
+    pub fn diverged(nodes: &NodeCollection, vec: &Array1<f64>) -> Self {
+        let node = nodes
+            .iter()
+            .filter(|(_, node)| !vec[node.idx].is_finite())
+            .min_by_key(|(_, node)| node.idx)
+            .map_or_else(String::new, |(name, _)| name.clone());
+
+        SimError::Diverged {
+            analysis: String::new(),
+            time: None,
+            node,
+        }
+    }
+
+    pub fn singular(nodes: &NodeCollection, idx: usize) -> Self {
+        match nodes.iter().find(|(_, node)| node.idx == idx) {
+            Some((name, node)) => SimError::SingularMatrix {
+                analysis: String::new(),
+                node: name.clone(),
+                branch: matches!(node.ntype, NodeType::Current),
+            },
+            None => SimError::Internal(format!("singular column {} is not a node", idx)),
+        }
+    }
+
+    // Sets the analysis unless it's already known, like `ParseError::in_file`
+    pub fn in_analysis(mut self, label: &str) -> Self {
+        match &mut self {
+            SimError::NotConverged { analysis, .. }
+            | SimError::Diverged { analysis, .. }
+            | SimError::SingularMatrix { analysis, .. }
+            | SimError::TimestepTooSmall { analysis, .. }
+                if analysis.is_empty() =>
+            {
+                *analysis = String::from(label)
+            }
+            _ => (),
+        }
+        self
+    }
+
+    pub fn at_time(mut self, t: f64) -> Self {
+        if let SimError::NotConverged { time, .. } | SimError::Diverged { time, .. } = &mut self {
+            time.get_or_insert(t);
+        }
+        self
+    }
+}
+
+impl fmt::Display for SimError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Couldn't converge simulation.")
+        match self {
+            SimError::NotConverged {
+                analysis,
+                time,
+                worst_nodes,
+                n_iters,
+            } => {
+                write!(f, "{} didn't converge", analysis)?;
+                if let Some(t) = time {
+                    write!(f, " at t={}", t)?;
+                }
+                write!(f, " after {} iterations", n_iters)?;
+                if !worst_nodes.is_empty() {
+                    let nodes = worst_nodes
+                        .iter()
+                        .map(|(name, step)| format!("{} (step {:e})", name, step))
+                        .collect::<Vec<_>>();
+                    write!(f, ", worst nodes: {}", nodes.join(", "))?;
+                }
+                Ok(())
+            }
+            SimError::Diverged {
+                analysis,
+                time,
+                node,
+            } => {
+                write!(f, "{} diverged", analysis)?;
+                if let Some(t) = time {
+                    write!(f, " at t={}", t)?;
+                }
+                write!(f, ", {} is no longer a finite number", node)
+            }
+            SimError::SingularMatrix {
+                analysis,
+                node,
+                branch: false,
+            } => write!(
+                f,
+                "{} has a singular matrix at node {}, check that it has a DC path to ground",
+                analysis, node
+            ),
+            SimError::SingularMatrix {
+                analysis,
+                node,
+                branch: true,
+            } => write!(
+                f,
+                "{} has a singular matrix at the current through {}, check for a loop of voltage sources or inductors",
+                analysis, node
+            ),
+            SimError::TimestepTooSmall { analysis, time } => {
+                write!(f, "{} timestep too small at t={}", analysis, time)
+            }
+            SimError::UnknownSweepSource(name) => write!(f, "Sweep source {} not found", name),
+            SimError::InvalidParameter(msg) => write!(f, "Invalid parameter: {}", msg),
+            SimError::Rebuild(errors) => {
+                write!(f, "Netlist can't be rebuilt: {}", errors.join(", "))
+            }
+            SimError::Internal(msg) => write!(f, "Internal error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SimError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_not_converged_worst_nodes() {
+        let err = SimError::not_converged(
+            vec![
+                (String::from("1"), 1e-3),
+                (String::from("2"), -2.0),
+                (String::from("V1"), 0.5),
+                (String::from("3"), 1e-9),
+            ],
+            100,
+        );
+
+        if let SimError::NotConverged { worst_nodes, .. } = &err {
+            let names = worst_nodes
+                .iter()
+                .map(|(n, _)| n.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(names, ["2", "V1", "1"]);
+        } else {
+            panic!("Error is not NotConverged");
+        }
+    }
+
+    #[test]
+    fn test_singular_not_a_node() {
+        let (elems, _) = crate::circuit::Circuit::new()
+            .vsource("V1", "1", "0", 1.0)
+            .resistor("R1", "1", "0", 1e3)
+            .build();
+        let nodes = NodeCollection::from_elems(&elems);
+
+        assert!(matches!(
+            SimError::singular(&nodes, 1),
+            SimError::SingularMatrix { branch: true, .. }
+        ));
+        assert_eq!(
+            SimError::singular(&nodes, 7).to_string(),
+            "Internal error: singular column 7 is not a node"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let err = SimError::not_converged(vec![(String::from("out"), 0.25)], 50)
+            .at_time(1e-6)
+            .in_analysis("tran step=1e-9 stop=4e-6")
+            .in_analysis("op");
+
+        assert_eq!(
+            err.to_string(),
+            "tran step=1e-9 stop=4e-6 didn't converge at t=0.000001 after 50 iterations, worst nodes: out (step 2.5e-1)"
+        );
     }
 }
@@ -1,21 +1,53 @@
 use ndarray::prelude::*;
 
+use crate::device::NonlinearFn;
+use crate::engine::sparse_lu::Solver;
+use crate::node::NodeType;
+use crate::node_collection::NodeCollection;
+use crate::sparse::SparseMatrix;
+
 // MNA Equation matrices
 pub struct MNA {
-    pub a: Array2<f64>,
+    pub a: SparseMatrix<f64>,
     pub b: Array1<f64>,
-    pub h: Array2<f64>,
-    pub g: Vec<Box<dyn Fn(&Array1<f64>) -> f64>>,
+    pub h: SparseMatrix<f64>,
+    pub g: Vec<NonlinearFn>,
+    // Keeps the pivots found for `a` while the analysis runs
+    pub solver: Solver<f64>,
+    // `a` with the nonlinear devices stamped on top, kept between Newton
+    // iterations along with how many entries `a` had when it was copied
+    pub jf: SparseMatrix<f64>,
+    jf_base: Option<usize>,
 }
 
 impl MNA {
     pub fn new(num_nodes: usize, num_nonlinear_funcs: usize) -> Self {
         MNA {
-            a: Array2::zeros((num_nodes, num_nodes)),
+            a: SparseMatrix::zeros((num_nodes, num_nodes)),
             b: Array1::zeros(num_nodes),
-            h: Array2::zeros((num_nodes, num_nonlinear_funcs)),
+            h: SparseMatrix::zeros((num_nodes, num_nonlinear_funcs)),
             g: Vec::new(),
+            solver: Solver::new(),
+            jf: SparseMatrix::zeros((num_nodes, num_nodes)),
+            jf_base: None,
+        }
+    }
+
+    // `jf` set back to `a` for the next Newton iteration. Only copied again when
+    // `a` got new entries, the solver has to work out the pattern again then.
+    pub fn jacobian(&mut self) -> &mut SparseMatrix<f64> {
+        match self.jf_base {
+            Some(n) if n == self.a.nnz() => self.jf.reset_to(&self.a),
+            base => {
+                if base.is_some() {
+                    self.solver = Solver::new();
+                }
+                self.jf = self.a.clone();
+                self.jf_base = Some(self.a.nnz());
+            }
         }
+
+        &mut self.jf
     }
 
     pub fn get_x(&self) -> Array1<f64> {
@@ -25,7 +57,16 @@ impl MNA {
     pub fn get_err(&self, x: &Array1<f64>) -> Array1<f64> {
         let g_val = self.g.iter().map(|f| f(x)).collect::<Array1<_>>();
 
-        self.a.dot(&x.t()) + self.h.dot(&g_val.t()) - &self.b
+        self.a.dot(x) + self.h.dot(&g_val) - &self.b
+    }
+}
+
+// Conductance from every node to ground, keeps floating nodes solvable
+pub fn stamp_shunts(a: &mut SparseMatrix<f64>, nodes: &NodeCollection, g: f64) {
+    for node in nodes.values() {
+        if matches!(node.ntype, NodeType::Voltage) {
+            a[(node.idx, node.idx)] += g;
+        }
     }
 }
 
@@ -36,18 +77,42 @@ mod tests {
     #[test]
     fn test_get_err() {
         let x = array![1.0, 2.0];
-        let mna = MNA {
-            a: array![[1.0, 2.0], [3.0, 4.0]],
-            b: array![8.0, 12.5],
-            h: array![[1.0, 0.0], [0.0, 1.0]],
-            g: vec![
-                Box::new(|x: &Array1<f64>| x.iter().sum()),
-                Box::new(|x: &Array1<f64>| x.iter().sum::<f64>() / x.len() as f64),
-            ],
-        };
+        let mut a = SparseMatrix::zeros((2, 2));
+        a[(0, 0)] = 1.0;
+        a[(0, 1)] = 2.0;
+        a[(1, 0)] = 3.0;
+        a[(1, 1)] = 4.0;
+        let mut h = SparseMatrix::zeros((2, 2));
+        h[(0, 0)] = 1.0;
+        h[(1, 1)] = 1.0;
+        let mut mna = MNA::new(2, 2);
+        mna.a = a;
+        mna.b = array![8.0, 12.5];
+        mna.h = h;
+        mna.g = vec![
+            Box::new(|x: &Array1<f64>| x.iter().sum()),
+            Box::new(|x: &Array1<f64>| x.iter().sum::<f64>() / x.len() as f64),
+        ];
 
         let err_vec = mna.get_err(&x);
 
         assert_eq!(err_vec, array![0.0, 0.0]);
     }
+
+    #[test]
+    fn test_jacobian() {
+        let mut mna = MNA::new(2, 0);
+        mna.a[(0, 0)] = 1.0;
+
+        mna.jacobian()[(1, 1)] += 2.0;
+        mna.a[(0, 0)] = 3.0;
+        let jf = mna.jacobian().clone();
+
+        // Set back to `a`, the entry the last iteration added is kept as a zero
+        assert_eq!(jf, array![[3.0, 0.0], [0.0, 0.0]]);
+        assert_eq!(jf.nnz(), 2);
+
+        mna.a[(0, 1)] = 1.0;
+        assert_eq!(mna.jacobian().nnz(), 2);
+    }
 }
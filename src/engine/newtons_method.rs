@@ -1,27 +1,23 @@
 use ndarray::prelude::*;
 
 use crate::device::Stamp;
-use crate::engine::error::NotConvergedError;
-use crate::engine::gauss_lu;
+use crate::engine::error::SimError;
 use crate::engine::mna::MNA;
 use crate::engine::node_vec_norm::NodeVecNorm;
+use crate::engine::settings::Settings;
 use crate::node_collection::NodeCollection;
 
-pub const MAX_ITERS: u64 = 100;
-
-const TOL_REL: f64 = 0.001;
-const TOL_ABS_V: f64 = 1e-6;
-const TOL_ABS_A: f64 = 1e-9;
-
 const DAMPING_GAMMA: f64 = 1.3;
 const DAMPING_K: f64 = 16.0;
 
 pub fn solve(
     nodes: &NodeCollection,
-    elems: &Vec<Box<dyn Stamp>>,
+    elems: &mut Vec<Box<dyn Stamp>>,
     x: &mut Array1<f64>,
-    mna: &MNA,
-) -> Result<u64, NotConvergedError> {
+    mna: &mut MNA,
+    settings: &Settings,
+    max_iters: u64,
+) -> Result<u64, SimError> {
     let mut err = NodeVecNorm::infty();
     let mut step = NodeVecNorm::infty();
 
@@ -30,29 +26,45 @@ pub fn solve(
     let mut step_old = err_old.clone();
 
     let mut n_iters = 0;
+    let mut last_step = Array1::zeros(x.len());
 
-    while n_iters < MAX_ITERS && !converged(&err, &step, &err_old, &step_old) {
-        let mut jf_mat = mna.a.clone();
+    while n_iters < max_iters && !converged(&err, &step, &err_old, &step_old, settings) {
         let mut b_temp = mna.b.clone();
         let mut x_proposed = x.clone();
 
-        for elem in elems.iter() {
-            elem.nonlinear_stamp(&nodes, &x_proposed, &mut jf_mat, &mut b_temp);
+        let jf_mat = mna.jacobian();
+        for elem in elems.iter_mut() {
+            elem.nonlinear_stamp(nodes, &x_proposed, jf_mat, &mut b_temp);
         }
 
-        gauss_lu::solve(&mut jf_mat, &mut b_temp, &mut x_proposed);
+        mna.solver
+            .solve(&mna.jf, &b_temp, &mut x_proposed)
+            .map_err(|idx| SimError::singular(nodes, idx))?;
 
         let step_proposed = &x_proposed.view() - &x.view();
-        let step_taken = dampen_step(&step_proposed);
+        if !step_proposed.iter().all(|v| v.is_finite()) {
+            return Err(SimError::diverged(nodes, &step_proposed));
+        }
+        // Devices limit their own junctions, damping every unknown is optional
+        let step_taken = if settings.damping {
+            dampen_step(&step_proposed)
+        } else {
+            step_proposed
+        };
         step = NodeVecNorm::new(nodes, &step_taken);
+        last_step = step_taken.clone();
 
         let x_new = &x.view() + &step_taken;
 
-        f0 = mna.get_err(&x_new);
-        err = NodeVecNorm::new(nodes, &f0);
-        if err.v.is_infinite() || err.i.is_infinite() {
-            panic!("v_err or i_err diverged");
+        // Undamped steps can overshoot far enough to overflow the device
+        // currents, the junction limiting pulls them back on the next iteration.
+        // Twice in a row and they are diverging.
+        let f_new = mna.get_err(&x_new);
+        if !f_new.iter().all(|v| v.is_finite()) && !f0.iter().all(|v| v.is_finite()) {
+            return Err(SimError::diverged(nodes, &f_new));
         }
+        f0 = f_new;
+        err = NodeVecNorm::new(nodes, &f0);
 
         for i in 0..x_new.len() {
             x[i] = x_new[i];
@@ -63,10 +75,14 @@ pub fn solve(
         step_old = step.clone();
     }
 
-    if n_iters < MAX_ITERS {
+    if n_iters < max_iters {
         Ok(n_iters)
     } else {
-        Err(NotConvergedError)
+        let steps = nodes
+            .iter()
+            .map(|(name, node)| (name.clone(), last_step[node.idx]))
+            .collect();
+        Err(SimError::not_converged(steps, n_iters))
     }
 }
 
@@ -81,11 +97,19 @@ fn converged(
     step: &NodeVecNorm,
     err_old: &NodeVecNorm,
     step_old: &NodeVecNorm,
+    settings: &Settings,
 ) -> bool {
-    step.v < TOL_REL * step_old.v + TOL_ABS_V
-        && step.i < TOL_REL * step_old.i + TOL_ABS_A
-        && err.v < TOL_REL * err_old.v + TOL_ABS_V
-        && err.i < TOL_REL * err_old.i + TOL_ABS_A
+    let Settings {
+        reltol,
+        vntol,
+        abstol,
+        ..
+    } = *settings;
+
+    step.v < reltol * step_old.v + vntol
+        && step.i < reltol * step_old.i + abstol
+        && err.v < reltol * err_old.v + vntol
+        && err.i < reltol * err_old.i + abstol
 }
 
 #[cfg(test)]
@@ -109,7 +133,13 @@ mod tests {
         let err_old = NodeVecNorm { v: 1.0, i: 1.0 };
         let step_old = NodeVecNorm { v: 1.0, i: 1.0 };
 
-        assert!(converged(&err, &step, &err_old, &step_old));
+        assert!(converged(
+            &err,
+            &step,
+            &err_old,
+            &step_old,
+            &Settings::default()
+        ));
     }
 
     #[test]
@@ -119,7 +149,13 @@ mod tests {
         let err_old = NodeVecNorm { v: 1.0, i: 1.0 };
         let step_old = NodeVecNorm { v: 1.0, i: 1.0 };
 
-        assert!(!converged(&err, &step, &err_old, &step_old));
+        assert!(!converged(
+            &err,
+            &step,
+            &err_old,
+            &step_old,
+            &Settings::default()
+        ));
     }
 
     #[test]
@@ -129,7 +165,13 @@ mod tests {
         let err_old = NodeVecNorm { v: 1.0, i: 1.0 };
         let step_old = NodeVecNorm { v: 1.0, i: 1.0 };
 
-        assert!(!converged(&err, &step, &err_old, &step_old));
+        assert!(!converged(
+            &err,
+            &step,
+            &err_old,
+            &step_old,
+            &Settings::default()
+        ));
     }
 
     #[test]
@@ -139,7 +181,13 @@ mod tests {
         let err_old = NodeVecNorm { v: 1.0, i: 1.0 };
         let step_old = NodeVecNorm { v: 1.0, i: 1.0 };
 
-        assert!(!converged(&err, &step, &err_old, &step_old));
+        assert!(!converged(
+            &err,
+            &step,
+            &err_old,
+            &step_old,
+            &Settings::default()
+        ));
     }
 
     #[test]
@@ -149,6 +197,12 @@ mod tests {
         let err_old = NodeVecNorm { v: 1.0, i: 1.0 };
         let step_old = NodeVecNorm { v: 1.0, i: 1.0 };
 
-        assert!(!converged(&err, &step, &err_old, &step_old));
+        assert!(!converged(
+            &err,
+            &step,
+            &err_old,
+            &step_old,
+            &Settings::default()
+        ));
     }
 }
@@ -10,6 +10,11 @@ pub struct NodeVecNorm {
 }
 
 fn norm(v: &Array1<f64>) -> f64 {
+    // Circuits without voltage sources have no current unknowns
+    if v.is_empty() {
+        return 0.0;
+    }
+
     v.mapv(|x| x.powi(2)).sum().sqrt() / v.len() as f64
 }
 
@@ -53,6 +58,13 @@ mod tests {
         assert_eq!(norm(&v), 0.0);
     }
 
+    #[test]
+    fn test_norm_empty() {
+        let v = Array1::<f64>::zeros(0);
+
+        assert_eq!(norm(&v), 0.0);
+    }
+
     #[test]
     fn test_norm_pos() {
         let v = array![1.0, 1.0, 1.0];
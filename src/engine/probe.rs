@@ -0,0 +1,120 @@
+use ndarray::prelude::*;
+
+use crate::command::Probe;
+use crate::device::Stamp;
+use crate::engine::error::SimError;
+use crate::node_collection::NodeCollection;
+
+// A `.probe` resolved to its element and the index into the element's currents
+#[derive(Debug)]
+pub struct Column {
+    pub label: String,
+    elem: usize,
+    idx: usize,
+}
+
+pub fn columns(probes: &[Probe], elems: &[Box<dyn Stamp>]) -> Result<Vec<Column>, SimError> {
+    probes
+        .iter()
+        .map(|p| {
+            let elem = elems
+                .iter()
+                .position(|e| e.get_name() == p.elem)
+                .ok_or_else(|| SimError::InvalidParameter(format!("{} not found", p.elem)))?;
+
+            let terminals = elems[elem].terminals();
+            let idx = match &p.terminal {
+                None if terminals.is_empty() => Some(0),
+                None => None,
+                Some(t) => terminals.iter().position(|x| x == t),
+            }
+            .ok_or_else(|| {
+                let msg = format!("{} doesn't name a terminal of {}", p, p.elem);
+                SimError::InvalidParameter(msg)
+            })?;
+
+            Ok(Column {
+                label: p.to_string(),
+                elem,
+                idx,
+            })
+        })
+        .collect()
+}
+
+// During a transient capacitors and inductors take their currents from their state
+pub fn values(
+    columns: &[Column],
+    elems: &[Box<dyn Stamp>],
+    nodes: &NodeCollection,
+    x: &Array1<f64>,
+    tran: bool,
+) -> Result<Array1<f64>, SimError> {
+    let values = columns
+        .iter()
+        .map(|c| {
+            let elem = &elems[c.elem];
+            let currents = match tran {
+                true => elem.tran_currents(nodes, x),
+                false => elem.currents(nodes, x),
+            };
+
+            currents.map(|i| i[c.idx]).ok_or_else(|| {
+                let msg = format!("{} has no current to probe", elem.get_name());
+                SimError::InvalidParameter(msg)
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Array1::from(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+
+    fn probe(elem: &str, terminal: Option<&str>) -> Probe {
+        Probe {
+            elem: String::from(elem),
+            terminal: terminal.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_columns() {
+        let (elems, _) = Circuit::new()
+            .resistor("R1", "1", "0", 1e3)
+            .npn("Q1", ["1", "2", "0"], Default::default())
+            .build();
+
+        let cols = columns(&[probe("R1", None), probe("Q1", Some("e"))], &elems).unwrap();
+
+        assert_eq!(cols[0].label, "I(R1)");
+        assert_eq!((cols[1].elem, cols[1].idx), (1, 2));
+        assert_eq!(cols[1].label, "I(Q1,e)");
+    }
+
+    #[test]
+    fn test_columns_bad_terminal() {
+        let (elems, _) = Circuit::new()
+            .resistor("R1", "1", "0", 1e3)
+            .npn("Q1", ["1", "2", "0"], Default::default())
+            .build();
+
+        for (p, msg) in [
+            (
+                probe("R1", Some("a")),
+                "Invalid parameter: I(R1,a) doesn't name a terminal of R1",
+            ),
+            (
+                probe("Q1", None),
+                "Invalid parameter: I(Q1) doesn't name a terminal of Q1",
+            ),
+            (probe("R2", None), "Invalid parameter: R2 not found"),
+        ] {
+            let err = columns(&[p], &elems).unwrap_err();
+            assert_eq!(err.to_string(), msg);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::command::OptionsParams;
+use crate::device::temperature::TNOM;
+use crate::integration::Method;
+
+// Solver tolerances and limits. The defaults are the ones the solver always used,
+// `.options` can set them to SPICE's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    // Relative tolerance on node voltages and branch currents
+    pub reltol: f64,
+    // Absolute tolerance on node voltages
+    pub vntol: f64,
+    // Absolute tolerance on branch currents
+    pub abstol: f64,
+    // Newton iteration limit for DC solutions
+    pub itl1: u64,
+    // Newton iteration limit for each transient time point
+    pub itl4: u64,
+    // Scales the truncation error allowed per transient step
+    pub trtol: f64,
+    // Conductance from every node to ground, none by default
+    pub gmin: f64,
+    // Largest transient step, the `.tran` step when not set
+    pub tmax: Option<f64>,
+    pub method: Method,
+    // Operating point fallbacks, 0 turns them off
+    pub gmin_steps: usize,
+    pub src_steps: usize,
+    // Log compression of every Newton step on top of the device limiting
+    pub damping: bool,
+    // Transient results on the uniform `.tran` step grid instead of the time points taken
+    pub interp: bool,
+    // Circuit temperature in degrees Celsius
+    pub temp: f64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            reltol: 1e-3,
+            vntol: 1e-6,
+            abstol: 1e-9,
+            itl1: 100,
+            itl4: 100,
+            trtol: 1.0,
+            gmin: 0.0,
+            tmax: None,
+            method: Method::default(),
+            gmin_steps: 10,
+            src_steps: 10,
+            damping: true,
+            interp: false,
+            temp: TNOM,
+        }
+    }
+}
+
+impl Settings {
+    pub fn apply(&mut self, options: &OptionsParams) {
+        let OptionsParams {
+            reltol,
+            vntol,
+            abstol,
+            itl1,
+            itl4,
+            trtol,
+            gmin,
+            tmax,
+            method,
+            gmin_steps,
+            src_steps,
+            damping,
+            interp,
+            temp,
+        } = options.clone();
+
+        self.reltol = reltol.unwrap_or(self.reltol);
+        self.vntol = vntol.unwrap_or(self.vntol);
+        self.abstol = abstol.unwrap_or(self.abstol);
+        self.itl1 = itl1.unwrap_or(self.itl1);
+        self.itl4 = itl4.unwrap_or(self.itl4);
+        self.trtol = trtol.unwrap_or(self.trtol);
+        self.gmin = gmin.unwrap_or(self.gmin);
+        self.tmax = tmax.or(self.tmax);
+        self.method = method.unwrap_or(self.method);
+        self.gmin_steps = gmin_steps.unwrap_or(self.gmin_steps);
+        self.src_steps = src_steps.unwrap_or(self.src_steps);
+        self.damping = damping.unwrap_or(self.damping);
+        self.interp = interp.unwrap_or(self.interp);
+        self.temp = temp.unwrap_or(self.temp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_keeps_unset() {
+        let mut settings = Settings::default();
+
+        settings.apply(&OptionsParams {
+            reltol: Some(1e-4),
+            itl4: Some(50),
+            ..OptionsParams::default()
+        });
+
+        assert_eq!(settings.reltol, 1e-4);
+        assert_eq!(settings.itl4, 50);
+        assert_eq!(settings.vntol, 1e-6);
+        assert_eq!(settings.tmax, None);
+    }
+
+    #[test]
+    fn test_apply_later_wins() {
+        let mut settings = Settings::default();
+
+        settings.apply(&OptionsParams {
+            method: Some(Method::Gear2),
+            tmax: Some(1e-9),
+            ..OptionsParams::default()
+        });
+        settings.apply(&OptionsParams {
+            method: Some(Method::BackwardEuler),
+            ..OptionsParams::default()
+        });
+
+        assert_eq!(settings.method, Method::BackwardEuler);
+        assert_eq!(settings.tmax, Some(1e-9));
+    }
+}
@@ -27,14 +27,20 @@ impl SimResult {
         for row in self.data.iter() {
             println!(
                 "{}",
+                // Adding zero turns -0.0 into 0.0
                 row.iter()
-                    .map(|x| x.to_string())
+                    .map(|x| (x + 0.0).to_string())
                     .collect::<Vec<_>>()
                     .join(",")
             );
         }
     }
 
+    // `n_iters` or the sweep/time columns first, then one per node
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
     pub fn get(&self, label: &str) -> Array1<f64> {
         let idx = self
             .headers
@@ -0,0 +1,573 @@
+use ndarray::prelude::*;
+use num_complex::ComplexFloat;
+
+use crate::sparse::{CscMatrix, SparseMatrix};
+
+mod ordering;
+
+// A pivot off the diagonal is only taken if the diagonal is this much smaller
+// than the largest candidate, so the fill-reducing order is mostly kept
+const PIVOT_REL_TOL: f64 = 1e-3;
+// Pivots this much smaller than the column they came from are only rounding
+// errors left after eliminating a singular matrix
+const SINGULAR_REL_TOL: f64 = 1e-13;
+
+// P A Q = L U, with L unit lower triangular
+#[derive(Debug)]
+pub struct LU<T> {
+    // Column of A eliminated at each step
+    q: Vec<usize>,
+    // Row of A pivoted on at each step, and the other way around
+    p: Vec<usize>,
+    pinv: Vec<usize>,
+    // Below the diagonal only, rows numbered by step
+    l: CscMatrix<T>,
+    // Rows numbered by step in increasing order, so the diagonal is last
+    u: CscMatrix<T>,
+}
+
+// Solves a sequence of matrices stamped the same way, e.g. the Newton
+// iterations of an analysis. The pattern, ordering and pivots of the first one
+// are reused, and only recomputed when entries are added or a pivot gets too
+// small for the new values.
+#[derive(Debug)]
+pub struct Solver<T> {
+    lu: Option<LU<T>>,
+    // The matrix last factored, and where each of its entries is in it
+    a: CscMatrix<T>,
+    slots: Vec<usize>,
+}
+
+// Real for DC and transient, complex for AC
+impl<T: ComplexFloat<Real = f64>> Solver<T> {
+    pub fn new() -> Self {
+        Solver {
+            lu: None,
+            a: CscMatrix::from_columns(0, Vec::new()),
+            slots: Vec::new(),
+        }
+    }
+
+    // Err with the unknown that couldn't be pivoted on when `a_mat` is singular
+    pub fn solve(
+        &mut self,
+        a_mat: &SparseMatrix<T>,
+        b_vec: &Array1<T>,
+        x_vec: &mut Array1<T>,
+    ) -> Result<(), usize> {
+        // Entries are never removed, one stamped the same way with as many of
+        // them has the same pattern
+        let lu = match self.lu.take() {
+            Some(mut lu) if a_mat.nnz() == self.slots.len() => {
+                a_mat.scatter(&self.slots, &mut self.a.values);
+                match lu.refactor(&self.a) {
+                    true => lu,
+                    false => LU::factor_ordered(&self.a, lu.q)?,
+                }
+            }
+            _ => {
+                (self.a, self.slots) = a_mat.to_csc_slots();
+                LU::factor(&self.a)?
+            }
+        };
+
+        x_vec.assign(&lu.solve(b_vec));
+        self.lu = Some(lu);
+
+        Ok(())
+    }
+}
+
+impl<T: ComplexFloat<Real = f64>> LU<T> {
+    pub fn factor(a: &CscMatrix<T>) -> Result<Self, usize> {
+        Self::factor_ordered(a, ordering::min_degree(a))
+    }
+
+    // Left-looking Gilbert-Peierls factorization with threshold partial pivoting,
+    // eliminating the columns of `a` in the order `q`. Fails with the column
+    // that has nothing left to pivot on if `a` is singular.
+    pub fn factor_ordered(a: &CscMatrix<T>, q: Vec<usize>) -> Result<Self, usize> {
+        let n = a.ncols;
+
+        let mut pinv: Vec<Option<usize>> = vec![None; n];
+        let mut p = Vec::with_capacity(n);
+        // Rows numbered as in A until every pivot is known
+        let mut l_cols: Vec<Vec<(usize, T)>> = Vec::with_capacity(n);
+        let mut u_cols: Vec<Vec<(usize, T)>> = Vec::with_capacity(n);
+
+        let mut x = vec![T::zero(); n];
+        let mut mark = vec![usize::MAX; n];
+
+        for (k, &col) in q.iter().enumerate() {
+            let reach = reach(a, col, &l_cols, &pinv, &mut mark, k);
+
+            for &i in reach.iter() {
+                x[i] = T::zero();
+            }
+            for (i, v) in a.col(col) {
+                x[i] = v;
+            }
+
+            // Sparse triangular solve L x = A(:, col)
+            for &j in reach.iter() {
+                if let Some(s) = pinv[j] {
+                    let xj = x[j];
+                    for &(i, v) in l_cols[s].iter() {
+                        x[i] = x[i] - v * xj;
+                    }
+                }
+            }
+
+            let mut u_col = Vec::new();
+            let mut ipiv = None;
+            let mut max_val = -1.0;
+
+            for &i in reach.iter() {
+                match pinv[i] {
+                    Some(s) => u_col.push((s, x[i])),
+                    None if x[i].abs() > max_val => {
+                        ipiv = Some(i);
+                        max_val = x[i].abs();
+                    }
+                    None => (),
+                }
+            }
+
+            // Prefer the diagonal, it's the pivot the ordering was planned around
+            if pinv[col].is_none() && mark[col] == k && x[col].abs() >= PIVOT_REL_TOL * max_val {
+                ipiv = Some(col);
+            }
+
+            let col_max = a.col(col).map(|(_, v)| v.abs()).fold(0.0, f64::max);
+            let (ipiv, pivot) = match ipiv {
+                Some(i) if x[i].abs() > SINGULAR_REL_TOL * col_max => (i, x[i]),
+                _ => return Err(col),
+            };
+
+            u_col.sort_by_key(|&(s, _)| s);
+            u_col.push((k, pivot));
+            pinv[ipiv] = Some(k);
+            p.push(ipiv);
+
+            let l_col = reach
+                .iter()
+                .filter(|&&i| pinv[i].is_none())
+                .map(|&i| (i, x[i] / pivot))
+                .collect();
+
+            l_cols.push(l_col);
+            u_cols.push(u_col);
+        }
+
+        let pinv = pinv.into_iter().map(Option::unwrap).collect::<Vec<_>>();
+
+        for col in l_cols.iter_mut() {
+            for (i, _) in col.iter_mut() {
+                *i = pinv[*i];
+            }
+        }
+
+        Ok(LU {
+            q,
+            p,
+            pinv,
+            l: CscMatrix::from_columns(n, l_cols),
+            u: CscMatrix::from_columns(n, u_cols),
+        })
+    }
+
+    // Factors new values with the same pivots and fill-in, no searching needed.
+    // False if a pivot got too small, `a` has to be factored again then.
+    pub fn refactor(&mut self, a: &CscMatrix<T>) -> bool {
+        let mut x = vec![T::zero(); self.q.len()];
+
+        for (k, &col) in self.q.iter().enumerate() {
+            for (i, v) in a.col(col) {
+                x[self.pinv[i]] = v;
+            }
+
+            let u_range = self.u.col_ptr[k]..self.u.col_ptr[k + 1];
+            let l_range = self.l.col_ptr[k]..self.l.col_ptr[k + 1];
+
+            for &s in self.u.row_idx[u_range.start..u_range.end - 1].iter() {
+                let xs = x[s];
+                for (i, v) in self.l.col(s) {
+                    x[i] = x[i] - v * xs;
+                }
+            }
+
+            for idx in u_range.clone() {
+                let s = self.u.row_idx[idx];
+                self.u.values[idx] = x[s];
+                x[s] = T::zero();
+            }
+
+            let pivot = self.u.values[u_range.end - 1];
+            let max_val = self.l.row_idx[l_range.clone()]
+                .iter()
+                .map(|&i| x[i].abs())
+                .fold(0.0, f64::max);
+            let col_max = a.col(col).map(|(_, v)| v.abs()).fold(0.0, f64::max);
+
+            if pivot.abs() <= SINGULAR_REL_TOL * col_max || pivot.abs() < PIVOT_REL_TOL * max_val {
+                return false;
+            }
+
+            for idx in l_range {
+                let i = self.l.row_idx[idx];
+                self.l.values[idx] = x[i] / pivot;
+                x[i] = T::zero();
+            }
+        }
+
+        true
+    }
+
+    pub fn solve(&self, b: &Array1<T>) -> Array1<T> {
+        let n = self.q.len();
+        let mut y = self.p.iter().map(|&i| b[i]).collect::<Vec<_>>();
+
+        for k in 0..n {
+            let yk = y[k];
+            for (i, v) in self.l.col(k) {
+                y[i] = y[i] - v * yk;
+            }
+        }
+
+        for k in (0..n).rev() {
+            let mut col = self.u.col(k).collect::<Vec<_>>();
+            let (_, diag) = col.pop().unwrap();
+
+            y[k] = y[k] / diag;
+            for (i, v) in col {
+                y[i] = y[i] - v * y[k];
+            }
+        }
+
+        let mut x = Array1::from_elem(n, T::zero());
+        for (k, &j) in self.q.iter().enumerate() {
+            x[j] = y[k];
+        }
+
+        x
+    }
+}
+
+// Rows of A reached while solving for column `col`, in topological order: row i
+// depends on row j if j was pivoted on and column pinv[j] of L has row i
+fn reach<T: ComplexFloat>(
+    a: &CscMatrix<T>,
+    col: usize,
+    l_cols: &[Vec<(usize, T)>],
+    pinv: &[Option<usize>],
+    mark: &mut [usize],
+    k: usize,
+) -> Vec<usize> {
+    let mut post_order = Vec::new();
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+
+    for (start, _) in a.col(col) {
+        if mark[start] == k {
+            continue;
+        }
+        mark[start] = k;
+        stack.push((start, 0));
+
+        while let Some((j, next)) = stack.pop() {
+            let children = pinv[j].map_or(&[][..], |s| &l_cols[s][..]);
+
+            match children[next..].iter().position(|&(i, _)| mark[i] != k) {
+                Some(offset) => {
+                    let i = children[next + offset].0;
+                    mark[i] = k;
+                    stack.push((j, next + offset + 1));
+                    stack.push((i, 0));
+                }
+                None => post_order.push(j),
+            }
+        }
+    }
+
+    post_order.reverse();
+    post_order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_complex::Complex64;
+
+    fn sparse(a: Array2<f64>) -> SparseMatrix<f64> {
+        let mut s = SparseMatrix::zeros((a.nrows(), a.ncols()));
+        for ((i, j), &v) in a.indexed_iter() {
+            if v != 0.0 {
+                s[(i, j)] = v;
+            }
+        }
+        s
+    }
+
+    #[test]
+    fn test_2x2_zero() {
+        let a_mat = sparse(array![[1.0, 0.0], [0.0, 1.0]]);
+        let b_vec = array![0.0, 0.0];
+        let mut x_vec = Array1::zeros(b_vec.len());
+
+        Solver::new().solve(&a_mat, &b_vec, &mut x_vec).unwrap();
+
+        assert_eq!(x_vec, array![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_2x2_nonzero() {
+        let a_mat = sparse(array![[1.0, 0.0], [0.0, 1.0]]);
+        let b_vec = array![1.0, 2.0];
+        let mut x_vec = Array1::zeros(b_vec.len());
+
+        Solver::new().solve(&a_mat, &b_vec, &mut x_vec).unwrap();
+
+        assert_eq!(x_vec, array![1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_2x2_nontrivial() {
+        let a_mat = sparse(array![[5.0, 2.0], [-1.0, 3.0]]);
+        let b_vec = array![1.0, 2.0];
+        let mut x_vec = Array1::zeros(b_vec.len());
+
+        Solver::new().solve(&a_mat, &b_vec, &mut x_vec).unwrap();
+
+        let eps = 1e-15;
+
+        assert!((x_vec[0] - (-1.0 / 17.0)).abs() < eps);
+        assert!((x_vec[1] - (11.0 / 17.0)).abs() < eps);
+    }
+
+    #[test]
+    fn test_3x3_nontrivial() {
+        let a_mat = sparse(array![[5.0, 2.0, 1.0], [-1.0, 3.0, -1.0], [0.0, 2.0, -1.0]]);
+        let b_vec = array![1.0, 2.0, 1.0];
+        let mut x_vec = Array1::zeros(b_vec.len());
+
+        Solver::new().solve(&a_mat, &b_vec, &mut x_vec).unwrap();
+
+        let eps = 1e-15;
+
+        assert!((x_vec[0] - (-2.0 / 9.0)).abs() < eps);
+        assert!((x_vec[1] - (7.0 / 9.0)).abs() < eps);
+        assert!((x_vec[2] - (5.0 / 9.0)).abs() < eps);
+    }
+
+    #[test]
+    fn test_2x2_complex() {
+        let j = Complex64::i();
+        let one = Complex64::new(1.0, 0.0);
+        let mut a_mat = SparseMatrix::zeros((2, 2));
+        a_mat[(0, 0)] = one;
+        a_mat[(0, 1)] = j;
+        a_mat[(1, 0)] = j;
+        a_mat[(1, 1)] = 2.0 * one;
+        let b_vec = array![one, j];
+        let mut x_vec = Array1::zeros(b_vec.len());
+
+        Solver::new().solve(&a_mat, &b_vec, &mut x_vec).unwrap();
+
+        let eps = 1e-15;
+
+        assert!((x_vec[0] - one).norm() < eps);
+        assert!(x_vec[1].norm() < eps);
+    }
+
+    #[test]
+    fn test_zero_diagonal() {
+        // Voltage source branch rows have nothing on the diagonal
+        let a_mat = sparse(array![[1e-3, 1.0], [1.0, 0.0]]);
+        let b_vec = array![0.0, 5.0];
+        let mut x_vec = Array1::zeros(b_vec.len());
+
+        Solver::new().solve(&a_mat, &b_vec, &mut x_vec).unwrap();
+
+        assert!((x_vec[0] - 5.0).abs() < 1e-12);
+        assert!((x_vec[1] + 5e-3).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_singular_empty_column() {
+        // A floating node without a shunt to ground
+        let a_mat = sparse(array![[1.0, 0.0], [0.0, 0.0]]);
+        let mut x_vec = Array1::zeros(2);
+
+        let res = Solver::new().solve(&a_mat, &array![1.0, 0.0], &mut x_vec);
+
+        assert_eq!(res, Err(1));
+    }
+
+    #[test]
+    fn test_singular_dependent_columns() {
+        // Two voltage sources in parallel, their branch currents can't be told apart
+        let a_mat = sparse(array![[1e-3, 1.0, 1.0], [1.0, 0.0, 0.0], [1.0, 0.0, 0.0]]);
+
+        let res = LU::factor(&a_mat.to_csc());
+
+        assert!(matches!(res, Err(1) | Err(2)));
+    }
+
+    #[test]
+    fn test_small_diagonal_pivots_off_diagonal() {
+        let a_mat = sparse(array![[1e-12, 1.0], [1.0, 1.0]]);
+        let lu = LU::factor(&a_mat.to_csc()).unwrap();
+
+        let x = lu.solve(&array![1.0, 2.0]);
+
+        assert!((x[0] - 1.0).abs() < 1e-9);
+        assert!((x[1] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ladder_matches_dense() {
+        // Resistor ladder, tridiagonal, solved for a known x
+        let n = 50;
+        let mut a_mat = SparseMatrix::zeros((n, n));
+        for i in 0..n {
+            a_mat[(i, i)] = 2.0 + i as f64 * 1e-2;
+            if i > 0 {
+                a_mat[(i, i - 1)] = -1.0;
+                a_mat[(i - 1, i)] = -1.0;
+            }
+        }
+        let x_expected = Array1::from_iter((0..n).map(|i| (i as f64).sin()));
+        let b_vec = a_mat.dot(&x_expected);
+        let mut x_vec = Array1::zeros(n);
+
+        Solver::new().solve(&a_mat, &b_vec, &mut x_vec).unwrap();
+
+        for i in 0..n {
+            assert!((x_vec[i] - x_expected[i]).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_factor_keeps_ladder_sparse() {
+        let n = 50;
+        let mut a_mat = SparseMatrix::zeros((n, n));
+        for i in 0..n {
+            a_mat[(i, i)] = 2.0;
+            if i > 0 {
+                a_mat[(i, i - 1)] = -1.0;
+                a_mat[(i - 1, i)] = -1.0;
+            }
+        }
+
+        let a_csc = a_mat.to_csc();
+        let lu = LU::factor(&a_csc).unwrap();
+
+        // No fill-in for a tridiagonal matrix, L's unit diagonal isn't stored
+        assert_eq!(lu.l.values.len() + lu.u.values.len(), a_csc.values.len());
+    }
+
+    #[test]
+    fn test_refactor_new_values() {
+        let mut lu = LU::factor(&sparse(array![[5.0, 2.0], [-1.0, 3.0]]).to_csc()).unwrap();
+
+        assert!(lu.refactor(&sparse(array![[4.0, 1.0], [2.0, 3.0]]).to_csc()));
+
+        let x = lu.solve(&array![5.0, 5.0]);
+        assert!((x[0] - 1.0).abs() < 1e-15);
+        assert!((x[1] - 1.0).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_refactor_small_pivot() {
+        let mut lu = LU::factor(&sparse(array![[2.0, 1.0], [1.0, 2.0]]).to_csc()).unwrap();
+
+        assert!(!lu.refactor(&sparse(array![[1e-12, 1.0], [1.0, 2.0]]).to_csc()));
+    }
+
+    #[test]
+    fn test_refactor_singular() {
+        let mut lu = LU::factor(&sparse(array![[2.0, 1.0], [1.0, 2.0]]).to_csc()).unwrap();
+
+        // Eliminating leaves a rounding error for the last pivot, not an exact zero
+        let a = sparse(array![[0.1, 0.3], [0.3, 0.9]]);
+        assert!(!lu.refactor(&a.to_csc()));
+
+        let mut solver = Solver::new();
+        let mut x_vec = Array1::zeros(2);
+        solver
+            .solve(
+                &sparse(array![[2.0, 1.0], [1.0, 2.0]]),
+                &array![3.0, 3.0],
+                &mut x_vec,
+            )
+            .unwrap();
+        let res = solver.solve(&a, &array![1.0, 1.0], &mut x_vec);
+        assert_eq!(res, Err(1));
+    }
+
+    #[test]
+    fn test_solver_repivots() {
+        let mut solver = Solver::new();
+        let mut x_vec = Array1::zeros(2);
+
+        solver
+            .solve(
+                &sparse(array![[2.0, 1.0], [1.0, 2.0]]),
+                &array![3.0, 3.0],
+                &mut x_vec,
+            )
+            .unwrap();
+        solver
+            .solve(
+                &sparse(array![[1e-12, 1.0], [1.0, 1.0]]),
+                &array![1.0, 2.0],
+                &mut x_vec,
+            )
+            .unwrap();
+
+        assert!((x_vec[0] - 1.0).abs() < 1e-9);
+        assert!((x_vec[1] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_solver_new_values() {
+        let mut solver = Solver::new();
+        let mut a_mat = sparse(array![[2.0, 1.0], [1.0, 2.0]]);
+        let mut x_vec = Array1::zeros(2);
+
+        solver.solve(&a_mat, &array![3.0, 3.0], &mut x_vec).unwrap();
+        let slots = solver.slots.clone();
+
+        a_mat[(0, 0)] = 4.0;
+        solver.solve(&a_mat, &array![5.0, 3.0], &mut x_vec).unwrap();
+
+        assert_eq!(solver.slots, slots);
+        assert!((x_vec[0] - 1.0).abs() < 1e-15);
+        assert!((x_vec[1] - 1.0).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_solver_pattern_change() {
+        let mut solver = Solver::new();
+        let mut x_vec = Array1::zeros(2);
+
+        solver
+            .solve(
+                &sparse(array![[2.0, 0.0], [0.0, 2.0]]),
+                &array![2.0, 4.0],
+                &mut x_vec,
+            )
+            .unwrap();
+        assert!(solver.lu.as_ref().unwrap().l.values.is_empty());
+
+        solver
+            .solve(
+                &sparse(array![[1.0, 1.0], [1.0, 2.0]]),
+                &array![2.0, 3.0],
+                &mut x_vec,
+            )
+            .unwrap();
+
+        assert!(!solver.lu.as_ref().unwrap().l.values.is_empty());
+        assert_eq!(x_vec, array![1.0, 1.0]);
+    }
+}
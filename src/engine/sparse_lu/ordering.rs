@@ -0,0 +1,82 @@
+use std::collections::BTreeSet;
+
+use num_complex::ComplexFloat;
+
+use crate::sparse::CscMatrix;
+
+// Minimum degree ordering on the pattern of A + A^T. Eliminating the node with
+// the fewest neighbours first keeps fill-in low. Degrees are exact rather than
+// approximated as in AMD, which is fast enough for circuit matrices.
+pub fn min_degree<T: ComplexFloat>(a: &CscMatrix<T>) -> Vec<usize> {
+    let n = a.ncols;
+    let mut adj = vec![BTreeSet::new(); n];
+
+    for j in 0..n {
+        for (i, _) in a.col(j) {
+            if i != j {
+                adj[i].insert(j);
+                adj[j].insert(i);
+            }
+        }
+    }
+
+    // Ties are broken by index so the order doesn't depend on anything else
+    let mut queue = (0..n).map(|i| (adj[i].len(), i)).collect::<BTreeSet<_>>();
+    let mut order = Vec::with_capacity(n);
+
+    while let Some((_, v)) = queue.pop_first() {
+        let neighbours = std::mem::take(&mut adj[v]);
+
+        // Eliminating v connects all of its neighbours to each other
+        for &u in neighbours.iter() {
+            queue.remove(&(adj[u].len(), u));
+
+            adj[u].remove(&v);
+            adj[u].extend(neighbours.iter().filter(|&&w| w != u));
+
+            queue.insert((adj[u].len(), u));
+        }
+
+        order.push(v);
+    }
+
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sparse::SparseMatrix;
+
+    #[test]
+    fn test_min_degree_is_permutation() {
+        let mut a = SparseMatrix::zeros((4, 4));
+        for i in 0..4 {
+            a[(i, i)] = 1.0;
+        }
+        a[(0, 3)] = 1.0;
+        a[(2, 1)] = 1.0;
+
+        let mut order = min_degree(&a.to_csc());
+        order.sort();
+
+        assert_eq!(order, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_min_degree_arrow() {
+        // Node 0 is connected to every other one, eliminating it first would
+        // fill in the whole matrix
+        let mut a = SparseMatrix::zeros((4, 4));
+        for i in 0..4 {
+            a[(i, i)] = 4.0;
+            a[(0, i)] = 1.0;
+            a[(i, 0)] = 1.0;
+        }
+
+        let order = min_degree(&a.to_csc());
+
+        // By the time 0 goes only one neighbour is left, so nothing fills in
+        assert_eq!(order, [1, 2, 0, 3]);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::command::{SweepType, SweepValues};
+
+// Small fudge so the stop value isn't lost to rounding
+const REL_TOL: f64 = 1e-9;
+
+pub fn values(values: &SweepValues) -> Vec<f64> {
+    match *values {
+        SweepValues::Step { start, stop, step } => steps(start, stop, step),
+        SweepValues::Points {
+            sweep,
+            points,
+            start,
+            stop,
+        } => self::points(sweep, points, start, stop),
+    }
+}
+
+// From start to stop, both included, the step may be negative
+pub fn steps(start: f64, stop: f64, step: f64) -> Vec<f64> {
+    let n = ((stop - start) / step + REL_TOL).floor().max(0.0) as usize;
+
+    (0..=n).map(|k| start + k as f64 * step).collect()
+}
+
+// Points per decade or octave, or in total for a linear sweep
+pub fn points(sweep: SweepType, points: usize, start: f64, stop: f64) -> Vec<f64> {
+    let n = points as f64;
+
+    match sweep {
+        SweepType::Lin if points == 1 => vec![start],
+        SweepType::Lin => (0..points)
+            .map(|k| start + k as f64 * (stop - start) / (n - 1.0))
+            .collect(),
+        SweepType::Dec | SweepType::Oct => {
+            let base: f64 = match sweep {
+                SweepType::Dec => 10.0,
+                _ => 2.0,
+            };
+            let ratio = base.powf(1.0 / n);
+            let steps = ((stop / start).log(base) * n + REL_TOL).floor() as i32;
+
+            (0..=steps).map(|k| start * ratio.powi(k)).collect()
+        }
+    }
+}
+
+// Every combination of the values of each sweep, the first one changing fastest
+pub fn grid(values: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    values.iter().fold(vec![vec![]], |inner, vals| {
+        vals.iter()
+            .flat_map(|v| {
+                inner.iter().map(move |point| {
+                    let mut point = point.clone();
+                    point.push(*v);
+                    point
+                })
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_inclusive() {
+        assert_eq!(steps(0.0, 1.0, 0.25), [0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(steps(0.0, 0.3, 0.1).len(), 4);
+    }
+
+    #[test]
+    fn test_steps_down() {
+        assert_eq!(steps(5.0, 3.0, -1.0), [5.0, 4.0, 3.0]);
+        assert_eq!(steps(1.0, 1.0, 0.5), [1.0]);
+    }
+
+    #[test]
+    fn test_grid() {
+        let points = grid(&[vec![1.0, 2.0, 3.0], vec![10.0, 20.0]]);
+
+        assert_eq!(
+            points,
+            [
+                [1.0, 10.0],
+                [2.0, 10.0],
+                [3.0, 10.0],
+                [1.0, 20.0],
+                [2.0, 20.0],
+                [3.0, 20.0],
+            ]
+        );
+    }
+}
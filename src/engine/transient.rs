@@ -1,76 +1,95 @@
 use ndarray::prelude::*;
 
 use crate::device::Stamp;
-use crate::engine::error::NotConvergedError;
+use crate::engine::error::SimError;
 use crate::engine::mna::MNA;
 use crate::engine::newtons_method;
 use crate::engine::node_vec_norm::NodeVecNorm;
+use crate::engine::settings::Settings;
+use crate::integration::TimeStep;
 use crate::node_collection::NodeCollection;
 
 pub mod state_history;
 
 pub const T_STEP_MIN: f64 = 1e-18;
 
-const TOL_REL: f64 = 0.001;
-const TOL_ABS_V: f64 = 1e-3;
-const TOL_ABS_A: f64 = 1e-6;
+// Truncation error allowed per step on top of reltol, before trtol scales it
+const LTE_ABS_V: f64 = 1e-3;
+const LTE_ABS_A: f64 = 1e-6;
+
+// Breakpoints closer than this fraction of the max step are the same one
+const BREAKPOINT_TOL: f64 = 1e-9;
+// First step after a breakpoint as a fraction of the max step or the gap to the next one
+const BREAKPOINT_STEP: f64 = 0.1;
+
+// What stays the same over every step of a transient
+pub struct Context<'a> {
+    pub nodes: &'a NodeCollection,
+    pub settings: &'a Settings,
+    pub step_max: f64,
+}
 
 pub fn step(
-    nodes: &NodeCollection,
+    ctx: &Context,
     elems: &mut Vec<Box<dyn Stamp>>,
     mna: &mut MNA,
     t: &f64,
-    h: &f64,
+    step: &TimeStep,
     x: &mut Array1<f64>,
     state_hist: &mut state_history::StateHistory,
-    step_max: &f64,
-) -> Result<(f64, f64), NotConvergedError> {
-    let mut h = h.to_owned();
-    let mut next_h = h;
+) -> Result<(TimeStep, f64), SimError> {
+    let Context {
+        nodes,
+        settings,
+        step_max,
+    } = *ctx;
+    let mut step = step.to_owned();
+    let mut next_h = step.h;
     let mut step_accepted = false;
 
     let a_bkp = mna.a.clone();
     let b_bkp = mna.b.clone();
 
     while !step_accepted {
+        let h = step.h;
+
         for elem in elems.iter_mut() {
             if elem.has_tran() {
-                elem.undo_linear_stamp(&nodes, &mut mna.a, &mut mna.b);
+                elem.undo_linear_stamp(nodes, &mut mna.a, &mut mna.b);
                 elem.eval_tran(&(t + h));
-                elem.linear_stamp(&nodes, &mut mna.a, &mut mna.b);
+                elem.linear_stamp(nodes, &mut mna.a, &mut mna.b);
             }
         }
 
         for elem in elems.iter() {
-            elem.dynamic_stamp(&nodes, &x, &h, &mut mna.a, &mut mna.b);
+            elem.dynamic_stamp(nodes, x, &step, &mut mna.a, &mut mna.b);
         }
 
-        let n_iters = newtons_method::solve(nodes, elems, x, &mna);
+        let n_iters = newtons_method::solve(nodes, elems, x, mna, settings, settings.itl4);
 
         match n_iters {
-            Err(NotConvergedError) => {
-                h /= 2.0;
+            Err(SimError::NotConverged { .. } | SimError::Diverged { .. }) => {
                 step_accepted = false;
             }
-            Ok(n_iters) if state_hist.len() < 3 => {
-                state_hist.push(n_iters, &x, t + h);
+            Err(e) => return Err(e),
+            Ok(n_iters) if state_hist.len_since_breakpoint() <= step.method.order() => {
+                state_hist.push(n_iters, x, t + h);
                 next_h = h;
                 step_accepted = true;
             }
             Ok(n_iters) => {
-                state_hist.push(n_iters, &x, t + h);
+                state_hist.push(n_iters, x, t + h);
 
-                let plte = state_hist.plte(state_hist.len() - 2);
+                let plte = state_hist.plte(state_hist.len() - 2, &step.method);
                 let plte_norm = NodeVecNorm::new(nodes, &plte);
-                let x_norm = NodeVecNorm::new(nodes, &x);
+                let x_norm = NodeVecNorm::new(nodes, x);
 
-                step_accepted = !plte_is_too_big(&plte_norm, &x_norm);
+                step_accepted = !plte_is_too_big(&plte_norm, &x_norm, settings);
 
                 if !step_accepted {
-                    h /= 2.0;
                     state_hist.pop();
                 } else {
-                    next_h = if plte_can_grow(&plte_norm) && h <= step_max / 2.0 {
+                    next_h = if plte_can_grow(&plte_norm, settings) && h <= step_max / 2.0 {
                         h * 2.0
                     } else {
                         h
@@ -80,26 +99,135 @@ pub fn step(
         }
 
         if !step_accepted {
+            // Undo with the same step the companion models were stamped with
             for elem in elems.iter_mut() {
-                elem.undo_dynamic_stamp(&nodes, &x, &h, &mut mna.a, &mut mna.b);
+                elem.undo_dynamic_stamp(nodes, x, &step, &mut mna.a, &mut mna.b);
             }
+            step.h /= 2.0;
         }
 
-        if h < T_STEP_MIN {
-            return Err(NotConvergedError);
+        if step.h < T_STEP_MIN {
+            return Err(SimError::TimestepTooSmall {
+                analysis: String::new(),
+                time: *t,
+            });
         }
     }
 
     mna.a = a_bkp;
     mna.b = b_bkp;
 
-    Ok((h, next_h))
+    Ok((step, next_h))
+}
+
+// Stops just short of the breakpoint, inside the tolerance that makes it count
+// as reached. Sources are evaluated before a jump, like the rest of the step.
+pub fn breakpoint_h(t: &f64, breakpoint: &f64, step_max: &f64) -> f64 {
+    breakpoint - t - 0.5 * BREAKPOINT_TOL * step_max
+}
+
+// Lands on the breakpoint when it's within reach. The last stretch before it is
+// split in two rather than leaving a sliver that would need a tiny step.
+pub fn clip_to_breakpoint(h: f64, h_breakpoint: f64) -> f64 {
+    if h >= h_breakpoint {
+        h_breakpoint
+    } else if h > 0.5 * h_breakpoint {
+        0.5 * h_breakpoint
+    } else {
+        h
+    }
+}
+
+pub fn next_breakpoint(elems: &[Box<dyn Stamp>], t: &f64, step_max: &f64) -> Option<f64> {
+    let t = t + BREAKPOINT_TOL * step_max;
+
+    elems
+        .iter()
+        .filter_map(|e| e.next_breakpoint(&t))
+        .reduce(f64::min)
+}
+
+// Restart with small steps, the history before the breakpoint doesn't say
+// anything about the waveform after it
+pub fn breakpoint_step(t: &f64, next_breakpoint: Option<f64>, step_max: &f64) -> f64 {
+    let gap = next_breakpoint.map_or(*step_max, |b| (b - t).min(*step_max));
+
+    BREAKPOINT_STEP * gap
+}
+
+fn plte_is_too_big(plte: &NodeVecNorm, x: &NodeVecNorm, settings: &Settings) -> bool {
+    let tol = plte_tol(x, settings);
+
+    plte.v > tol.v || plte.i > tol.i
+}
+
+// Only well inside the absolute tolerance, large signals don't make the step grow
+fn plte_can_grow(plte: &NodeVecNorm, settings: &Settings) -> bool {
+    plte.v < 0.1 * settings.trtol * LTE_ABS_V && plte.i < 0.1 * settings.trtol * LTE_ABS_A
 }
 
-fn plte_is_too_big(plte: &NodeVecNorm, x: &NodeVecNorm) -> bool {
-    plte.v > x.v * TOL_REL + TOL_ABS_V || plte.i > x.i * TOL_REL + TOL_ABS_A
+fn plte_tol(x: &NodeVecNorm, settings: &Settings) -> NodeVecNorm {
+    NodeVecNorm {
+        v: settings.trtol * (settings.reltol * x.v + LTE_ABS_V),
+        i: settings.trtol * (settings.reltol * x.i + LTE_ABS_A),
+    }
 }
 
-fn plte_can_grow(plte: &NodeVecNorm) -> bool {
-    plte.v < 0.1 * TOL_ABS_V && plte.i < 0.1 * TOL_ABS_A
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::vdd::Vdd;
+    use crate::spice_fn::{PwlParams, SpiceFn};
+
+    fn pwl_source(name: &str, points: Vec<(f64, f64)>) -> Box<dyn Stamp> {
+        Box::new(Vdd {
+            name: String::from(name),
+            nodes: vec![String::from("0"), String::from("1")],
+            val: 0.0,
+            ac_mag: 0.0,
+            ac_phase: 0.0,
+            tran_fn: Some(SpiceFn::Pwl(PwlParams {
+                points,
+                delay: 0.0,
+                repeat: None,
+            })),
+        })
+    }
+
+    #[test]
+    fn test_next_breakpoint() {
+        let elems = vec![
+            pwl_source("V1", vec![(0.0, 0.0), (2.0, 1.0), (5.0, 0.0)]),
+            pwl_source("V2", vec![(0.0, 0.0), (3.0, 1.0)]),
+        ];
+
+        assert_eq!(next_breakpoint(&elems, &0.0, &1.0), Some(2.0));
+        assert_eq!(next_breakpoint(&elems, &2.5, &1.0), Some(3.0));
+        assert_eq!(next_breakpoint(&elems, &5.0, &1.0), None);
+    }
+
+    #[test]
+    fn test_next_breakpoint_reached() {
+        let elems = vec![pwl_source("V1", vec![(0.0, 0.0), (2.0, 1.0), (5.0, 0.0)])];
+        // Where a step from 0 clipped to the breakpoint lands
+        let t = breakpoint_h(&0.0, &2.0, &1.0);
+
+        assert!(t < 2.0);
+        assert_eq!(next_breakpoint(&elems, &t, &1.0), Some(5.0));
+    }
+
+    #[test]
+    fn test_clip_to_breakpoint() {
+        assert_eq!(clip_to_breakpoint(1.0, 10.0), 1.0);
+        assert_eq!(clip_to_breakpoint(3.0, 2.0), 2.0);
+        assert_eq!(clip_to_breakpoint(1.5, 2.0), 1.0);
+        assert_eq!(clip_to_breakpoint(1.0, 2.0), 1.0);
+    }
+
+    #[test]
+    fn test_breakpoint_step() {
+        assert_eq!(breakpoint_step(&0.0, Some(5.0), &1.0), 0.1);
+        assert_eq!(breakpoint_step(&0.0, Some(0.5), &1.0), 0.05);
+        assert_eq!(breakpoint_step(&0.0, None, &1.0), 0.1);
+    }
 }
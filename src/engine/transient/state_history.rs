@@ -1,11 +1,15 @@
 use ndarray::prelude::*;
 
+use crate::integration::Method;
+
 #[derive(Debug)]
 pub struct StateHistory {
     data: Vec<Record>,
+    // Index of the last breakpoint, the error estimate doesn't look past it
+    restart: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Record {
     pub n_iters: u64,
     pub x: Array1<f64>,
@@ -14,13 +18,25 @@ pub struct Record {
 
 impl StateHistory {
     pub fn new() -> Self {
-        StateHistory { data: vec![] }
+        StateHistory {
+            data: vec![],
+            restart: 0,
+        }
     }
 
     pub fn len(&self) -> usize {
         self.data.len()
     }
 
+    pub fn len_since_breakpoint(&self) -> usize {
+        self.data.len() - self.restart
+    }
+
+    // The last record is on a discontinuity, start the history over from it
+    pub fn mark_breakpoint(&mut self) {
+        self.restart = self.data.len() - 1;
+    }
+
     pub fn push(&mut self, n_iters: u64, x: &Array1<f64>, t: f64) {
         self.data.push(Record {
             n_iters,
@@ -37,12 +53,15 @@ impl StateHistory {
         self.data.iter()
     }
 
-    pub fn plte(&self, n: usize) -> Array1<f64> {
-        let c3 = -1.0 / 12.0;
+    // Needs `method.order() + 2` records up to n + 1
+    pub fn plte(&self, n: usize, method: &Method) -> Array1<f64> {
+        let k = method.order() + 1;
         let h_next = self.data[n + 1].t - self.data[n].t;
-        let alpha = 6.0 * c3 * h_next.powi(3);
+        // The k-th divided difference is the k-th derivative over k!
+        let k_factorial = (1..=k).product::<usize>() as f64;
+        let alpha = k_factorial * method.error_const() * h_next.powi(k as i32);
 
-        alpha * &self.divided_diff(n + 1, n - 2)
+        alpha * &self.divided_diff(n + 1, n + 1 - k)
     }
 
     pub fn divided_diff(&self, n_max: usize, n_min: usize) -> Array1<f64> {
@@ -54,4 +73,80 @@ impl StateHistory {
             alpha * (&self.divided_diff(n_max, n_min + 1) - &self.divided_diff(n_max - 1, n_min))
         }
     }
+
+    // Linear interpolation onto t = start, start + step, ... up to stop. The
+    // initial state at t = 0 isn't part of the history, it comes in `x0`.
+    pub fn sample_uniform(
+        &self,
+        x0: &Array1<f64>,
+        start: f64,
+        stop: f64,
+        step: f64,
+    ) -> Vec<Record> {
+        let initial = Record {
+            n_iters: 0,
+            x: x0.clone(),
+            t: 0.0,
+        };
+        let points = std::iter::once(&initial)
+            .chain(self.data.iter())
+            .collect::<Vec<_>>();
+        // Don't lose the last sample to rounding
+        let n_samples = ((stop - start) / step + 1e-9).floor() as usize + 1;
+
+        let mut i = 0;
+        (0..n_samples)
+            .map(|k| {
+                let t = start + k as f64 * step;
+                while i + 2 < points.len() && points[i + 1].t < t {
+                    i += 1;
+                }
+                let (a, b) = (points[i], points[i + 1]);
+                let w = ((t - a.t) / (b.t - a.t)).clamp(0.0, 1.0);
+
+                Record {
+                    n_iters: b.n_iters,
+                    x: (1.0 - w) * &a.x + w * &b.x,
+                    t,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp() -> StateHistory {
+        let mut hist = StateHistory::new();
+        for (n_iters, t) in [(1, 0.3), (2, 1.0), (3, 2.5)] {
+            hist.push(n_iters, &array![2.0 * t, 1.0], t);
+        }
+        hist
+    }
+
+    #[test]
+    fn test_sample_uniform() {
+        let samples = ramp().sample_uniform(&array![0.0, 1.0], 0.0, 2.0, 0.5);
+
+        let t = samples.iter().map(|r| r.t).collect::<Vec<_>>();
+        assert_eq!(t, [0.0, 0.5, 1.0, 1.5, 2.0]);
+        for r in samples.iter() {
+            assert!((r.x[0] - 2.0 * r.t).abs() < 1e-12);
+            assert_eq!(r.x[1], 1.0);
+        }
+        assert_eq!(samples[1].n_iters, 2);
+        assert_eq!(samples[4].n_iters, 3);
+    }
+
+    #[test]
+    fn test_sample_uniform_start() {
+        let samples = ramp().sample_uniform(&array![0.0, 1.0], 1.2, 2.5, 0.1);
+
+        assert_eq!(samples.len(), 14);
+        assert_eq!(samples[0].t, 1.2);
+        assert!((samples[13].t - 2.5).abs() < 1e-12);
+        assert!((samples[13].x[0] - 5.0).abs() < 1e-12);
+    }
 }
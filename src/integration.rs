@@ -0,0 +1,120 @@
+// Numerical integration of the capacitor and inductor equations in transient
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Method {
+    BackwardEuler,
+    #[default]
+    Trapezoidal,
+    Gear2,
+}
+
+impl Method {
+    // Order of the local truncation error, h^(order + 1)
+    pub fn order(&self) -> usize {
+        match self {
+            Method::BackwardEuler => 1,
+            Method::Trapezoidal | Method::Gear2 => 2,
+        }
+    }
+
+    // Error constant C such that LTE = C h^(order + 1) x^(order + 1)
+    pub fn error_const(&self) -> f64 {
+        match self {
+            Method::BackwardEuler => -1.0 / 2.0,
+            Method::Trapezoidal => -1.0 / 12.0,
+            Method::Gear2 => -2.0 / 9.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeStep {
+    pub method: Method,
+    pub h: f64,
+    // Length of the step before, only Gear2 looks that far back
+    pub h_prev: f64,
+}
+
+// The derivative at the end of the step is estimated as
+// x'(n+1) = (a0 x(n+1) + a1 x(n) + a2 x(n-1)) / h + b1 x'(n)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coeffs {
+    pub a0: f64,
+    pub a1: f64,
+    pub a2: f64,
+    pub b1: f64,
+}
+
+impl TimeStep {
+    pub fn new(method: Method, h: f64, h_prev: f64) -> Self {
+        TimeStep { method, h, h_prev }
+    }
+
+    pub fn coeffs(&self) -> Coeffs {
+        match self.method {
+            Method::BackwardEuler => Coeffs {
+                a0: 1.0,
+                a1: -1.0,
+                a2: 0.0,
+                b1: 0.0,
+            },
+            Method::Trapezoidal => Coeffs {
+                a0: 2.0,
+                a1: -2.0,
+                a2: 0.0,
+                b1: -1.0,
+            },
+            Method::Gear2 => {
+                // Variable step BDF2, w = 1 is the textbook 3/2, -2, 1/2
+                let w = self.h / self.h_prev;
+
+                Coeffs {
+                    a0: (1.0 + 2.0 * w) / (1.0 + w),
+                    a1: -(1.0 + w),
+                    a2: w * w / (1.0 + w),
+                    b1: 0.0,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Applies the estimate to samples of `f` ending at t = 1
+    fn derivative(step: &TimeStep, f: impl Fn(f64) -> f64, df: impl Fn(f64) -> f64) -> f64 {
+        let c = step.coeffs();
+        let t = 1.0;
+
+        (c.a0 * f(t) + c.a1 * f(t - step.h) + c.a2 * f(t - step.h - step.h_prev)) / step.h
+            + c.b1 * df(t - step.h)
+    }
+
+    #[test]
+    fn test_coeffs_exact_for_lines() {
+        for method in [Method::BackwardEuler, Method::Trapezoidal, Method::Gear2] {
+            let step = TimeStep::new(method, 0.1, 0.3);
+            let d = derivative(&step, |t| 3.0 * t + 1.0, |_| 3.0);
+
+            assert!((d - 3.0).abs() < 1e-12, "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn test_coeffs_exact_for_parabolas() {
+        for method in [Method::Trapezoidal, Method::Gear2] {
+            let step = TimeStep::new(method, 0.1, 0.3);
+            let d = derivative(&step, |t| t * t, |t| 2.0 * t);
+
+            assert!((d - 2.0).abs() < 1e-12, "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn test_coeffs_gear2_constant_step() {
+        let c = TimeStep::new(Method::Gear2, 1e-9, 1e-9).coeffs();
+
+        assert_eq!((c.a0, c.a1, c.a2, c.b1), (1.5, -2.0, 0.5, 0.0));
+    }
+}
@@ -0,0 +1,23 @@
+#![allow(clippy::upper_case_acronyms)]
+
+extern crate ndarray;
+extern crate num_complex;
+extern crate pest;
+#[macro_use]
+extern crate pest_derive;
+
+pub mod circuit;
+pub mod command;
+pub mod device;
+pub mod engine;
+pub mod integration;
+pub mod node;
+pub mod node_collection;
+pub mod parser;
+pub mod sparse;
+pub mod spice_fn;
+
+pub use circuit::Circuit;
+pub use device::Stamp;
+pub use engine::sim_result::SimResult;
+pub use engine::Engine;
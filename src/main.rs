@@ -1,47 +1,88 @@
 use std::env;
+use std::process;
 
-extern crate ndarray;
-extern crate pest;
-#[macro_use]
-extern crate pest_derive;
+use ftspice::engine::continuation::Strategy;
+use ftspice::parser::check_elems::Severity;
+use ftspice::parser::dialect::Dialect;
+use ftspice::parser::error::ParseError;
+use ftspice::{engine, parser};
 
-use crate::engine::error::NotConvergedError;
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
 
-mod command;
-mod device;
-mod engine;
-mod node;
-mod node_collection;
-mod parser;
-mod spice_fn;
-
-fn main() -> Result<(), NotConvergedError> {
-    let args: Vec<String> = env::args().collect();
+    let dialect = match args.iter().position(|a| a == "--spice3") {
+        Some(i) => {
+            args.remove(i);
+            Dialect::Spice3
+        }
+        None => Dialect::Native,
+    };
 
     let file = &args
-        .get(1)
+        .first()
         .expect("Insufficient arguments. Specify spice file to simulate.");
 
-    let (elems, cmds) = parser::parse_spice_file(file);
-
-    parser::check_elems::check_elems(&elems);
+    let sources = match parser::source::Sources::load(file, dialect) {
+        Ok(sources) => sources,
+        Err(errors) => exit_with_parse_errors(file, &errors),
+    };
+    let ((elems, cmds), rebuild) = match parser::parse_sources_with_rebuild(&sources) {
+        Ok(parsed) => parsed,
+        Err(errors) => exit_with_parse_errors(file, &errors),
+    };
 
     let mut engine = engine::Engine::new(elems, cmds);
+    engine.rebuild = Some(rebuild);
 
-    if let Some(_) = engine.op_cmd {
-        let res = engine.run_op()?;
-        res.print();
+    let findings = engine.check();
+    for f in findings.iter() {
+        eprintln!("{}", f);
     }
+    let n_errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    if n_errors > 0 {
+        eprintln!("{}: {} error(s) found", file, n_errors);
+        process::exit(1);
+    }
+
+    // Each block of results is labelled with its analysis when there are several
+    let labelled = engine.analyses.len() > 1;
 
-    if let Some(_) = engine.dc_cmd {
-        let res = engine.run_dc()?;
+    for (i, analysis) in engine.analyses.clone().iter().enumerate() {
+        let res = match engine.run(analysis) {
+            Ok(res) => res,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        };
+        report_op_strategy(&mut engine);
+
+        if labelled {
+            if i > 0 {
+                println!();
+            }
+            println!("# {}", analysis);
+        }
         res.print();
     }
+}
 
-    if let Some(_) = engine.tran_cmd {
-        let res = engine.run_tran()?;
-        res.print();
+fn exit_with_parse_errors(file: &str, errors: &[ParseError]) -> ! {
+    for e in errors.iter() {
+        eprintln!("{}\n", e);
     }
+    eprintln!("{}: {} error(s) found", file, errors.len());
+    process::exit(1);
+}
 
-    Ok(())
+// Only worth mentioning when plain Newton wasn't enough
+fn report_op_strategy(engine: &mut engine::Engine) {
+    if let Some(strategy) = engine.op_strategy.take() {
+        if strategy != Strategy::Newton {
+            eprintln!("Operating point found with {}", strategy);
+        }
+    }
 }
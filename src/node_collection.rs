@@ -50,13 +50,13 @@ impl NodeCollection {
         let mut nc = NodeCollection::from_elems(elems);
         let nc_len = nc.data.len();
 
-        fn is_startup_elem(e: &Box<dyn Stamp>) -> bool {
+        fn is_startup_elem(e: &dyn Stamp) -> bool {
             matches!((e.gtype(), e.gtype_startup()), (GType::G1, GType::G2))
         }
 
         let i_names = elems
             .iter()
-            .filter(|e| is_startup_elem(e))
+            .filter(|e| is_startup_elem(e.as_ref()))
             .map(|e| e.get_name())
             .collect::<BTreeSet<_>>();
 
@@ -77,6 +77,10 @@ impl NodeCollection {
         self.data.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
     pub fn get_idx(&self, name: &str) -> Option<usize> {
         self.data.get(name).map(|x| x.idx)
     }
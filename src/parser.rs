@@ -1,281 +1,1120 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
 
 use crate::command;
+use crate::command::Source;
 use crate::device;
 use crate::device::Stamp;
-use crate::spice_fn::{ExpParams, PulseParams, SineParams, SpiceFn};
+use crate::integration::Method;
+use crate::parser::dialect::Dialect;
+use crate::parser::error::{ParseError, ParseResult};
+use crate::parser::models::{Model, ModelTable};
+use crate::parser::params::ParamDefs;
+use crate::parser::scope::Scope;
+use crate::parser::subckt::SubcktTable;
+use crate::spice_fn::{ExpParams, PulseParams, PwlParams, SineParams, SpiceFn};
 
-use pest::iterators::Pair;
-use pest::Parser;
+use pest::iterators::{Pair, Pairs};
 
 pub mod check_elems;
+pub mod dialect;
+pub mod error;
+pub mod expr;
+pub mod models;
+pub mod params;
+pub mod scope;
+pub mod source;
+pub mod subckt;
 
 #[derive(Parser)]
 #[grammar = "spice.pest"]
 pub struct SpiceParser;
 
-pub fn parse_spice_file(file: &str) -> (Vec<Box<dyn Stamp>>, Vec<command::Command>) {
+pub type Netlist = (Vec<Box<dyn Stamp>>, Vec<command::Command>);
+
+// Builds the elements again with some `.param` values replaced, for `.dc` sweeps over them
+pub type Rebuild<'a> =
+    Box<dyn Fn(&HashMap<String, f64>) -> Result<Vec<Box<dyn Stamp>>, Vec<ParseError>> + 'a>;
+
+pub fn parse_spice_file(file: &str, dialect: Dialect) -> Result<Netlist, Vec<ParseError>> {
+    parse_spice_file_with_params(file, dialect, &HashMap::new())
+}
+
+// Reads the netlist with some `.param` values replaced
+pub fn parse_spice_file_with_params(
+    file: &str,
+    dialect: Dialect,
+    overrides: &HashMap<String, f64>,
+) -> Result<Netlist, Vec<ParseError>> {
+    let sources = source::Sources::load(file, dialect)?;
+    let lines = sources.lines().map_err(|e| vec![e])?;
+    parse_lines(&lines, dialect, overrides)
+}
+
+// Also gives a closure that builds the elements again from the lines parsed
+// here, only the `.param` values and what depends on them are evaluated again
+pub fn parse_sources_with_rebuild(
+    sources: &source::Sources,
+) -> Result<(Netlist, Rebuild<'_>), Vec<ParseError>> {
+    let dialect = sources.dialect();
+    let lines = sources.lines().map_err(|e| vec![e])?;
+    let netlist = parse_lines(&lines, dialect, &HashMap::new())?;
+
+    let rebuild: Rebuild =
+        Box::new(move |overrides| parse_lines(&lines, dialect, overrides).map(|(elems, _)| elems));
+
+    Ok((netlist, rebuild))
+}
+
+fn parse_lines(
+    lines: &[source::Line],
+    dialect: Dialect,
+    overrides: &HashMap<String, f64>,
+) -> Result<Netlist, Vec<ParseError>> {
     let mut elems = Vec::new();
     let mut cmds = Vec::new();
+    let mut errors = Vec::new();
+
+    let mut param_defs = ParamDefs::new();
+    for cmd in lines.iter().filter(|l| l.pair.as_rule() == Rule::param_cmd) {
+        for (name, def) in params::parse_param_cmd(cmd.pair.clone(), cmd.file, dialect) {
+            if param_defs.contains_key(&name) {
+                let msg = format!("Duplicate parameter {} found!", name);
+                errors.push(ParseError::at(&def.assign, msg).in_file(cmd.file));
+                continue;
+            }
+            param_defs.insert(name, def);
+        }
+    }
+
+    // Everything below depends on the parameters, don't pile up errors caused by a bad one
+    let globals = params::resolve_params(&param_defs, overrides).map_err(|e| vec![e])?;
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let scope = Scope::new(globals, dialect);
+
+    let mut models = models::builtin_models();
+    let mut model_names = Vec::new();
+    for card in lines
+        .iter()
+        .filter(|l| l.pair.as_rule() == Rule::model_card)
+    {
+        let (name, model) = match models::parse_model_card(card.pair.clone(), &scope) {
+            Ok(m) => m,
+            Err(e) => {
+                errors.push(e.in_file(card.file));
+                continue;
+            }
+        };
+        if model_names.contains(&name) {
+            let msg = format!("Duplicate model {} found!", name);
+            errors.push(ParseError::at(&card.pair, msg).in_file(card.file));
+            continue;
+        }
+        model_names.push(name.clone());
+        models.insert(name, model);
+    }
+
+    let mut subckts = SubcktTable::new();
+    for def in lines.iter().filter(|l| l.pair.as_rule() == Rule::subckt) {
+        let subckt = subckt::parse_subckt(def.pair.clone(), def.file);
+        let key = subckt.name.to_lowercase();
+        if subckts.contains_key(&key) {
+            let msg = format!("Duplicate subcircuit {} found!", subckt.name);
+            errors.push(ParseError::at(&def.pair, msg).in_file(def.file));
+            continue;
+        }
+        subckts.insert(key, subckt);
+    }
 
-    let unparsed_file = fs::read_to_string(file).expect("Cannot read file.");
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
-    let file = SpiceParser::parse(Rule::file, &unparsed_file)
-        .expect("Unsuccessful parse")
-        .next()
-        .unwrap(); // unwrap `file` rule, never fails
-
-    for line in file.into_inner() {
-        match line.as_rule() {
-            Rule::node => {
-                let node = line.into_inner().next().unwrap();
-
-                let e: Box<dyn Stamp> = match node.as_rule() {
-                    Rule::r_node => Box::new(parse_res(node)),
-                    Rule::v_node => Box::new(parse_vdd(node)),
-                    Rule::i_node => Box::new(parse_idd(node)),
-                    Rule::ind_node => Box::new(parse_ind(node)),
-                    Rule::cap_node => Box::new(parse_cap(node)),
-                    Rule::dio_node => Box::new(parse_dio(node)),
-                    Rule::bjt_node => Box::new(parse_bjt(node)),
-                    Rule::mos_node => Box::new(parse_mos(node)),
+    for line in lines.iter() {
+        let pair = line.pair.clone();
+        let res = match pair.as_rule() {
+            Rule::node => parse_node(
+                pair,
+                &scope.in_file(line.file),
+                &models,
+                &subckts,
+                &mut elems,
+            ),
+            Rule::command => {
+                let cmd = pair.into_inner().next().unwrap();
+
+                let cmd = match cmd.as_rule() {
+                    Rule::options_cmd => parse_options_cmd(cmd, &scope),
+                    Rule::ic_cmd => parse_node_values(cmd, &scope).map(command::Command::IC),
+                    Rule::nodeset_cmd => {
+                        parse_node_values(cmd, &scope).map(command::Command::NodeSet)
+                    }
+                    Rule::probe_cmd => Ok(parse_probe_cmd(cmd)),
+                    Rule::op_cmd => Ok(parse_op_cmd()),
+                    Rule::dc_cmd => parse_dc_cmd(cmd, &scope),
+                    Rule::tran_cmd => parse_tran_cmd(cmd, &scope),
+                    Rule::ac_cmd => parse_ac_cmd(cmd, &scope),
                     _ => unreachable!(),
                 };
-                elems.push(e);
+                cmd.map(|c| cmds.push(c))
             }
-            Rule::command => {
-                let cmd = line.into_inner().next().unwrap();
+            Rule::model_card => Ok(()),
+            Rule::subckt => Ok(()),
+            Rule::param_cmd => Ok(()),
+            _ => unreachable!(),
+        };
 
-                match cmd.as_rule() {
-                    Rule::op_cmd => cmds.push(parse_op_cmd()),
-                    Rule::dc_cmd => cmds.push(parse_dc_cmd(cmd)),
-                    Rule::tran_cmd => cmds.push(parse_tran_cmd(cmd)),
-                    _ => unreachable!(),
+        if let Err(e) = res {
+            errors.push(e.in_file(line.file));
+        }
+    }
+
+    // `.ic` and `.nodeset` may come before the elements on their nodes
+    if errors.is_empty() {
+        errors.extend(check_node_refs(lines, &elems));
+        errors.extend(check_sweep_refs(lines, &elems));
+        errors.extend(check_probe_refs(lines, &elems));
+    }
+
+    match errors.is_empty() {
+        true => Ok((elems, cmds)),
+        false => Err(errors),
+    }
+}
+
+fn check_node_refs(lines: &[source::Line], elems: &[Box<dyn Stamp>]) -> Vec<ParseError> {
+    let nodes = elems
+        .iter()
+        .flat_map(|e| e.get_nodes().iter())
+        .collect::<HashSet<_>>();
+
+    lines
+        .iter()
+        .filter(|l| l.pair.as_rule() == Rule::command)
+        .flat_map(|l| l.pair.clone().into_inner().map(move |cmd| (cmd, l.file)))
+        .filter(|(cmd, _)| matches!(cmd.as_rule(), Rule::ic_cmd | Rule::nodeset_cmd))
+        .flat_map(|(cmd, file)| cmd.into_inner().map(move |v| (v, file)))
+        .map(|(node_value, file)| (node_value.into_inner().next().unwrap(), file))
+        .filter(|(node, _)| !nodes.contains(&node.as_str().to_string()))
+        .map(|(node, file)| {
+            let msg = format!("Node {} not found!", node.as_str());
+            ParseError::at(&node, msg).in_file(file)
+        })
+        .collect()
+}
+
+// Only elements with a single value can be swept, not semiconductors or subcircuits
+fn check_sweep_refs(lines: &[source::Line], elems: &[Box<dyn Stamp>]) -> Vec<ParseError> {
+    lines
+        .iter()
+        .filter(|l| l.pair.as_rule() == Rule::command)
+        .flat_map(|l| l.pair.clone().into_inner().map(move |cmd| (cmd, l.file)))
+        .filter(|(cmd, _)| cmd.as_rule() == Rule::dc_cmd)
+        .flat_map(|(cmd, file)| cmd.into_inner().map(move |s| (s, file)))
+        .map(|(sweep, file)| (sweep.into_inner().next().unwrap(), file))
+        .filter(|(var, _)| var.as_rule() == Rule::name)
+        .filter_map(|(var, file)| {
+            let name = var.as_str();
+            let msg = match elems.iter().find(|e| e.get_name() == name) {
+                None => format!("Element {} not found!", name),
+                Some(e) if e.get_value().is_none() => {
+                    format!("Element {} has no value to sweep", name)
                 }
-            }
-            Rule::EOI => (),
+                Some(_) => return None,
+            };
+            Some(ParseError::at(&var, msg).in_file(file))
+        })
+        .collect()
+}
+
+// Two terminal elements are probed by name alone, the others on one of their terminals
+fn check_probe_refs(lines: &[source::Line], elems: &[Box<dyn Stamp>]) -> Vec<ParseError> {
+    lines
+        .iter()
+        .filter(|l| l.pair.as_rule() == Rule::command)
+        .flat_map(|l| l.pair.clone().into_inner().map(move |cmd| (cmd, l.file)))
+        .filter(|(cmd, _)| cmd.as_rule() == Rule::probe_cmd)
+        .flat_map(|(cmd, file)| cmd.into_inner().map(move |p| (p, file)))
+        .filter_map(|(probe, file)| {
+            let mut details = probe.clone().into_inner();
+            let name = details.next().unwrap().as_str();
+            let terminal = details.next().map(|t| t.as_str().to_lowercase());
+
+            let terminals = match elems.iter().find(|e| e.get_name() == name) {
+                Some(e) => e.terminals(),
+                None => {
+                    let msg = format!("Element {} not found!", name);
+                    return Some(ParseError::at(&probe, msg).in_file(file));
+                }
+            };
+            let msg = match terminal {
+                None if !terminals.is_empty() => format!(
+                    "Element {} needs a terminal, one of {}",
+                    name,
+                    terminals.join(", ")
+                ),
+                Some(t) if !terminals.contains(&t.as_str()) => {
+                    format!("Element {} has no terminal {}", name, t)
+                }
+                _ => return None,
+            };
+            Some(ParseError::at(&probe, msg).in_file(file))
+        })
+        .collect()
+}
+
+fn parse_node(
+    line: Pair<Rule>,
+    scope: &Scope,
+    models: &ModelTable,
+    subckts: &SubcktTable,
+    elems: &mut Vec<Box<dyn Stamp>>,
+) -> ParseResult<()> {
+    let node = line.into_inner().next().unwrap();
+
+    let e: Box<dyn Stamp> = match node.as_rule() {
+        Rule::r_node => Box::new(parse_res(node, scope)?),
+        Rule::v_node => Box::new(parse_vdd(node, scope)?),
+        Rule::i_node => Box::new(parse_idd(node, scope)?),
+        Rule::e_node => Box::new(parse_vcvs(node, scope)?),
+        Rule::f_node => Box::new(parse_cccs(node, scope)?),
+        Rule::g_node => Box::new(parse_vccs(node, scope)?),
+        Rule::h_node => Box::new(parse_ccvs(node, scope)?),
+        Rule::ind_node => Box::new(parse_ind(node, scope)?),
+        Rule::cap_node => Box::new(parse_cap(node, scope)?),
+        Rule::dio_node => Box::new(parse_dio(node, scope, models)?),
+        Rule::bjt_node => Box::new(parse_bjt(node, scope, models)?),
+        Rule::mos_node => Box::new(parse_mos(node, scope, models)?),
+        Rule::x_node => return parse_instance(node, scope, models, subckts, elems),
+        _ => unreachable!(),
+    };
+    elems.push(e);
+
+    Ok(())
+}
+
+fn parse_instance(
+    node: Pair<Rule>,
+    scope: &Scope,
+    models: &ModelTable,
+    subckts: &SubcktTable,
+    elems: &mut Vec<Box<dyn Stamp>>,
+) -> ParseResult<()> {
+    let mut node_details = node.clone().into_inner();
+    let name = scope.elem_name(node_details.next().unwrap().as_str());
+
+    let mut args = Vec::new();
+    let mut overrides = Vec::new();
+    for item in node_details {
+        match item.as_rule() {
+            Rule::x_arg => args.push(item),
+            Rule::subckt_params => overrides = subckt::parse_params(item),
             _ => unreachable!(),
         }
     }
 
-    (elems, cmds)
+    let subckt_arg = args.pop().unwrap();
+    let subckt_name = subckt_arg.as_str();
+    let subckt = subckts.get(&subckt_name.to_lowercase()).ok_or_else(|| {
+        let msg = format!("Subcircuit {} of {} not found", subckt_name, name);
+        ParseError::at(&subckt_arg, msg)
+    })?;
+
+    if scope.is_expanding(&subckt.name) {
+        let msg = format!("Subcircuit {} of {} instantiates itself", subckt.name, name);
+        return Err(ParseError::at(&subckt_arg, msg));
+    }
+    if args.len() != subckt.ports.len() {
+        let msg = format!(
+            "{} connects {} nodes to subcircuit {} with {} ports",
+            name,
+            args.len(),
+            subckt.name,
+            subckt.ports.len()
+        );
+        return Err(ParseError::at(&node, msg));
+    }
+
+    let ports = subckt
+        .ports
+        .iter()
+        .zip(args)
+        .map(|(port, arg)| (port.clone(), scope.node_name(arg.as_str())))
+        .collect();
+
+    let mut given = HashMap::new();
+    for (param, value) in overrides {
+        if !subckt.params.iter().any(|(p, _)| *p == param) {
+            let msg = format!(
+                "Unknown parameter {} for subcircuit {} in {}",
+                param, subckt.name, name
+            );
+            return Err(ParseError::at(&value, msg));
+        }
+        given.insert(param, parse_value(value, scope)?);
+    }
+
+    // Defaults in order, each one seeing the values before it
+    let mut params = HashMap::new();
+    for (param, value) in subckt.params.iter() {
+        let value = match given.get(param) {
+            Some(v) => *v,
+            None => parse_value(value.clone(), &scope.with_params(&params))
+                .map_err(|e| e.in_file(subckt.file))?,
+        };
+        params.insert(param.clone(), value);
+    }
+
+    let inner = scope
+        .instance(&name, &subckt.name, ports, params)
+        .in_file(subckt.file);
+    for line in subckt.body.iter() {
+        parse_node(line.clone(), &inner, models, subckts, elems)
+            .map_err(|e| e.in_file(subckt.file))?;
+    }
+
+    Ok(())
 }
 
-fn parse_res(node: Pair<Rule>) -> device::res::Res {
+fn parse_res(node: Pair<Rule>, scope: &Scope) -> ParseResult<device::res::Res> {
     let mut node_details = node.into_inner();
 
     let name = node_details.next().unwrap().as_str();
     let node_0 = node_details.next().unwrap().as_str();
     let node_1 = node_details.next().unwrap().as_str();
 
-    let val = parse_value(node_details.next().unwrap());
+    let val = parse_value(node_details.next().unwrap(), scope)?;
 
-    device::res::Res {
-        name: String::from(name),
-        nodes: vec![String::from(node_0), String::from(node_1)],
+    Ok(device::res::Res {
+        name: scope.elem_name(name),
+        nodes: vec![scope.node_name(node_0), scope.node_name(node_1)],
         val,
-    }
+    })
 }
 
-fn parse_vdd(node: Pair<Rule>) -> device::vdd::Vdd {
+fn parse_vdd(node: Pair<Rule>, scope: &Scope) -> ParseResult<device::vdd::Vdd> {
     let mut node_details = node.into_inner();
     let name = node_details.next().unwrap().as_str();
     let node_1 = node_details.next().unwrap().as_str();
     let node_0 = node_details.next().unwrap().as_str();
 
-    let val_details = node_details.next().unwrap().into_inner().next().unwrap();
-    let val;
-    let tran_fn;
+    let source = parse_source_value(node_details, scope)?;
 
-    match val_details.as_rule() {
-        Rule::v_dc_value => {
-            val = parse_value(val_details.into_inner().next().unwrap());
-            tran_fn = None;
-        }
-        Rule::fn_value => {
-            let spice_fn = parse_spice_fn(val_details.into_inner().next().unwrap());
-            val = spice_fn.eval(&0.0).clone();
-            tran_fn = Some(spice_fn);
-        }
-        _ => unreachable!(),
-    };
+    Ok(device::vdd::Vdd {
+        name: scope.elem_name(name),
+        nodes: vec![scope.node_name(node_0), scope.node_name(node_1)],
+        val: source.dc,
+        ac_mag: source.ac_mag,
+        ac_phase: source.ac_phase,
+        tran_fn: source.tran_fn,
+    })
+}
 
-    device::vdd::Vdd {
-        name: String::from(name),
-        nodes: vec![String::from(node_0), String::from(node_1)],
-        val,
-        tran_fn,
+fn parse_idd(node: Pair<Rule>, scope: &Scope) -> ParseResult<device::idd::Idd> {
+    let mut node_details = node.into_inner();
+    let name = node_details.next().unwrap().as_str();
+    let mut node_1 = node_details.next().unwrap().as_str();
+    let mut node_0 = node_details.next().unwrap().as_str();
+
+    // SPICE3 current flows from the first node to the second through the source
+    if scope.dialect == Dialect::Spice3 {
+        (node_0, node_1) = (node_1, node_0);
     }
+
+    let source = parse_source_value(node_details, scope)?;
+
+    Ok(device::idd::Idd {
+        name: scope.elem_name(name),
+        nodes: vec![scope.node_name(node_0), scope.node_name(node_1)],
+        val: source.dc,
+        ac_mag: source.ac_mag,
+        ac_phase: source.ac_phase,
+        tran_fn: source.tran_fn,
+    })
+}
+
+fn parse_vcvs(node: Pair<Rule>, scope: &Scope) -> ParseResult<device::vcvs::VCVS> {
+    let mut node_details = node.into_inner();
+    let name = node_details.next().unwrap().as_str();
+    let nodes = node_details
+        .by_ref()
+        .take(4)
+        .map(|n| scope.node_name(n.as_str()))
+        .collect();
+    let value = parse_value(node_details.next().unwrap(), scope)?;
+
+    Ok(device::vcvs::VCVS {
+        name: scope.elem_name(name),
+        nodes,
+        val: value,
+    })
+}
+
+fn parse_vccs(node: Pair<Rule>, scope: &Scope) -> ParseResult<device::vccs::VCCS> {
+    let mut node_details = node.into_inner();
+    let name = node_details.next().unwrap().as_str();
+    let nodes = node_details
+        .by_ref()
+        .take(4)
+        .map(|n| scope.node_name(n.as_str()))
+        .collect();
+    let value = parse_value(node_details.next().unwrap(), scope)?;
+
+    Ok(device::vccs::VCCS {
+        name: scope.elem_name(name),
+        nodes,
+        val: value,
+    })
 }
 
-fn parse_idd(node: Pair<Rule>) -> device::idd::Idd {
+fn parse_cccs(node: Pair<Rule>, scope: &Scope) -> ParseResult<device::cccs::CCCS> {
     let mut node_details = node.into_inner();
     let name = node_details.next().unwrap().as_str();
+    let node_0 = node_details.next().unwrap().as_str();
     let node_1 = node_details.next().unwrap().as_str();
+    let ctrl = node_details.next().unwrap().as_str();
+    let value = parse_value(node_details.next().unwrap(), scope)?;
+
+    Ok(device::cccs::CCCS {
+        name: scope.elem_name(name),
+        nodes: vec![scope.node_name(node_0), scope.node_name(node_1)],
+        ctrl: scope.elem_name(ctrl),
+        val: value,
+    })
+}
+
+fn parse_ccvs(node: Pair<Rule>, scope: &Scope) -> ParseResult<device::ccvs::CCVS> {
+    let mut node_details = node.into_inner();
+    let name = node_details.next().unwrap().as_str();
     let node_0 = node_details.next().unwrap().as_str();
+    let node_1 = node_details.next().unwrap().as_str();
+    let ctrl = node_details.next().unwrap().as_str();
+    let value = parse_value(node_details.next().unwrap(), scope)?;
 
-    let val_details = node_details.next().unwrap().into_inner().next().unwrap();
-    let val;
-    let tran_fn;
+    Ok(device::ccvs::CCVS {
+        name: scope.elem_name(name),
+        nodes: vec![scope.node_name(node_0), scope.node_name(node_1)],
+        ctrl: scope.elem_name(ctrl),
+        val: value,
+    })
+}
 
-    match val_details.as_rule() {
-        Rule::i_dc_value => {
-            val = parse_value(val_details.into_inner().next().unwrap());
-            tran_fn = None;
-        }
-        Rule::fn_value => {
-            let spice_fn = parse_spice_fn(val_details.into_inner().next().unwrap());
-            val = spice_fn.eval(&0.0).clone();
-            tran_fn = Some(spice_fn);
+// DC value, AC magnitude and phase and transient function of an independent
+// source, in any order. Without a DC value the function at t = 0 sets the
+// operating point.
+fn parse_source_value(values: Pairs<Rule>, scope: &Scope) -> ParseResult<Source> {
+    let mut dc = None;
+    let mut ac = None;
+    let mut tran_fn = None;
+
+    for item in values {
+        match item.as_rule() {
+            Rule::dc_value if dc.is_some() => {
+                return Err(ParseError::at(&item, String::from("Duplicate DC value")));
+            }
+            Rule::dc_value => dc = Some(parse_value(item.into_inner().next().unwrap(), scope)?),
+            Rule::ac_value if ac.is_some() => {
+                return Err(ParseError::at(&item, String::from("Duplicate AC value")));
+            }
+            Rule::ac_value => {
+                let mut ac_details = item.into_inner();
+                let mag = parse_value(ac_details.next().unwrap(), scope)?;
+                let phase = match ac_details.next() {
+                    Some(p) => parse_value(p, scope)?,
+                    None => 0.0,
+                };
+                ac = Some((mag, phase));
+            }
+            Rule::fn_value if tran_fn.is_some() => {
+                let msg = String::from("Duplicate transient function");
+                return Err(ParseError::at(&item, msg));
+            }
+            Rule::fn_value => {
+                tran_fn = Some(parse_spice_fn(item.into_inner().next().unwrap(), scope)?)
+            }
+            _ => unreachable!(),
         }
-        _ => unreachable!(),
+    }
+
+    let dc = match (dc, &tran_fn) {
+        (Some(val), _) => val,
+        (None, Some(f)) => f.eval(&0.0),
+        (None, None) => 0.0,
     };
+    let (ac_mag, ac_phase) = ac.unwrap_or((0.0, 0.0));
 
-    device::idd::Idd {
-        name: String::from(name),
-        nodes: vec![String::from(node_0), String::from(node_1)],
-        val,
+    Ok(Source {
+        dc,
+        ac_mag,
+        ac_phase,
         tran_fn,
-    }
+    })
 }
 
-fn parse_ind(node: Pair<Rule>) -> device::ind::Ind {
+fn parse_ind(node: Pair<Rule>, scope: &Scope) -> ParseResult<device::ind::Ind> {
     let mut node_details = node.into_inner();
     let name = node_details.next().unwrap().as_str();
     let node_1 = node_details.next().unwrap().as_str();
     let node_0 = node_details.next().unwrap().as_str();
-    let value = parse_value(node_details.next().unwrap());
+    let value = parse_value(node_details.next().unwrap(), scope)?;
+    let ic = match node_details.next() {
+        Some(ic_param) => Some(parse_value(ic_param.into_inner().next().unwrap(), scope)?),
+        None => None,
+    };
 
-    device::ind::Ind {
-        name: String::from(name),
-        nodes: vec![String::from(node_0), String::from(node_1)],
+    Ok(device::ind::Ind {
+        name: scope.elem_name(name),
+        nodes: vec![scope.node_name(node_0), scope.node_name(node_1)],
         val: value,
         u_curr: None,
         i_curr: None,
-    }
+        i_prev: None,
+        ic,
+    })
 }
 
-fn parse_cap(node: Pair<Rule>) -> device::cap::Cap {
+fn parse_cap(node: Pair<Rule>, scope: &Scope) -> ParseResult<device::cap::Cap> {
     let mut node_details = node.into_inner();
     let name = node_details.next().unwrap().as_str();
     let node_1 = node_details.next().unwrap().as_str();
     let node_0 = node_details.next().unwrap().as_str();
-    let value = parse_value(node_details.next().unwrap());
+    let value = parse_value(node_details.next().unwrap(), scope)?;
+    let ic = match node_details.next() {
+        Some(ic_param) => Some(parse_value(ic_param.into_inner().next().unwrap(), scope)?),
+        None => None,
+    };
 
-    device::cap::Cap {
-        name: String::from(name),
-        nodes: vec![String::from(node_0), String::from(node_1)],
+    Ok(device::cap::Cap {
+        name: scope.elem_name(name),
+        nodes: vec![scope.node_name(node_0), scope.node_name(node_1)],
         val: value,
         u_curr: None,
         i_curr: None,
-    }
+        u_prev: None,
+        ic,
+    })
 }
 
-fn parse_dio(node: Pair<Rule>) -> device::diode::Diode {
+fn parse_dio(
+    node: Pair<Rule>,
+    scope: &Scope,
+    models: &ModelTable,
+) -> ParseResult<device::diode::Diode> {
     let mut node_details = node.into_inner();
     let name = node_details.next().unwrap().as_str();
-    let node_1 = node_details.next().unwrap().as_str();
-    let node_0 = node_details.next().unwrap().as_str();
+    let mut node_1 = node_details.next().unwrap().as_str();
+    let mut node_0 = node_details.next().unwrap().as_str();
+    let model_name = node_details.next().unwrap();
 
-    device::diode::Diode {
-        name: String::from(name),
-        nodes: vec![String::from(node_0), String::from(node_1)],
+    // SPICE3 writes the anode first
+    if scope.dialect == Dialect::Spice3 {
+        (node_0, node_1) = (node_1, node_0);
     }
+
+    let params = match models::get_model(models, &model_name)? {
+        Model::Diode(p) => *p,
+        _ => {
+            let msg = format!(
+                "Model {} of {} is not a diode model",
+                model_name.as_str(),
+                name
+            );
+            return Err(ParseError::at(&model_name, msg));
+        }
+    };
+
+    Ok(device::diode::Diode {
+        name: scope.elem_name(name),
+        nodes: vec![scope.node_name(node_0), scope.node_name(node_1)],
+        params,
+        vd_old: 0.0,
+    })
 }
 
-fn parse_bjt(node: Pair<Rule>) -> device::npn::NPN {
+fn parse_bjt(
+    node: Pair<Rule>,
+    scope: &Scope,
+    models: &ModelTable,
+) -> ParseResult<device::npn::NPN> {
     let mut node_details = node.into_inner();
     let name = node_details.next().unwrap().as_str();
     let node_0 = node_details.next().unwrap().as_str();
     let node_1 = node_details.next().unwrap().as_str();
     let node_2 = node_details.next().unwrap().as_str();
+    let mut model_name = node_details.next().unwrap();
+
+    // The substrate node is optional
+    if model_name.as_rule() == Rule::name {
+        model_name = node_details.next().unwrap();
+    }
 
-    device::npn::NPN {
-        name: String::from(name),
+    let params = match models::get_model(models, &model_name)? {
+        Model::NPN(p) => *p,
+        _ => {
+            let msg = format!(
+                "Model {} of {} is not an NPN model",
+                model_name.as_str(),
+                name
+            );
+            return Err(ParseError::at(&model_name, msg));
+        }
+    };
+
+    Ok(device::npn::NPN {
+        name: scope.elem_name(name),
         nodes: vec![
-            String::from(node_0),
-            String::from(node_1),
-            String::from(node_2),
+            scope.node_name(node_0),
+            scope.node_name(node_1),
+            scope.node_name(node_2),
         ],
-    }
+        params,
+        vbe_old: 0.0,
+        vbc_old: 0.0,
+    })
 }
 
-fn parse_mos(node: Pair<Rule>) -> device::nmos::NMOS {
+fn parse_mos(
+    node: Pair<Rule>,
+    scope: &Scope,
+    models: &ModelTable,
+) -> ParseResult<device::nmos::NMOS> {
     let mut node_details = node.into_inner();
 
     let name = node_details.next().unwrap().as_str();
     let node_0 = node_details.next().unwrap().as_str();
     let node_1 = node_details.next().unwrap().as_str();
     let node_2 = node_details.next().unwrap().as_str();
+    let _bulk = node_details.next().unwrap().as_str();
+    let model_name = node_details.next().unwrap();
+
+    let mut params = match models::get_model(models, &model_name)? {
+        Model::NMOS(p) => *p,
+        _ => {
+            let msg = format!(
+                "Model {} of {} is not an NMOS model",
+                model_name.as_str(),
+                name
+            );
+            return Err(ParseError::at(&model_name, msg));
+        }
+    };
+
+    let mut w = 1.0;
+    let mut l = 1.0;
+    for param in node_details {
+        let mut param_details = param.into_inner();
+        let param_name = param_details.next().unwrap();
+        let value = parse_value(param_details.next().unwrap(), scope)?;
+
+        match param_name.as_str().to_lowercase().as_str() {
+            "w" => w = value,
+            "l" => l = value,
+            // Junction geometry, only matters for capacitances we don't model
+            "ad" | "as" | "pd" | "ps" | "nrd" | "nrs" => (),
+            other => {
+                let msg = format!("Unknown parameter {} for {}, expected W or L", other, name);
+                return Err(ParseError::at(&param_name, msg));
+            }
+        }
+    }
+    params.kp *= w / l;
 
-    device::nmos::NMOS {
-        name: String::from(name),
+    Ok(device::nmos::NMOS {
+        name: scope.elem_name(name),
         nodes: vec![
-            String::from(node_0),
-            String::from(node_1),
-            String::from(node_2),
+            scope.node_name(node_0),
+            scope.node_name(node_1),
+            scope.node_name(node_2),
         ],
+        params,
+        vgs_old: 0.0,
+        vds_old: 0.0,
+    })
+}
+
+// What a numeric option accepts, and how to say it in the error
+type OptionCheck = (fn(f64) -> bool, &'static str);
+
+const POSITIVE: OptionCheck = (|x| x > 0.0, "positive");
+const NON_NEGATIVE: OptionCheck = (|x| x >= 0.0, "non-negative");
+const COUNT: OptionCheck = (|x| x >= 1.0 && x.fract() == 0.0, "a positive integer");
+const ABOVE_ABSOLUTE_ZERO: OptionCheck = (|x| x > -273.15, "above -273.15");
+const STEPS: OptionCheck = (|x| x >= 0.0 && x.fract() == 0.0, "a non-negative integer");
+
+fn parse_options_cmd(cmd: Pair<Rule>, scope: &Scope) -> ParseResult<command::Command> {
+    let mut options = command::OptionsParams::default();
+
+    for assign in cmd.into_inner() {
+        let mut assign_details = assign.into_inner();
+        let name = assign_details.next().unwrap();
+        let value = assign_details.next().unwrap();
+        let number = |(valid, expected): OptionCheck| {
+            parse_option_number(&name, &value, scope, valid, expected)
+        };
+
+        match name.as_str().to_lowercase().as_str() {
+            "reltol" => options.reltol = Some(number(POSITIVE)?),
+            "vntol" => options.vntol = Some(number(POSITIVE)?),
+            "abstol" => options.abstol = Some(number(POSITIVE)?),
+            "itl1" => options.itl1 = Some(number(COUNT)? as u64),
+            "itl4" => options.itl4 = Some(number(COUNT)? as u64),
+            "trtol" => options.trtol = Some(number(POSITIVE)?),
+            "gmin" => options.gmin = Some(number(NON_NEGATIVE)?),
+            "tmax" => options.tmax = Some(number(POSITIVE)?),
+            "method" => options.method = Some(parse_method(value)?),
+            "gminsteps" => options.gmin_steps = Some(number(STEPS)? as usize),
+            "srcsteps" => options.src_steps = Some(number(STEPS)? as usize),
+            "damping" => options.damping = Some(parse_option_switch(&name, value)?),
+            "interp" => options.interp = Some(parse_option_switch(&name, value)?),
+            "temp" => options.temp = Some(number(ABOVE_ABSOLUTE_ZERO)?),
+            _ => {
+                let msg = format!("Unknown option {}", name.as_str());
+                return Err(ParseError::at(&name, msg));
+            }
+        }
+    }
+
+    Ok(command::Command::Options(options))
+}
+
+fn parse_method(value: Pair<Rule>) -> ParseResult<Method> {
+    match value.as_str().to_lowercase().as_str() {
+        "be" => Ok(Method::BackwardEuler),
+        "trap" => Ok(Method::Trapezoidal),
+        "gear2" => Ok(Method::Gear2),
+        _ => {
+            let msg = format!(
+                "Unknown integration method {}, expected be, trap or gear2",
+                value.as_str()
+            );
+            Err(ParseError::at(&value, msg))
+        }
+    }
+}
+
+fn parse_option_number(
+    name: &Pair<Rule>,
+    value: &Pair<Rule>,
+    scope: &Scope,
+    valid: fn(f64) -> bool,
+    expected: &str,
+) -> ParseResult<f64> {
+    let number = match value.as_rule() {
+        Rule::value => parse_value(value.clone(), scope)?,
+        _ => f64::NAN,
+    };
+
+    if !valid(number) {
+        let msg = format!(
+            "Option {} must be {}, got {}",
+            name.as_str(),
+            expected,
+            value.as_str()
+        );
+        return Err(ParseError::at(value, msg));
+    }
+
+    Ok(number)
+}
+
+fn parse_option_switch(name: &Pair<Rule>, value: Pair<Rule>) -> ParseResult<bool> {
+    match value.as_str().to_lowercase().as_str() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => {
+            let msg = format!(
+                "Option {} must be on or off, got {}",
+                name.as_str(),
+                value.as_str()
+            );
+            Err(ParseError::at(&value, msg))
+        }
     }
 }
 
+fn parse_node_values(cmd: Pair<Rule>, scope: &Scope) -> ParseResult<Vec<(String, f64)>> {
+    cmd.into_inner()
+        .map(|node_value| {
+            let mut details = node_value.into_inner();
+            let node = details.next().unwrap().as_str();
+            let value = parse_value(details.next().unwrap(), scope)?;
+
+            Ok((scope.node_name(node), value))
+        })
+        .collect()
+}
+
+fn parse_probe_cmd(cmd: Pair<Rule>) -> command::Command {
+    let probes = cmd
+        .into_inner()
+        .map(|probe| {
+            let mut details = probe.into_inner();
+
+            command::Probe {
+                elem: details.next().unwrap().as_str().to_string(),
+                terminal: details.next().map(|t| t.as_str().to_lowercase()),
+            }
+        })
+        .collect();
+
+    command::Command::Probe(probes)
+}
+
 fn parse_op_cmd() -> command::Command {
     command::Command::Op
 }
 
-fn parse_dc_cmd(cmd: Pair<Rule>) -> command::Command {
-    let mut cmd_details = cmd.into_inner();
+fn parse_dc_cmd(cmd: Pair<Rule>, scope: &Scope) -> ParseResult<command::Command> {
+    let sweeps = cmd
+        .into_inner()
+        .map(|sweep| parse_dc_sweep(sweep, scope))
+        .collect::<ParseResult<Vec<_>>>()?;
 
-    let source = cmd_details.next().unwrap().as_str();
-    let start = parse_value(cmd_details.next().unwrap());
-    let stop = parse_value(cmd_details.next().unwrap());
-    let step = parse_value(cmd_details.next().unwrap());
+    Ok(command::Command::DC(command::DCParams { sweeps }))
+}
 
-    command::Command::DC(command::DCParams {
-        source: String::from(source),
-        start: start,
-        stop: stop,
-        step: step,
-    })
+fn parse_dc_sweep(sweep: Pair<Rule>, scope: &Scope) -> ParseResult<command::DCSweep> {
+    let mut sweep_details = sweep.into_inner();
+
+    let var_pair = sweep_details.next().unwrap();
+    let var = match var_pair.as_rule() {
+        Rule::temp_var => command::SweepVar::Temp,
+        Rule::param_var => {
+            let name = var_pair.clone().into_inner().next().unwrap().as_str();
+            if scope.param(name).is_none() {
+                let msg = format!("Parameter {} not found!", name);
+                return Err(ParseError::at(&var_pair, msg));
+            }
+            command::SweepVar::Param(name.to_lowercase())
+        }
+        _ => command::SweepVar::Elem(scope.elem_name(var_pair.as_str())),
+    };
+
+    let values = match sweep_details.peek().unwrap().as_rule() {
+        Rule::sweep_type => {
+            let sweep = parse_sweep_type(sweep_details.next().unwrap());
+
+            let points_pair = sweep_details.next().unwrap();
+            let points = parse_value(points_pair.clone(), scope)?;
+            if points < 1.0 || points.fract() != 0.0 {
+                let msg = format!(
+                    "Number of sweep points must be a positive integer, got {}",
+                    points_pair.as_str()
+                );
+                return Err(ParseError::at(&points_pair, msg));
+            }
+
+            let start_pair = sweep_details.next().unwrap();
+            let start = parse_value(start_pair.clone(), scope)?;
+            if sweep != command::SweepType::Lin && start <= 0.0 {
+                let msg = format!(
+                    "Logarithmic sweeps must start above 0, got {}",
+                    start_pair.as_str()
+                );
+                return Err(ParseError::at(&start_pair, msg));
+            }
+
+            let stop_pair = sweep_details.next().unwrap();
+            let stop = parse_value(stop_pair.clone(), scope)?;
+            if sweep != command::SweepType::Lin && stop < start {
+                let msg = format!(
+                    "Logarithmic sweep stop {} is below the start {}",
+                    stop_pair.as_str(),
+                    start_pair.as_str()
+                );
+                return Err(ParseError::at(&stop_pair, msg));
+            }
+
+            command::SweepValues::Points {
+                sweep,
+                points: points as usize,
+                start,
+                stop,
+            }
+        }
+        _ => {
+            let start = parse_value(sweep_details.next().unwrap(), scope)?;
+            let stop = parse_value(sweep_details.next().unwrap(), scope)?;
+            let step_pair = sweep_details.next().unwrap();
+            let step = parse_value(step_pair.clone(), scope)?;
+            if step == 0.0 && stop != start || (stop - start) * step < 0.0 {
+                let msg = format!(
+                    "Sweep step {} doesn't go from {} to {}",
+                    step_pair.as_str(),
+                    start,
+                    stop
+                );
+                return Err(ParseError::at(&step_pair, msg));
+            }
+
+            command::SweepValues::Step { start, stop, step }
+        }
+    };
+
+    Ok(command::DCSweep { var, values })
+}
+
+fn parse_sweep_type(sweep: Pair<Rule>) -> command::SweepType {
+    match sweep.as_str().to_lowercase().as_str() {
+        "dec" => command::SweepType::Dec,
+        "oct" => command::SweepType::Oct,
+        "lin" => command::SweepType::Lin,
+        _ => unreachable!(),
+    }
 }
 
-fn parse_tran_cmd(cmd: Pair<Rule>) -> command::Command {
+fn parse_tran_cmd(cmd: Pair<Rule>, scope: &Scope) -> ParseResult<command::Command> {
     let mut cmd_details = cmd.into_inner();
 
-    let stop = parse_value(cmd_details.next().unwrap());
-    let step = parse_value(cmd_details.next().unwrap());
+    let mut step_pair = cmd_details.next().unwrap();
+    let mut stop_pair = cmd_details.next().unwrap();
+
+    // Native netlists write the stop time first
+    if scope.dialect == Dialect::Native {
+        (step_pair, stop_pair) = (stop_pair, step_pair);
+    }
+
+    let step = parse_value(step_pair.clone(), scope)?;
+    if step <= 0.0 {
+        let msg = format!(
+            "Transient step must be positive, got {}",
+            step_pair.as_str()
+        );
+        return Err(ParseError::at(&step_pair, msg));
+    }
+
+    let stop = parse_value(stop_pair.clone(), scope)?;
+    if stop <= 0.0 {
+        let msg = format!(
+            "Transient stop time must be positive, got {}",
+            stop_pair.as_str()
+        );
+        return Err(ParseError::at(&stop_pair, msg));
+    }
+
+    let uic = cmd_details.clone().any(|p| p.as_rule() == Rule::uic);
+    let mut rest = cmd_details.filter(|p| p.as_rule() == Rule::value);
+
+    let start = match rest.next() {
+        Some(start_pair) => {
+            let start = parse_value(start_pair.clone(), scope)?;
+            if start < 0.0 || start >= stop {
+                let msg = format!(
+                    "Transient start time must be between 0 and the stop time {}, got {}",
+                    stop_pair.as_str(),
+                    start_pair.as_str()
+                );
+                return Err(ParseError::at(&start_pair, msg));
+            }
+            start
+        }
+        None => 0.0,
+    };
+
+    let tmax = match rest.next() {
+        Some(tmax_pair) => {
+            let tmax = parse_value(tmax_pair.clone(), scope)?;
+            if tmax <= 0.0 {
+                let msg = format!(
+                    "Transient max step must be positive, got {}",
+                    tmax_pair.as_str()
+                );
+                return Err(ParseError::at(&tmax_pair, msg));
+            }
+            Some(tmax)
+        }
+        None => None,
+    };
 
-    command::Command::Tran(command::TranParams {
-        start: 0.0,
+    Ok(command::Command::Tran(command::TranParams {
+        start,
         stop,
         step,
-    })
+        tmax,
+        uic,
+    }))
+}
+
+fn parse_ac_cmd(cmd: Pair<Rule>, scope: &Scope) -> ParseResult<command::Command> {
+    let mut cmd_details = cmd.into_inner();
+
+    let sweep = parse_sweep_type(cmd_details.next().unwrap());
+
+    let points_pair = cmd_details.next().unwrap();
+    let points = parse_value(points_pair.clone(), scope)?;
+    if points < 1.0 || points.fract() != 0.0 {
+        let msg = format!(
+            "Number of AC points must be a positive integer, got {}",
+            points
+        );
+        return Err(ParseError::at(&points_pair, msg));
+    }
+
+    let start_pair = cmd_details.next().unwrap();
+    let start = parse_value(start_pair.clone(), scope)?;
+    if start <= 0.0 {
+        let msg = format!("AC start frequency must be positive, got {}", start);
+        return Err(ParseError::at(&start_pair, msg));
+    }
+
+    let stop_pair = cmd_details.next().unwrap();
+    let stop = parse_value(stop_pair.clone(), scope)?;
+    if stop < start {
+        let msg = format!(
+            "AC stop frequency {} is below the start frequency {}",
+            stop, start
+        );
+        return Err(ParseError::at(&stop_pair, msg));
+    }
+
+    Ok(command::Command::AC(command::ACParams {
+        sweep,
+        points: points as usize,
+        start,
+        stop,
+    }))
 }
 
-fn parse_spice_fn(fn_value: Pair<Rule>) -> SpiceFn {
+fn parse_spice_fn(fn_value: Pair<Rule>, scope: &Scope) -> ParseResult<SpiceFn> {
     match fn_value.as_rule() {
         Rule::sine_fn => {
             let mut fn_details = fn_value.into_inner();
-            let offset = parse_value(fn_details.next().unwrap());
-            let amplitude = parse_value(fn_details.next().unwrap());
-            let freq = parse_value(fn_details.next().unwrap());
+            let offset = parse_value(fn_details.next().unwrap(), scope)?;
+            let amplitude = parse_value(fn_details.next().unwrap(), scope)?;
+            let freq = parse_value(fn_details.next().unwrap(), scope)?;
+            let delay = fn_details
+                .next()
+                .map_or(Ok(0.0), |v| parse_value(v, scope))?;
+            let damping = fn_details
+                .next()
+                .map_or(Ok(0.0), |v| parse_value(v, scope))?;
 
-            SpiceFn::Sine(SineParams {
+            Ok(SpiceFn::Sine(SineParams {
                 offset,
                 amplitude,
                 freq,
-            })
+                delay,
+                damping,
+            }))
         }
         Rule::pulse_fn => {
             let mut fn_details = fn_value.into_inner();
-            let v1 = parse_value(fn_details.next().unwrap());
-            let v2 = parse_value(fn_details.next().unwrap());
-            let delay = parse_value(fn_details.next().unwrap());
-            let t_rise = parse_value(fn_details.next().unwrap());
-            let t_fall = parse_value(fn_details.next().unwrap());
-            let pulse_width = parse_value(fn_details.next().unwrap());
-            let period = parse_value(fn_details.next().unwrap());
-
-            SpiceFn::Pulse(PulseParams {
+            let v1 = parse_value(fn_details.next().unwrap(), scope)?;
+            let v2 = parse_value(fn_details.next().unwrap(), scope)?;
+            let delay = parse_value(fn_details.next().unwrap(), scope)?;
+            let t_rise = parse_value(fn_details.next().unwrap(), scope)?;
+            let t_fall = parse_value(fn_details.next().unwrap(), scope)?;
+            let pulse_width = parse_value(fn_details.next().unwrap(), scope)?;
+            let period_pair = fn_details.next().unwrap();
+            let period = parse_value(period_pair.clone(), scope)?;
+            if period <= 0.0 {
+                let msg = format!("PULSE period must be positive, got {}", period);
+                return Err(ParseError::at(&period_pair, msg));
+            }
+
+            Ok(SpiceFn::Pulse(PulseParams {
                 v1,
                 v2,
                 delay,
@@ -283,69 +1122,187 @@ fn parse_spice_fn(fn_value: Pair<Rule>) -> SpiceFn {
                 t_fall,
                 pulse_width,
                 period,
-            })
+            }))
         }
         Rule::exp_fn => {
             let mut fn_details = fn_value.into_inner();
-            let v1 = parse_value(fn_details.next().unwrap());
-            let v2 = parse_value(fn_details.next().unwrap());
-            let rise_delay = parse_value(fn_details.next().unwrap());
-            let rise_tau = parse_value(fn_details.next().unwrap());
-            let fall_delay = parse_value(fn_details.next().unwrap());
-            let fall_tau = parse_value(fn_details.next().unwrap());
-
-            SpiceFn::Exp(ExpParams {
+            let v1 = parse_value(fn_details.next().unwrap(), scope)?;
+            let v2 = parse_value(fn_details.next().unwrap(), scope)?;
+            let rise_delay = parse_value(fn_details.next().unwrap(), scope)?;
+            let rise_tau = parse_value(fn_details.next().unwrap(), scope)?;
+            let fall_delay = parse_value(fn_details.next().unwrap(), scope)?;
+            let fall_tau = parse_value(fn_details.next().unwrap(), scope)?;
+
+            Ok(SpiceFn::Exp(ExpParams {
                 v1,
                 v2,
                 rise_delay,
                 rise_tau,
                 fall_delay,
                 fall_tau,
-            })
+            }))
         }
+        Rule::pwl_fn => parse_pwl(fn_value, scope).map(SpiceFn::Pwl),
         _ => unreachable!(),
     }
 }
 
-fn parse_value(value: Pair<Rule>) -> f64 {
-    let mut value_details = value.into_inner();
-
-    let mut value = value_details
-        .next()
-        .unwrap()
-        .as_str()
-        .parse::<f64>()
-        .unwrap();
-
-    if let Some(prefix) = value_details.next() {
-        let mult = match prefix.as_str() {
-            "G" => 1e9,
-            "M" => 1e6,
-            "k" => 1e3,
-            "h" => 1e2,
-            "da" => 1e1,
-            "d" => 1e-1,
-            "c" => 1e-2,
-            "m" => 1e-3,
-            "u" => 1e-6,
-            "n" => 1e-9,
-            "p" => 1e-12,
-            "f" => 1e-15,
+fn parse_pwl(fn_value: Pair<Rule>, scope: &Scope) -> ParseResult<PwlParams> {
+    let mut points = Vec::new();
+    let mut delay = 0.0;
+    let mut repeat = None;
+
+    for item in fn_value.into_inner() {
+        match item.as_rule() {
+            Rule::pwl_point => {
+                let mut point = item.clone().into_inner();
+                let t = parse_value(point.next().unwrap(), scope)?;
+                let v = parse_value(point.next().unwrap(), scope)?;
+                if let Some((t_prev, _)) = points.last() {
+                    if t <= *t_prev {
+                        let msg = format!("PWL times must increase, {} follows {}", t, t_prev);
+                        return Err(ParseError::at(&item, msg));
+                    }
+                }
+                points.push((t, v));
+            }
+            Rule::pwl_file => points = read_pwl_file(item, scope)?,
+            Rule::pwl_option => {
+                let mut option = item.into_inner();
+                let name = option.next().unwrap().as_str().to_lowercase();
+                let value = option.next().unwrap();
+
+                match name.as_str() {
+                    "td" => delay = parse_value(value, scope)?,
+                    _ => repeat = Some((parse_value(value.clone(), scope)?, value)),
+                }
+            }
             _ => unreachable!(),
-        };
-        value *= mult;
+        }
     }
 
-    value
+    let (t_first, t_last) = (points[0].0, points.last().unwrap().0);
+    let repeat = match repeat {
+        Some((r, pair)) if r < t_first || r > t_last => {
+            let msg = format!(
+                "PWL repeat time {} is outside of the waveform, {} to {}",
+                r, t_first, t_last
+            );
+            return Err(ParseError::at(&pair, msg));
+        }
+        r => r.map(|(r, _)| r),
+    };
+
+    Ok(PwlParams {
+        points,
+        delay,
+        repeat,
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// Time and value columns separated by commas or whitespace. Comment lines
+// starting with `#` or `*` and headers above the first point are skipped.
+fn read_pwl_file(pwl_file: Pair<Rule>, scope: &Scope) -> ParseResult<Vec<(f64, f64)>> {
+    let path_pair = pwl_file.into_inner().next().unwrap();
+    let path = path_pair.as_str().trim_matches(|c| c == '"' || c == '\'');
+    let path = scope.dir.join(path);
+
+    let text = fs::read_to_string(&path).map_err(|e| {
+        let msg = format!("Couldn't read PWL file {}: {}", path.display(), e);
+        ParseError::at(&path_pair, msg)
+    })?;
+
+    let mut points: Vec<(f64, f64)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('*') {
+            continue;
+        }
 
-    #[test]
-    fn parse_spice_file_v_divider() {
-        let (elems, cmds) = parse_spice_file("test/v_divider.sp");
+        let cols = line
+            .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+            .filter(|c| !c.is_empty())
+            .map(str::parse::<f64>)
+            .collect::<Result<Vec<_>, _>>();
+
+        let msg = match cols.as_deref() {
+            Ok([t, v]) => match points.last() {
+                Some((t_prev, _)) if t <= t_prev => {
+                    format!("PWL times must increase, {} follows {}", t, t_prev)
+                }
+                _ => {
+                    points.push((*t, *v));
+                    continue;
+                }
+            },
+            Err(_) if points.is_empty() => continue,
+            _ => String::from("expected a time and a value"),
+        };
+
+        let msg = format!("{} on line {} of {}", msg, i + 1, path.display());
+        return Err(ParseError::at(&path_pair, msg));
+    }
+
+    if points.is_empty() {
+        let msg = format!("No PWL points found in {}", path.display());
+        return Err(ParseError::at(&path_pair, msg));
+    }
+
+    Ok(points)
+}
+
+fn parse_value(value: Pair<Rule>, scope: &Scope) -> ParseResult<f64> {
+    let mut value_details = value.into_inner();
+    let value = value_details.next().unwrap();
+
+    match value.as_rule() {
+        Rule::literal => Ok(parse_literal(value, scope.dialect)),
+        Rule::brace_expr => {
+            let expr = expr::parse_expr(value.clone().into_inner().next().unwrap(), scope.dialect);
+
+            let val = expr
+                .eval(&mut |name| {
+                    scope
+                        .param(name)
+                        .ok_or_else(|| format!("undefined parameter {}", name))
+                })
+                .map_err(|msg| ParseError::at(&value, msg))?;
+
+            // Units can trail expressions just like numbers, e.g. `{vdd}V`
+            match value_details.next() {
+                Some(suffix) => Ok(val * scope.dialect.scale(suffix.as_str())),
+                None => Ok(val),
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn parse_literal(literal: Pair<Rule>, dialect: Dialect) -> f64 {
+    let mut value_details = literal.into_inner();
+
+    let value = value_details
+        .next()
+        .unwrap()
+        .as_str()
+        .parse::<f64>()
+        .unwrap();
+
+    match value_details.next() {
+        Some(suffix) => value * dialect.scale(suffix.as_str()),
+        None => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pest::Parser;
+    use std::path::Path;
+
+    #[test]
+    fn parse_spice_file_v_divider() {
+        let (elems, cmds) = parse_spice_file("test/v_divider.sp", Dialect::Native).unwrap();
 
         assert_eq!(elems.len(), 3);
         assert_eq!(elems[0].get_name(), "V1");
@@ -356,9 +1313,172 @@ mod tests {
         assert!(matches!(cmds[0], command::Command::Op));
     }
 
+    #[test]
+    fn parse_spice_file_include_test() {
+        let (elems, cmds) = parse_spice_file("test/include.sp", Dialect::Native).unwrap();
+
+        assert_eq!(elems.len(), 4);
+        assert_eq!(elems[1].get_name(), "R1");
+        assert_eq!(elems[3].get_name(), "D1");
+
+        assert_eq!(cmds.len(), 1);
+    }
+
+    #[test]
+    fn parse_spice_file_lib_test() {
+        let (elems, _) = parse_spice_file("test/lib_corner.sp", Dialect::Native).unwrap();
+
+        assert_eq!(elems.len(), 3);
+        assert_eq!(elems[2].get_name(), "D1");
+    }
+
+    #[test]
+    fn parse_spice_file_syntax_errors() {
+        let errors = parse_spice_file("test/syntax_errors.sp", Dialect::Native)
+            .err()
+            .unwrap();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].file, Path::new("test/syntax_errors.sp"));
+        assert_eq!((errors[0].line, errors[0].col), (4, 10));
+    }
+
+    #[test]
+    fn parse_spice_file_semantic_errors() {
+        let errors = parse_spice_file("test/semantic_errors.sp", Dialect::Native)
+            .err()
+            .unwrap();
+        let messages = errors
+            .iter()
+            .map(|e| e.message.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            messages,
+            ["undefined parameter rload", "Model dfast not found"]
+        );
+        assert_eq!(errors[0].line, 4);
+        assert_eq!(errors[1].line, 5);
+    }
+
+    #[test]
+    fn parse_spice_file_ic_unknown_node() {
+        let errors = parse_spice_file("test/ic_unknown_node.sp", Dialect::Native)
+            .err()
+            .unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Node 3 not found!");
+        assert_eq!(errors[0].line, 6);
+        assert_eq!(errors[0].col, 7);
+    }
+
+    #[test]
+    fn parse_spice_file_dc_bad_sweep() {
+        let errors = parse_spice_file("test/dc_bad_sweep.sp", Dialect::Native)
+            .err()
+            .unwrap();
+
+        let messages = errors
+            .iter()
+            .map(|e| e.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            ["Element V2 not found!", "Element D1 has no value to sweep"]
+        );
+        assert_eq!((errors[1].line, errors[1].col), (7, 14));
+    }
+
+    #[test]
+    fn parse_spice_file_probe_bad_refs() {
+        let errors = parse_spice_file("test/probe_bad.sp", Dialect::Native)
+            .err()
+            .unwrap();
+
+        let messages = errors
+            .iter()
+            .map(|e| e.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "Element R2 not found!",
+                "Element R1 has no terminal a",
+                "Element Q1 needs a terminal, one of c, b, e"
+            ]
+        );
+        assert_eq!((errors[2].line, errors[2].col), (8, 8));
+    }
+
+    #[test]
+    fn parse_probe_cmd_terminals() {
+        let pair = SpiceParser::parse(Rule::probe_cmd, ".probe I(R1) i(x1.Q2,C)")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let cmd = parse_probe_cmd(pair);
+
+        if let command::Command::Probe(probes) = cmd {
+            let labels = probes.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            assert_eq!(labels, ["I(R1)", "I(x1.Q2,c)"]);
+        } else {
+            panic!("Command is not Probe");
+        }
+    }
+
+    #[test]
+    fn parse_spice_file_dc_param_override() {
+        let overrides = HashMap::from([(String::from("rbias"), 5e3)]);
+        let (elems, _) =
+            parse_spice_file_with_params("test/d_temp_sweep.sp", Dialect::Native, &overrides)
+                .unwrap();
+
+        assert_eq!(elems[1].get_value(), Some(5e3));
+    }
+
+    #[test]
+    fn parse_sources_rebuild() {
+        let sources = source::Sources::load("test/d_temp_sweep.sp", Dialect::Native).unwrap();
+        let ((elems, _), rebuild) = parse_sources_with_rebuild(&sources).unwrap();
+
+        let overrides = HashMap::from([(String::from("rbias"), 5e3)]);
+        let rebuilt = rebuild(&overrides).unwrap();
+
+        assert_eq!(elems[1].get_value(), Some(1e3));
+        assert_eq!(rebuilt[1].get_value(), Some(5e3));
+        assert_eq!(rebuild(&HashMap::new()).unwrap()[1].get_value(), Some(1e3));
+    }
+
+    #[test]
+    fn parse_spice_file_latch_test() {
+        let (_, cmds) = parse_spice_file("test/latch.sp", Dialect::Native).unwrap();
+
+        assert_eq!(cmds.len(), 2);
+        if let command::Command::NodeSet(values) = &cmds[0] {
+            assert_eq!(
+                values,
+                &[(String::from("2"), 5.0), (String::from("3"), 0.0)]
+            );
+        } else {
+            panic!("Command is not NodeSet");
+        }
+    }
+
+    #[test]
+    fn parse_spice_file_missing() {
+        let errors = parse_spice_file("test/does_not_exist.sp", Dialect::Native)
+            .err()
+            .unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.starts_with("cannot read file"));
+    }
+
     #[test]
     fn parse_spice_file_i_divider() {
-        let (elems, cmds) = parse_spice_file("test/i_divider.sp");
+        let (elems, cmds) = parse_spice_file("test/i_divider.sp", Dialect::Native).unwrap();
 
         assert_eq!(elems.len(), 3);
         assert_eq!(elems[0].get_name(), "I1");
@@ -371,7 +1491,7 @@ mod tests {
 
     #[test]
     fn parse_spice_file_r_d_direct() {
-        let (elems, cmds) = parse_spice_file("test/r_d_direct.sp");
+        let (elems, cmds) = parse_spice_file("test/r_d_direct.sp", Dialect::Native).unwrap();
 
         assert_eq!(elems.len(), 3);
         assert_eq!(elems[0].get_name(), "V1");
@@ -384,7 +1504,7 @@ mod tests {
 
     #[test]
     fn parse_spice_file_npn_test() {
-        let (elems, cmds) = parse_spice_file("test/npn_test.sp");
+        let (elems, cmds) = parse_spice_file("test/npn_test.sp", Dialect::Native).unwrap();
 
         assert_eq!(elems.len(), 5);
         assert_eq!(elems[0].get_name(), "V01");
@@ -399,7 +1519,7 @@ mod tests {
 
     #[test]
     fn parse_spice_file_nmos_test() {
-        let (elems, cmds) = parse_spice_file("test/nmos_test.sp");
+        let (elems, cmds) = parse_spice_file("test/nmos_test.sp", Dialect::Native).unwrap();
 
         assert_eq!(elems.len(), 4);
         assert_eq!(elems[0].get_name(), "V01");
@@ -413,297 +1533,1183 @@ mod tests {
 
     #[test]
     fn parse_spice_file_v_divider_sweep_test() {
-        let (elems, cmds) = parse_spice_file("test/v_divider_sweep.sp");
+        let (elems, cmds) = parse_spice_file("test/v_divider_sweep.sp", Dialect::Native).unwrap();
+
+        assert_eq!(elems.len(), 3);
+        assert_eq!(elems[0].get_name(), "V1");
+        assert_eq!(elems[1].get_name(), "R12");
+        assert_eq!(elems[2].get_name(), "R20");
+
+        assert_eq!(cmds.len(), 2);
+        assert!(matches!(cmds[0], command::Command::Op));
+        assert!(matches!(cmds[1], command::Command::DC(_)));
+    }
+
+    #[test]
+    fn parse_spice_file_i_divider_sweep_test() {
+        let (elems, cmds) = parse_spice_file("test/i_divider_sweep.sp", Dialect::Native).unwrap();
+
+        assert_eq!(elems.len(), 3);
+        assert_eq!(elems[0].get_name(), "I1");
+        assert_eq!(elems[1].get_name(), "R10a");
+        assert_eq!(elems[2].get_name(), "R10b");
+
+        assert_eq!(cmds.len(), 2);
+        assert!(matches!(cmds[0], command::Command::Op));
+        assert!(matches!(cmds[1], command::Command::DC(_)));
+    }
+
+    #[test]
+    fn parse_spice_file_rc_tran_test() {
+        let (elems, cmds) = parse_spice_file("test/rc.sp", Dialect::Native).unwrap();
+
+        assert_eq!(elems.len(), 3);
+        assert_eq!(elems[0].get_name(), "V01");
+        assert_eq!(elems[1].get_name(), "R12");
+        assert_eq!(elems[2].get_name(), "C20");
+
+        assert_eq!(cmds.len(), 1);
+        assert!(matches!(cmds[0], command::Command::Tran(_)));
+    }
+
+    #[test]
+    fn parse_spice_file_rc_sine_tran_test() {
+        let (elems, cmds) = parse_spice_file("test/rc_sine.sp", Dialect::Native).unwrap();
+
+        assert_eq!(elems.len(), 3);
+        assert_eq!(elems[0].get_name(), "V01");
+        assert_eq!(elems[1].get_name(), "R12");
+        assert_eq!(elems[2].get_name(), "C20");
+
+        assert_eq!(cmds.len(), 1);
+        assert!(matches!(cmds[0], command::Command::Tran(_)));
+    }
+
+    #[test]
+    fn parse_spice_file_rc_pulse_tran_test() {
+        let (elems, cmds) = parse_spice_file("test/rc_pulse.sp", Dialect::Native).unwrap();
+
+        assert_eq!(elems.len(), 3);
+        assert_eq!(elems[0].get_name(), "V01");
+        assert_eq!(elems[1].get_name(), "R12");
+        assert_eq!(elems[2].get_name(), "C20");
+
+        assert_eq!(cmds.len(), 1);
+        assert!(matches!(cmds[0], command::Command::Tran(_)));
+    }
+
+    #[test]
+    fn parse_spice_file_rc_exp_tran_test() {
+        let (elems, cmds) = parse_spice_file("test/rc_exp.sp", Dialect::Native).unwrap();
+
+        assert_eq!(elems.len(), 3);
+        assert_eq!(elems[0].get_name(), "V01");
+        assert_eq!(elems[1].get_name(), "R12");
+        assert_eq!(elems[2].get_name(), "C20");
+
+        assert_eq!(cmds.len(), 1);
+        assert!(matches!(cmds[0], command::Command::Tran(_)));
+    }
+
+    #[test]
+    fn parse_spice_file_models_test() {
+        let (elems, cmds) = parse_spice_file("test/models.sp", Dialect::Native).unwrap();
+
+        assert_eq!(elems.len(), 7);
+        assert_eq!(elems[4].get_name(), "D10");
+        assert_eq!(elems[5].get_name(), "D20");
+        assert_eq!(elems[6].get_name(), "D30");
+
+        assert_eq!(cmds.len(), 1);
+        assert!(matches!(cmds[0], command::Command::Op));
+    }
+
+    #[test]
+    fn parse_spice_file_subckt_test() {
+        let (elems, cmds) = parse_spice_file("test/subckt.sp", Dialect::Native).unwrap();
+
+        let names = elems.iter().map(|e| e.get_name()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["V1", "Xa.X1.R1", "Xa.X1.R2", "Xa.X2.R1", "Xa.X2.R2", "Xb.R1", "Xb.R2"]
+        );
+
+        assert_eq!(elems[1].get_nodes(), &["1", "Xa.mid"]);
+        assert_eq!(elems[2].get_nodes(), &["Xa.mid", "0"]);
+        assert_eq!(elems[3].get_nodes(), &["Xa.mid", "2"]);
+        assert_eq!(elems[4].get_nodes(), &["2", "0"]);
+
+        assert_eq!(elems[1].get_value(), Some(1e3));
+        assert_eq!(elems[3].get_value(), Some(2e3));
+        assert_eq!(elems[5].get_value(), Some(10e3));
+
+        assert_eq!(cmds.len(), 1);
+    }
+
+    #[test]
+    fn parse_spice_file_params_test() {
+        let (elems, cmds) = parse_spice_file("test/params.sp", Dialect::Native).unwrap();
+
+        assert_eq!(elems.len(), 4);
+        assert_eq!(elems[0].get_value(), Some(3.3));
+        assert_eq!(elems[1].get_value(), Some(1e3));
+        assert_eq!(
+            elems[2].get_value(),
+            Some(1.0 / (2.0 * std::f64::consts::PI * 1e6 * 1e3))
+        );
+        assert_eq!(elems[3].get_value(), Some(2e3));
+
+        assert_eq!(cmds.len(), 1);
+    }
+
+    #[test]
+    fn parse_spice_file_spice3() {
+        let (elems, cmds) = parse_spice_file("test/spice3.sp", Dialect::Spice3).unwrap();
+
+        let names = elems.iter().map(|e| e.get_name()).collect::<Vec<_>>();
+        assert_eq!(names, ["VCC", "VIN", "RB", "RC", "Q1", "D1", "I1", "C1"]);
+        assert_eq!(elems[1].get_value(), Some(0.8));
+        assert_eq!(elems[2].get_value(), Some(10e3));
+        assert_eq!(elems[7].get_value(), Some(1.5e-12));
+
+        // Anode first and current flowing out of the first node
+        assert_eq!(elems[5].get_nodes(), &["c", "vcc"]);
+        assert_eq!(elems[6].get_nodes(), &["0", "c"]);
+
+        assert_eq!(cmds.len(), 1);
+    }
+
+    #[test]
+    fn parse_spice_file_spice3_as_native() {
+        let errs = parse_spice_file("test/spice3.sp", Dialect::Native)
+            .err()
+            .unwrap();
+
+        assert_eq!((errs[0].line, errs[0].col), (1, 1));
+        assert_eq!(
+            errs[0].message,
+            "expected element or dot command; SPICE3 netlists start with a title, run with --spice3"
+        );
+    }
+
+    #[test]
+    fn parse_value_undefined_param() {
+        let pair = SpiceParser::parse(Rule::value, "{2*rl}")
+            .unwrap()
+            .next()
+            .unwrap();
+        let err = parse_value(pair, &Scope::default()).unwrap_err();
+
+        assert_eq!(err.message, "undefined parameter rl");
+        assert_eq!((err.line, err.col), (1, 1));
+    }
+
+    #[test]
+    fn parse_value_division_by_zero() {
+        let pair = SpiceParser::parse(Rule::r_node, "R1 1 2 R={1/0}")
+            .unwrap()
+            .next()
+            .unwrap();
+        let err = parse_res(pair, &Scope::default()).unwrap_err();
+
+        assert_eq!(err.message, "division by zero");
+        assert_eq!((err.line, err.col), (1, 10));
+    }
+
+    #[test]
+    fn parse_value_expr() {
+        let scope = Scope::new(
+            HashMap::from([(String::from("rload"), 1e3)]),
+            Dialect::Native,
+        );
+        let pair = SpiceParser::parse(Rule::value, "{ 2 * Rload + sqrt(4k*1k) }")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        assert_eq!(parse_value(pair, &scope).unwrap(), 4e3);
+    }
+
+    fn parse_instance_str(subckt_def: &str, instance: &str) -> ParseResult<Vec<Box<dyn Stamp>>> {
+        let def = SpiceParser::parse(Rule::subckt, subckt_def)
+            .unwrap()
+            .next()
+            .unwrap();
+        let subckt = subckt::parse_subckt(def, Path::new("test.sp"));
+        let subckts = SubcktTable::from([(subckt.name.to_lowercase(), subckt)]);
+
+        let pair = SpiceParser::parse(Rule::x_node, instance)
+            .unwrap()
+            .next()
+            .unwrap();
+        let mut elems = Vec::new();
+        parse_instance(
+            pair,
+            &Scope::default(),
+            &models::builtin_models(),
+            &subckts,
+            &mut elems,
+        )?;
+
+        Ok(elems)
+    }
+
+    #[test]
+    fn parse_instance_default_refers_to_sibling() {
+        let def = ".subckt load a b params: r1=1k r2={r1*2}\nR1 a b R={r1}\nR2 b 0 R={r2}\n.ends";
+
+        let elems = parse_instance_str(def, "X1 5 6 LOAD").unwrap();
+        assert_eq!(elems[1].get_value(), Some(2e3));
+
+        // The default follows the value given for the parameter before it
+        let elems = parse_instance_str(def, "X1 5 6 LOAD r1=3k").unwrap();
+        assert_eq!(elems[0].get_value(), Some(3e3));
+        assert_eq!(elems[1].get_value(), Some(6e3));
+    }
+
+    #[test]
+    fn parse_instance_generic() {
+        let elems = parse_instance_str(
+            ".subckt load a b params: r=1k\nR1 a b R={r}\nD1 b 0 d_model\n.ends",
+            "X3 5 6 LOAD r=2.2k",
+        )
+        .unwrap();
+
+        assert_eq!(elems.len(), 2);
+        assert_eq!(elems[0].get_name(), "X3.R1");
+        assert_eq!(elems[0].get_nodes(), &["5", "6"]);
+        assert_eq!(elems[0].get_value(), Some(2.2e3));
+        assert_eq!(elems[1].get_name(), "X3.D1");
+        assert_eq!(elems[1].get_nodes(), &["0", "6"]);
+    }
+
+    #[test]
+    fn parse_instance_port_mismatch() {
+        let err = parse_instance_str(".subckt load a b\nR1 a b R=1\n.ends", "X1 5 load")
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.message,
+            "X1 connects 1 nodes to subcircuit load with 2 ports"
+        );
+    }
+
+    #[test]
+    fn parse_instance_unknown_param() {
+        let err = parse_instance_str(
+            ".subckt load a b params: r=1\nR1 a b R={r}\n.ends",
+            "X1 5 6 load c=2",
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(err.message, "Unknown parameter c for subcircuit load in X1");
+    }
+
+    #[test]
+    fn parse_instance_recursive() {
+        let err = parse_instance_str(".subckt loop a\nX2 a loop\n.ends", "X1 5 loop")
+            .err()
+            .unwrap();
+
+        assert_eq!(err.message, "Subcircuit loop of X1.X2 instantiates itself");
+        assert_eq!(err.file, Path::new("test.sp"));
+        assert_eq!((err.line, err.col), (2, 6));
+    }
+
+    #[test]
+    fn parse_res_generic() {
+        let pair = SpiceParser::parse(Rule::r_node, "R1 1 0 R=2.2k")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_res(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.name, "R1");
+        assert_eq!(elem.nodes, ["1", "0"]);
+        assert_eq!(elem.val, 2.2e3);
+    }
+
+    #[test]
+    fn parse_res_positional() {
+        let pair = SpiceParser::parse(Rule::r_node, "R1 1 0 2.2k")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_res(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.val, 2.2e3);
+    }
+
+    #[test]
+    fn parse_res_continuation() {
+        let pair = SpiceParser::parse(Rule::r_node, "R1 1\n+ 0 1meg")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_res(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.nodes, ["1", "0"]);
+        assert_eq!(elem.val, 1e6);
+    }
+
+    #[test]
+    fn parse_vdd_generic() {
+        let pair = SpiceParser::parse(Rule::v_node, "V1 1 0 4.0V")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_vdd(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.name, "V1");
+        assert_eq!(elem.nodes, ["0", "1"]);
+        assert_eq!(elem.val, 4.0);
+        assert!(elem.tran_fn.is_none());
+    }
+
+    #[test]
+    fn parse_vdd_sine() {
+        let pair = SpiceParser::parse(Rule::v_node, "V1 1 0 SIN(0.0 1.0 10k)")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_vdd(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.name, "V1");
+        assert_eq!(elem.nodes, ["0", "1"]);
+        assert_eq!(elem.val, 0.0);
+
+        let tran_fn = elem.tran_fn.expect("Tran Fn not set");
+        if let SpiceFn::Sine(params) = tran_fn {
+            assert_eq!(params.offset, 0.0);
+            assert_eq!(params.amplitude, 1.0);
+            assert_eq!(params.freq, 10e3);
+        } else {
+            panic!("Tran Function is not Sine");
+        }
+    }
+
+    #[test]
+    fn parse_vdd_dc_ac() {
+        let pair = SpiceParser::parse(Rule::v_node, "V1 1 0 DC 5 AC 1")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_vdd(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.val, 5.0);
+        assert_eq!(elem.ac_mag, 1.0);
+        assert_eq!(elem.ac_phase, 0.0);
+        assert!(elem.tran_fn.is_none());
+    }
+
+    #[test]
+    fn parse_idd_ac_phase() {
+        let pair = SpiceParser::parse(Rule::i_node, "I1 1 0 AC 2m 90")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_idd(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.val, 0.0);
+        assert_eq!(elem.ac_mag, 2e-3);
+        assert_eq!(elem.ac_phase, 90.0);
+    }
+
+    #[test]
+    fn parse_vdd_fn_dc_value() {
+        let pair = SpiceParser::parse(Rule::v_node, "V1 1 0 SIN(0 1 1k) DC 0.5")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_vdd(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.val, 0.5);
+        assert!(elem.tran_fn.is_some());
+    }
+
+    #[test]
+    fn parse_vdd_duplicate_dc() {
+        let pair = SpiceParser::parse(Rule::v_node, "V1 1 0 DC 5 3")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        assert!(parse_vdd(pair, &Scope::default()).is_err());
+    }
+
+    #[test]
+    fn parse_idd_generic() {
+        let pair = SpiceParser::parse(Rule::i_node, "I1 1 0 4.0mA")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_idd(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.name, "I1");
+        assert_eq!(elem.nodes, ["0", "1"]);
+        assert_eq!(elem.val, 4.0e-3);
+    }
+
+    #[test]
+    fn parse_idd_sine() {
+        let pair = SpiceParser::parse(Rule::i_node, "I1 1 0 SIN(0.0 1.0 10k)")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_idd(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.name, "I1");
+        assert_eq!(elem.nodes, ["0", "1"]);
+        assert_eq!(elem.val, 0.0);
+
+        let tran_fn = elem.tran_fn.expect("Tran Fn not set");
+        if let SpiceFn::Sine(params) = tran_fn {
+            assert_eq!(params.offset, 0.0);
+            assert_eq!(params.amplitude, 1.0);
+            assert_eq!(params.freq, 10e3);
+        } else {
+            panic!("Tran Function is not Sine");
+        }
+    }
+
+    #[test]
+    fn parse_vcvs_generic() {
+        let pair = SpiceParser::parse(Rule::e_node, "E1 out 0 in 0 {2*5}")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_vcvs(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.name, "E1");
+        assert_eq!(elem.nodes, ["out", "0", "in", "0"]);
+        assert_eq!(elem.val, 10.0);
+    }
+
+    #[test]
+    fn parse_vccs_generic() {
+        let pair = SpiceParser::parse(Rule::g_node, "G1 1 2 3 4 1.5m")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_vccs(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.nodes, ["1", "2", "3", "4"]);
+        assert_eq!(elem.val, 1.5e-3);
+    }
+
+    #[test]
+    fn parse_cccs_generic() {
+        let pair = SpiceParser::parse(Rule::f_node, "F1 1 0 Vsense 5")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_cccs(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.nodes, ["1", "0"]);
+        assert_eq!(elem.ctrl, "Vsense");
+        assert_eq!(elem.val, 5.0);
+    }
+
+    #[test]
+    fn parse_ccvs_in_instance() {
+        let scope = Scope::default().instance("X1", "amp", HashMap::new(), HashMap::new());
+        let pair = SpiceParser::parse(Rule::h_node, "H1 out 0 V1 1k")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_ccvs(pair, &scope).unwrap();
+
+        assert_eq!(elem.name, "X1.H1");
+        assert_eq!(elem.nodes, ["X1.out", "0"]);
+        assert_eq!(elem.ctrl, "X1.V1");
+        assert_eq!(elem.val, 1e3);
+    }
+
+    #[test]
+    fn parse_cccs_not_a_source() {
+        assert!(SpiceParser::parse(Rule::f_node, "F1 1 0 R1 5").is_err());
+    }
+
+    #[test]
+    fn parse_ind_generic() {
+        let pair = SpiceParser::parse(Rule::ind_node, "L1 1 0 L=1u")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_ind(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.name, "L1");
+        assert_eq!(elem.nodes, ["0", "1"]);
+        assert_eq!(elem.val, 1e-6);
+        assert_eq!(elem.u_curr, None);
+        assert_eq!(elem.i_curr, None);
+    }
+
+    #[test]
+    fn parse_ind_ic() {
+        let pair = SpiceParser::parse(Rule::ind_node, "L1 1 0 1u IC=2m")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_ind(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.val, 1e-6);
+        assert_eq!(elem.ic, Some(2e-3));
+    }
+
+    #[test]
+    fn parse_cap_generic() {
+        let pair = SpiceParser::parse(Rule::cap_node, "C1 1 0 C=1u")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_cap(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.name, "C1");
+        assert_eq!(elem.nodes, ["0", "1"]);
+        assert_eq!(elem.val, 1e-6);
+        assert_eq!(elem.u_curr, None);
+        assert_eq!(elem.i_curr, None);
+    }
+
+    #[test]
+    fn parse_cap_ic() {
+        let pair = SpiceParser::parse(Rule::cap_node, "C1 1 0 C=1u IC=-1.5")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_cap(pair, &Scope::default()).unwrap();
+
+        assert_eq!(elem.val, 1e-6);
+        assert_eq!(elem.ic, Some(-1.5));
+    }
+
+    #[test]
+    fn parse_dio_generic() {
+        let pair = SpiceParser::parse(Rule::dio_node, "D1 1 0 d_model")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_dio(pair, &Scope::default(), &models::builtin_models()).unwrap();
+
+        assert_eq!(elem.name, "D1");
+        assert_eq!(elem.nodes, ["0", "1"]);
+    }
+
+    #[test]
+    fn parse_dio_custom_model() {
+        let (name, model) = SpiceParser::parse(Rule::model_card, ".model dfast D (IS=1n N=2)")
+            .map(|mut p| models::parse_model_card(p.next().unwrap(), &Scope::default()))
+            .unwrap()
+            .unwrap();
+        let mut table = models::builtin_models();
+        table.insert(name, model);
+
+        let pair = SpiceParser::parse(Rule::dio_node, "D1 1 0 DFAST")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_dio(pair, &Scope::default(), &table).unwrap();
+
+        assert_eq!(elem.params.is, 1e-9);
+        assert_eq!(elem.params.n, 2.0);
+    }
+
+    #[test]
+    fn parse_dio_wrong_model_type() {
+        let pair = SpiceParser::parse(Rule::dio_node, "D1 1 0 q_model")
+            .unwrap()
+            .next()
+            .unwrap();
+        let err = parse_dio(pair, &Scope::default(), &models::builtin_models()).unwrap_err();
+
+        assert_eq!(err.message, "Model q_model of D1 is not a diode model");
+        assert_eq!(err.col, 8);
+    }
+
+    #[test]
+    fn parse_dio_missing_model() {
+        let pair = SpiceParser::parse(Rule::dio_node, "D1 1 0 dx")
+            .unwrap()
+            .next()
+            .unwrap();
+        let err = parse_dio(pair, &Scope::default(), &models::builtin_models()).unwrap_err();
+
+        assert_eq!(err.message, "Model dx not found");
+    }
+
+    #[test]
+    fn parse_bjt_generic() {
+        let pair = SpiceParser::parse(Rule::bjt_node, "Q1 1 2 3 0 q_model")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_bjt(pair, &Scope::default(), &models::builtin_models()).unwrap();
+
+        assert_eq!(elem.name, "Q1");
+        assert_eq!(elem.nodes, ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn parse_nmos_generic() {
+        let pair = SpiceParser::parse(Rule::mos_node, "M1 1 2 3 0 t_model")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_mos(pair, &Scope::default(), &models::builtin_models()).unwrap();
+
+        assert_eq!(elem.name, "M1");
+        assert_eq!(elem.nodes, ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn parse_bjt_no_substrate() {
+        let pair = SpiceParser::parse(Rule::bjt_node, "Q1 1 2 3 q_model")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_bjt(pair, &Scope::default(), &models::builtin_models()).unwrap();
+
+        assert_eq!(elem.nodes, ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn parse_nmos_geometry() {
+        let pair = SpiceParser::parse(Rule::mos_node, "M1 1 2 3 0 t_model W=10u L=2u AD=1p")
+            .unwrap()
+            .next()
+            .unwrap();
+        let elem = parse_mos(pair, &Scope::default(), &models::builtin_models()).unwrap();
+
+        assert!((elem.params.kp - 2.5e-3).abs() < 1e-15);
+    }
+
+    #[test]
+    fn parse_nmos_unknown_param() {
+        let pair = SpiceParser::parse(Rule::mos_node, "M1 1 2 3 0 t_model M=2")
+            .unwrap()
+            .next()
+            .unwrap();
+        let err = parse_mos(pair, &Scope::default(), &models::builtin_models()).unwrap_err();
+
+        assert_eq!(err.message, "Unknown parameter m for M1, expected W or L");
+    }
+
+    #[test]
+    fn parse_op_cmd_generic() {
+        let _pair = SpiceParser::parse(Rule::op_cmd, ".op")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let cmd = parse_op_cmd();
+
+        assert!(matches!(cmd, command::Command::Op));
+    }
+
+    #[test]
+    fn parse_dc_cmd_v_generic() {
+        let pair = SpiceParser::parse(Rule::dc_cmd, ".DC V1 0 1 1m")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let cmd = parse_dc_cmd(pair, &Scope::default()).unwrap();
+
+        assert!(matches!(cmd, command::Command::DC(_)));
+        if let command::Command::DC(params) = cmd {
+            assert_eq!(params.sweeps.len(), 1);
+            assert_eq!(
+                params.sweeps[0].var,
+                command::SweepVar::Elem(String::from("V1"))
+            );
+            assert_eq!(
+                params.sweeps[0].values,
+                command::SweepValues::Step {
+                    start: 0.0,
+                    stop: 1.0,
+                    step: 1e-3
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn parse_dc_cmd_i_generic() {
+        let pair = SpiceParser::parse(Rule::dc_cmd, ".DC I1 0 1 1m")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let cmd = parse_dc_cmd(pair, &Scope::default()).unwrap();
+
+        assert!(matches!(cmd, command::Command::DC(_)));
+        if let command::Command::DC(params) = cmd {
+            assert_eq!(params.sweeps.len(), 1);
+            assert_eq!(
+                params.sweeps[0].var,
+                command::SweepVar::Elem(String::from("I1"))
+            );
+            assert_eq!(
+                params.sweeps[0].values,
+                command::SweepValues::Step {
+                    start: 0.0,
+                    stop: 1.0,
+                    step: 1e-3
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn parse_dc_cmd_nested() {
+        let pair = SpiceParser::parse(Rule::dc_cmd, ".dc VDS 0 5 0.1 VGS 0 3 0.5")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let cmd = parse_dc_cmd(pair, &Scope::default()).unwrap();
+
+        if let command::Command::DC(params) = cmd {
+            let vars = params.sweeps.iter().map(|s| &s.var).collect::<Vec<_>>();
+            assert_eq!(
+                vars,
+                [
+                    &command::SweepVar::Elem(String::from("VDS")),
+                    &command::SweepVar::Elem(String::from("VGS"))
+                ]
+            );
+        } else {
+            panic!("Command is not DC");
+        }
+    }
+
+    #[test]
+    fn parse_dc_cmd_temp_and_param() {
+        let pair = SpiceParser::parse(
+            Rule::dc_cmd,
+            ".dc TEMP lin 5 -25 75 param Rload dec 2 1k 100k",
+        )
+        .unwrap()
+        .next()
+        .unwrap();
+        let scope = Scope::new(
+            HashMap::from([(String::from("rload"), 1e3)]),
+            Dialect::Native,
+        );
+
+        let cmd = parse_dc_cmd(pair, &scope).unwrap();
+
+        if let command::Command::DC(params) = cmd {
+            assert_eq!(params.sweeps[0].var, command::SweepVar::Temp);
+            assert_eq!(
+                params.sweeps[0].values,
+                command::SweepValues::Points {
+                    sweep: command::SweepType::Lin,
+                    points: 5,
+                    start: -25.0,
+                    stop: 75.0
+                }
+            );
+            assert_eq!(
+                params.sweeps[1].var,
+                command::SweepVar::Param(String::from("rload"))
+            );
+        } else {
+            panic!("Command is not DC");
+        }
+    }
+
+    #[test]
+    fn parse_dc_cmd_unknown_param() {
+        let pair = SpiceParser::parse(Rule::dc_cmd, ".dc param rload 1k 2k 100")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let err = parse_dc_cmd(pair, &Scope::default()).unwrap_err();
+
+        assert_eq!(err.message, "Parameter rload not found!");
+    }
+
+    #[test]
+    fn parse_dc_cmd_step_away_from_stop() {
+        let pair = SpiceParser::parse(Rule::dc_cmd, ".dc V1 0 1 -0.1")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let err = parse_dc_cmd(pair, &Scope::default()).unwrap_err();
+
+        assert_eq!(err.message, "Sweep step -0.1 doesn't go from 0 to 1");
+        assert_eq!(err.col, 12);
+    }
+
+    #[test]
+    fn parse_dc_cmd_log_from_zero() {
+        let pair = SpiceParser::parse(Rule::dc_cmd, ".dc R1 dec 10 0 1k")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let err = parse_dc_cmd(pair, &Scope::default()).unwrap_err();
+
+        assert_eq!(err.message, "Logarithmic sweeps must start above 0, got 0");
+    }
+
+    #[test]
+    fn parse_options_cmd_method() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".OPTIONS METHOD=Gear2")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let cmd = parse_options_cmd(pair, &Scope::default()).unwrap();
+
+        assert!(matches!(
+            cmd,
+            command::Command::Options(o) if o.method == Some(Method::Gear2)
+        ));
+    }
+
+    #[test]
+    fn parse_options_cmd_empty() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".option")
+            .unwrap()
+            .next()
+            .unwrap();
 
-        assert_eq!(elems.len(), 3);
-        assert_eq!(elems[0].get_name(), "V1");
-        assert_eq!(elems[1].get_name(), "R12");
-        assert_eq!(elems[2].get_name(), "R20");
+        let cmd = parse_options_cmd(pair, &Scope::default()).unwrap();
 
-        assert_eq!(cmds.len(), 2);
-        assert!(matches!(cmds[0], command::Command::Op));
-        assert!(matches!(cmds[1], command::Command::DC(_)));
+        assert!(matches!(cmd, command::Command::Options(o) if o.method.is_none()));
     }
 
     #[test]
-    fn parse_spice_file_i_divider_sweep_test() {
-        let (elems, cmds) = parse_spice_file("test/i_divider_sweep.sp");
+    fn parse_options_cmd_unknown_method() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".options method=rk4")
+            .unwrap()
+            .next()
+            .unwrap();
 
-        assert_eq!(elems.len(), 3);
-        assert_eq!(elems[0].get_name(), "I1");
-        assert_eq!(elems[1].get_name(), "R10a");
-        assert_eq!(elems[2].get_name(), "R10b");
+        let err = parse_options_cmd(pair, &Scope::default()).unwrap_err();
 
-        assert_eq!(cmds.len(), 2);
-        assert!(matches!(cmds[0], command::Command::Op));
-        assert!(matches!(cmds[1], command::Command::DC(_)));
+        assert_eq!(
+            err.message,
+            "Unknown integration method rk4, expected be, trap or gear2"
+        );
+        assert_eq!(err.col, 17);
     }
 
     #[test]
-    fn parse_spice_file_rc_tran_test() {
-        let (elems, cmds) = parse_spice_file("test/rc.sp");
+    fn parse_options_cmd_unknown_option() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".options method=be foo=1")
+            .unwrap()
+            .next()
+            .unwrap();
 
-        assert_eq!(elems.len(), 3);
-        assert_eq!(elems[0].get_name(), "V01");
-        assert_eq!(elems[1].get_name(), "R12");
-        assert_eq!(elems[2].get_name(), "C20");
+        let err = parse_options_cmd(pair, &Scope::default()).unwrap_err();
 
-        assert_eq!(cmds.len(), 1);
-        assert!(matches!(cmds[0], command::Command::Tran(_)));
+        assert_eq!(err.message, "Unknown option foo");
+        assert_eq!(err.col, 20);
     }
 
     #[test]
-    fn parse_spice_file_rc_sine_tran_test() {
-        let (elems, cmds) = parse_spice_file("test/rc_sine.sp");
+    fn parse_options_cmd_steps() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".options gminsteps=0 srcsteps=20")
+            .unwrap()
+            .next()
+            .unwrap();
 
-        assert_eq!(elems.len(), 3);
-        assert_eq!(elems[0].get_name(), "V01");
-        assert_eq!(elems[1].get_name(), "R12");
-        assert_eq!(elems[2].get_name(), "C20");
+        let cmd = parse_options_cmd(pair, &Scope::default()).unwrap();
 
-        assert_eq!(cmds.len(), 1);
-        assert!(matches!(cmds[0], command::Command::Tran(_)));
+        assert!(matches!(
+            cmd,
+            command::Command::Options(o) if o.gmin_steps == Some(0) && o.src_steps == Some(20)
+        ));
     }
 
     #[test]
-    fn parse_spice_file_rc_pulse_tran_test() {
-        let (elems, cmds) = parse_spice_file("test/rc_pulse.sp");
+    fn parse_options_cmd_bad_steps() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".options srcsteps=2.5")
+            .unwrap()
+            .next()
+            .unwrap();
 
-        assert_eq!(elems.len(), 3);
-        assert_eq!(elems[0].get_name(), "V01");
-        assert_eq!(elems[1].get_name(), "R12");
-        assert_eq!(elems[2].get_name(), "C20");
+        let err = parse_options_cmd(pair, &Scope::default()).unwrap_err();
 
-        assert_eq!(cmds.len(), 1);
-        assert!(matches!(cmds[0], command::Command::Tran(_)));
+        assert_eq!(
+            err.message,
+            "Option srcsteps must be a non-negative integer, got 2.5"
+        );
+        assert_eq!(err.col, 19);
     }
 
     #[test]
-    fn parse_spice_file_rc_exp_tran_test() {
-        let (elems, cmds) = parse_spice_file("test/rc_exp.sp");
+    fn parse_options_cmd_temp() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".options temp=-40")
+            .unwrap()
+            .next()
+            .unwrap();
 
-        assert_eq!(elems.len(), 3);
-        assert_eq!(elems[0].get_name(), "V01");
-        assert_eq!(elems[1].get_name(), "R12");
-        assert_eq!(elems[2].get_name(), "C20");
+        let cmd = parse_options_cmd(pair, &Scope::default()).unwrap();
 
-        assert_eq!(cmds.len(), 1);
-        assert!(matches!(cmds[0], command::Command::Tran(_)));
+        assert!(matches!(cmd, command::Command::Options(o) if o.temp == Some(-40.0)));
     }
 
     #[test]
-    fn parse_res_generic() {
-        let pair = SpiceParser::parse(Rule::r_node, "R1 1 0 R=2.2k")
+    fn parse_options_cmd_temp_below_absolute_zero() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".options temp=-300")
             .unwrap()
             .next()
             .unwrap();
-        let elem = parse_res(pair);
 
-        assert_eq!(elem.name, "R1");
-        assert_eq!(elem.nodes, ["1", "0"]);
-        assert_eq!(elem.val, 2.2e3);
+        let err = parse_options_cmd(pair, &Scope::default()).unwrap_err();
+
+        assert_eq!(err.message, "Option temp must be above -273.15, got -300");
     }
 
     #[test]
-    fn parse_vdd_generic() {
-        let pair = SpiceParser::parse(Rule::v_node, "V1 1 0 4.0V")
+    fn parse_tran_cmd_uic() {
+        let pair = SpiceParser::parse(Rule::tran_cmd, ".tran 10n 1n UIC")
             .unwrap()
             .next()
             .unwrap();
-        let elem = parse_vdd(pair);
 
-        assert_eq!(elem.name, "V1");
-        assert_eq!(elem.nodes, ["0", "1"]);
-        assert_eq!(elem.val, 4.0);
-        assert!(matches!(elem.tran_fn, None));
+        let cmd = parse_tran_cmd(pair, &Scope::default()).unwrap();
+
+        if let command::Command::Tran(t) = cmd {
+            assert_eq!(t.stop, 10e-9);
+            assert_eq!(t.step, 1e-9);
+            assert!(t.uic);
+        } else {
+            panic!("Command is not Tran");
+        }
     }
 
     #[test]
-    fn parse_vdd_sine() {
-        let pair = SpiceParser::parse(Rule::v_node, "V1 1 0 SIN(0.0 1.0 10k)")
+    fn parse_node_values_ic() {
+        let pair = SpiceParser::parse(Rule::ic_cmd, ".ic V(out)=1.2 v(x1.n)=-3m")
             .unwrap()
             .next()
             .unwrap();
-        let elem = parse_vdd(pair);
 
-        assert_eq!(elem.name, "V1");
-        assert_eq!(elem.nodes, ["0", "1"]);
-        assert_eq!(elem.val, 0.0);
+        let values = parse_node_values(pair, &Scope::default()).unwrap();
 
-        let tran_fn = elem.tran_fn.expect("Tran Fn not set");
-        if let SpiceFn::Sine(params) = tran_fn {
-            assert_eq!(params.offset, 0.0);
-            assert_eq!(params.amplitude, 1.0);
-            assert_eq!(params.freq, 10e3);
+        assert_eq!(
+            values,
+            [(String::from("out"), 1.2), (String::from("x1.n"), -3e-3)]
+        );
+    }
+
+    #[test]
+    fn parse_options_cmd_tolerances() {
+        let pair = SpiceParser::parse(
+            Rule::options_cmd,
+            ".options reltol=1e-4 vntol=1u abstol=1p itl1=200 itl4=20 trtol=5 gmin=0 tmax=1n",
+        )
+        .unwrap()
+        .next()
+        .unwrap();
+
+        let cmd = parse_options_cmd(pair, &Scope::default()).unwrap();
+
+        if let command::Command::Options(o) = cmd {
+            assert_eq!(o.reltol, Some(1e-4));
+            assert_eq!(o.vntol, Some(1e-6));
+            assert_eq!(o.abstol, Some(1e-12));
+            assert_eq!(o.itl1, Some(200));
+            assert_eq!(o.itl4, Some(20));
+            assert_eq!(o.trtol, Some(5.0));
+            assert_eq!(o.gmin, Some(0.0));
+            assert_eq!(o.tmax, Some(1e-9));
         } else {
-            panic!("Tran Function is not Sine");
+            panic!("Command is not Options");
         }
     }
 
     #[test]
-    fn parse_idd_generic() {
-        let pair = SpiceParser::parse(Rule::i_node, "I1 1 0 4.0mA")
+    fn parse_options_cmd_bad_tolerance() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".options reltol=0")
             .unwrap()
             .next()
             .unwrap();
-        let elem = parse_idd(pair);
 
-        assert_eq!(elem.name, "I1");
-        assert_eq!(elem.nodes, ["0", "1"]);
-        assert_eq!(elem.val, 4.0e-3);
+        let err = parse_options_cmd(pair, &Scope::default()).unwrap_err();
+
+        assert_eq!(err.message, "Option reltol must be positive, got 0");
     }
 
     #[test]
-    fn parse_idd_sine() {
-        let pair = SpiceParser::parse(Rule::i_node, "I1 1 0 SIN(0.0 1.0 10k)")
+    fn parse_options_cmd_word_for_number() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".options itl1=many")
             .unwrap()
             .next()
             .unwrap();
-        let elem = parse_idd(pair);
 
-        assert_eq!(elem.name, "I1");
-        assert_eq!(elem.nodes, ["0", "1"]);
-        assert_eq!(elem.val, 0.0);
+        let err = parse_options_cmd(pair, &Scope::default()).unwrap_err();
 
-        let tran_fn = elem.tran_fn.expect("Tran Fn not set");
-        if let SpiceFn::Sine(params) = tran_fn {
-            assert_eq!(params.offset, 0.0);
-            assert_eq!(params.amplitude, 1.0);
-            assert_eq!(params.freq, 10e3);
-        } else {
-            panic!("Tran Function is not Sine");
-        }
+        assert_eq!(
+            err.message,
+            "Option itl1 must be a positive integer, got many"
+        );
     }
 
     #[test]
-    fn parse_ind_generic() {
-        let pair = SpiceParser::parse(Rule::ind_node, "L1 1 0 L=1u")
+    fn parse_options_cmd_damping() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".options damping=ON")
             .unwrap()
             .next()
             .unwrap();
-        let elem = parse_ind(pair);
 
-        assert_eq!(elem.name, "L1");
-        assert_eq!(elem.nodes, ["0", "1"]);
-        assert_eq!(elem.val, 1e-6);
-        assert_eq!(elem.u_curr, None);
-        assert_eq!(elem.i_curr, None);
+        let cmd = parse_options_cmd(pair, &Scope::default()).unwrap();
+
+        assert!(matches!(cmd, command::Command::Options(o) if o.damping == Some(true)));
     }
 
     #[test]
-    fn parse_cap_generic() {
-        let pair = SpiceParser::parse(Rule::cap_node, "C1 1 0 C=1u")
+    fn parse_options_cmd_bad_switch() {
+        let pair = SpiceParser::parse(Rule::options_cmd, ".options damping=1")
             .unwrap()
             .next()
             .unwrap();
-        let elem = parse_cap(pair);
 
-        assert_eq!(elem.name, "C1");
-        assert_eq!(elem.nodes, ["0", "1"]);
-        assert_eq!(elem.val, 1e-6);
-        assert_eq!(elem.u_curr, None);
-        assert_eq!(elem.i_curr, None);
+        let err = parse_options_cmd(pair, &Scope::default()).unwrap_err();
+
+        assert_eq!(err.message, "Option damping must be on or off, got 1");
     }
 
     #[test]
-    fn parse_dio_generic() {
-        let pair = SpiceParser::parse(Rule::dio_node, "D1 1 0 d_model")
+    fn parse_tran_cmd_generic() {
+        let pair = SpiceParser::parse(Rule::tran_cmd, ".TRAN 1 1m")
             .unwrap()
             .next()
             .unwrap();
-        let elem = parse_dio(pair);
 
-        assert_eq!(elem.name, "D1");
-        assert_eq!(elem.nodes, ["0", "1"]);
+        let cmd = parse_tran_cmd(pair, &Scope::default()).unwrap();
+
+        assert!(matches!(cmd, command::Command::Tran(_)));
+        if let command::Command::Tran(params) = cmd {
+            assert_eq!(params.start, 0.0);
+            assert_eq!(params.stop, 1.0);
+            assert_eq!(params.step, 1e-3);
+        }
     }
 
     #[test]
-    fn parse_bjt_generic() {
-        let pair = SpiceParser::parse(Rule::bjt_node, "Q1 1 2 3 0 q_model")
+    fn parse_tran_cmd_start_tmax() {
+        let pair = SpiceParser::parse(Rule::tran_cmd, ".tran 40n 1n 10n 0.5n")
             .unwrap()
             .next()
             .unwrap();
-        let elem = parse_bjt(pair);
 
-        assert_eq!(elem.name, "Q1");
-        assert_eq!(elem.nodes, ["1", "2", "3"]);
+        let cmd = parse_tran_cmd(pair, &Scope::default()).unwrap();
+
+        if let command::Command::Tran(params) = cmd {
+            assert_eq!(params.start, 10e-9);
+            assert_eq!(params.stop, 40e-9);
+            assert_eq!(params.step, 1e-9);
+            assert_eq!(params.tmax, Some(0.5e-9));
+            assert!(!params.uic);
+        } else {
+            panic!("Command is not Tran");
+        }
     }
 
     #[test]
-    fn parse_nmos_generic() {
-        let pair = SpiceParser::parse(Rule::mos_node, "M1 1 2 3 0 t_model")
+    fn parse_tran_cmd_spice3() {
+        let pair = SpiceParser::parse(Rule::tran_cmd, ".tran 1n 40n 0 0.5n uic")
             .unwrap()
             .next()
             .unwrap();
-        let elem = parse_mos(pair);
 
-        assert_eq!(elem.name, "M1");
-        assert_eq!(elem.nodes, ["1", "2", "3"]);
+        let cmd = parse_tran_cmd(pair, &Scope::new(HashMap::new(), Dialect::Spice3)).unwrap();
+
+        if let command::Command::Tran(params) = cmd {
+            assert_eq!(params.start, 0.0);
+            assert_eq!(params.stop, 40e-9);
+            assert_eq!(params.step, 1e-9);
+            assert_eq!(params.tmax, Some(0.5e-9));
+            assert!(params.uic);
+        } else {
+            panic!("Command is not Tran");
+        }
     }
 
     #[test]
-    fn parse_op_cmd_generic() {
-        let _pair = SpiceParser::parse(Rule::op_cmd, ".op")
+    fn parse_tran_cmd_start_after_stop() {
+        let pair = SpiceParser::parse(Rule::tran_cmd, ".tran 40n 1n 50n")
             .unwrap()
             .next()
             .unwrap();
 
-        let cmd = parse_op_cmd();
+        let err = parse_tran_cmd(pair, &Scope::default()).unwrap_err();
 
-        assert!(matches!(cmd, command::Command::Op));
+        assert_eq!(
+            err.message,
+            "Transient start time must be between 0 and the stop time 40n, got 50n"
+        );
+        assert_eq!(err.col, 14);
     }
 
     #[test]
-    fn parse_dc_cmd_v_generic() {
-        let pair = SpiceParser::parse(Rule::dc_cmd, ".DC V1 0 1 1m")
+    fn parse_ac_cmd_generic() {
+        let pair = SpiceParser::parse(Rule::ac_cmd, ".AC DEC 10 1 1G")
             .unwrap()
             .next()
             .unwrap();
 
-        let cmd = parse_dc_cmd(pair);
+        let cmd = parse_ac_cmd(pair, &Scope::default()).unwrap();
 
-        assert!(matches!(cmd, command::Command::DC(_)));
-        if let command::Command::DC(params) = cmd {
-            assert_eq!(params.source, "V1");
-            assert_eq!(params.start, 0.0);
-            assert_eq!(params.stop, 1.0);
-            assert_eq!(params.step, 1e-3);
+        if let command::Command::AC(params) = cmd {
+            assert_eq!(params.sweep, command::SweepType::Dec);
+            assert_eq!(params.points, 10);
+            assert_eq!(params.start, 1.0);
+            assert_eq!(params.stop, 1e9);
+        } else {
+            panic!("Command is not AC");
         }
     }
 
     #[test]
-    fn parse_dc_cmd_i_generic() {
-        let pair = SpiceParser::parse(Rule::dc_cmd, ".DC I1 0 1 1m")
+    fn parse_ac_cmd_lin() {
+        let pair = SpiceParser::parse(Rule::ac_cmd, ".ac lin 5 100 500")
             .unwrap()
             .next()
             .unwrap();
 
-        let cmd = parse_dc_cmd(pair);
+        let cmd = parse_ac_cmd(pair, &Scope::default()).unwrap();
 
-        assert!(matches!(cmd, command::Command::DC(_)));
-        if let command::Command::DC(params) = cmd {
-            assert_eq!(params.source, "I1");
-            assert_eq!(params.start, 0.0);
-            assert_eq!(params.stop, 1.0);
-            assert_eq!(params.step, 1e-3);
-        }
+        assert!(matches!(cmd, command::Command::AC(p) if p.sweep == command::SweepType::Lin));
     }
 
     #[test]
-    fn parse_tran_cmd_generic() {
-        let pair = SpiceParser::parse(Rule::tran_cmd, ".TRAN 1 1m")
+    fn parse_ac_cmd_bad_points() {
+        let pair = SpiceParser::parse(Rule::ac_cmd, ".ac dec 2.5 1 1k")
             .unwrap()
             .next()
             .unwrap();
 
-        let cmd = parse_tran_cmd(pair);
+        let err = parse_ac_cmd(pair, &Scope::default()).unwrap_err();
 
-        assert!(matches!(cmd, command::Command::Tran(_)));
-        if let command::Command::Tran(params) = cmd {
-            assert_eq!(params.start, 0.0);
-            assert_eq!(params.stop, 1.0);
-            assert_eq!(params.step, 1e-3);
-        }
+        assert_eq!(
+            err.message,
+            "Number of AC points must be a positive integer, got 2.5"
+        );
+        assert_eq!(err.col, 9);
+    }
+
+    #[test]
+    fn parse_ac_cmd_zero_start() {
+        let pair = SpiceParser::parse(Rule::ac_cmd, ".ac oct 4 0 1k")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let err = parse_ac_cmd(pair, &Scope::default()).unwrap_err();
+
+        assert_eq!(err.message, "AC start frequency must be positive, got 0");
     }
 
     #[test]
@@ -716,7 +2722,7 @@ mod tests {
             .next()
             .unwrap();
 
-        let fn_ = parse_spice_fn(pair);
+        let fn_ = parse_spice_fn(pair, &Scope::default()).unwrap();
 
         assert!(matches!(fn_, SpiceFn::Sine(_)));
         if let SpiceFn::Sine(params) = fn_ {
@@ -728,6 +2734,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_spice_fn_sine_damped() {
+        let pair = SpiceParser::parse(Rule::fn_value, "sin(0 1 1k 1m 100)")
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner()
+            .next()
+            .unwrap();
+
+        if let SpiceFn::Sine(params) = parse_spice_fn(pair, &Scope::default()).unwrap() {
+            assert_eq!(params.delay, 1e-3);
+            assert_eq!(params.damping, 100.0);
+        } else {
+            panic!("Tran Function is not Sine");
+        }
+    }
+
     #[test]
     fn parse_spice_fn_pulse() {
         let pair = SpiceParser::parse(Rule::fn_value, "PULSE(0.0 1.0 0.0 1p 1p 5n 10n)")
@@ -738,7 +2762,7 @@ mod tests {
             .next()
             .unwrap();
 
-        let fn_ = parse_spice_fn(pair);
+        let fn_ = parse_spice_fn(pair, &Scope::default()).unwrap();
 
         assert!(matches!(fn_, SpiceFn::Pulse(_)));
         if let SpiceFn::Pulse(params) = fn_ {
@@ -754,6 +2778,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_spice_fn_pulse_bad_period() {
+        for pulse in ["PULSE(0 1 0 1p 1p 5n 0)", "PULSE(0 1 0 1p 1p 5n -10n)"] {
+            let pair = SpiceParser::parse(Rule::fn_value, pulse)
+                .unwrap()
+                .next()
+                .unwrap()
+                .into_inner()
+                .next()
+                .unwrap();
+
+            let err = parse_spice_fn(pair, &Scope::default()).unwrap_err();
+            assert!(err.message.starts_with("PULSE period must be positive"));
+        }
+    }
+
     #[test]
     fn parse_spice_fn_exp() {
         let pair = SpiceParser::parse(Rule::fn_value, "EXP(0.0 1.0 0.0 1n 5n 1n)")
@@ -764,7 +2804,7 @@ mod tests {
             .next()
             .unwrap();
 
-        let fn_ = parse_spice_fn(pair);
+        let fn_ = parse_spice_fn(pair, &Scope::default()).unwrap();
 
         assert!(matches!(fn_, SpiceFn::Exp(_)));
         if let SpiceFn::Exp(params) = fn_ {
@@ -779,6 +2819,78 @@ mod tests {
         }
     }
 
+    fn parse_pwl_str(pwl: &str) -> ParseResult<PwlParams> {
+        let pair = SpiceParser::parse(Rule::fn_value, pwl)
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner()
+            .next()
+            .unwrap();
+
+        parse_pwl(pair, &Scope::default().in_file(Path::new("test/rc_pwl.sp")))
+    }
+
+    #[test]
+    fn parse_spice_fn_pwl() {
+        let pwl = parse_pwl_str("PWL(0 0 1n 1.8 5n 1.8 6e-9 0)").unwrap();
+
+        assert_eq!(
+            pwl.points,
+            [(0.0, 0.0), (1e-9, 1.8), (5e-9, 1.8), (6e-9, 0.0)]
+        );
+        assert_eq!(pwl.delay, 0.0);
+        assert_eq!(pwl.repeat, None);
+    }
+
+    #[test]
+    fn parse_spice_fn_pwl_options() {
+        let pwl = parse_pwl_str("pwl(0 0 1u 1 2u 0) r=0 td=2u").unwrap();
+
+        assert_eq!(pwl.delay, 2e-6);
+        assert_eq!(pwl.repeat, Some(0.0));
+    }
+
+    #[test]
+    fn parse_spice_fn_pwl_file() {
+        let pwl = parse_pwl_str("PWL file=\"stim.csv\" td=1u").unwrap();
+
+        assert_eq!(pwl.points.len(), 5);
+        assert_eq!(pwl.points[1], (1e-6, 1.0));
+        assert_eq!(pwl.delay, 1e-6);
+    }
+
+    #[test]
+    fn parse_spice_fn_pwl_missing_file() {
+        let err = parse_pwl_str("PWL file=nope.csv").unwrap_err();
+
+        assert!(err
+            .message
+            .starts_with("Couldn't read PWL file test/nope.csv"));
+        assert_eq!(err.col, 10);
+    }
+
+    #[test]
+    fn parse_spice_fn_pwl_decreasing() {
+        let err = parse_pwl_str("PWL(0 0 2n 1 1n 0)").unwrap_err();
+
+        assert_eq!(
+            err.message,
+            "PWL times must increase, 0.000000001 follows 0.000000002"
+        );
+        assert_eq!(err.col, 14);
+    }
+
+    #[test]
+    fn parse_spice_fn_pwl_bad_repeat() {
+        let err = parse_pwl_str("PWL(0 0 1n 1) r=2n").unwrap_err();
+
+        assert_eq!(
+            err.message,
+            "PWL repeat time 0.000000002 is outside of the waveform, 0 to 0.000000001"
+        );
+    }
+
     #[test]
     fn parse_value_generic() {
         let test_vals = [("1.23", 1.23), ("-50", -50.0), ("1.3k", 1300.0)];
@@ -788,7 +2900,20 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            assert_eq!(&parse_value(pair), tgt_val);
+            assert_eq!(&parse_value(pair, &Scope::default()).unwrap(), tgt_val);
+        }
+    }
+
+    #[test]
+    fn parse_value_exponent() {
+        let test_vals = [("1E-6", 1e-6), ("-.5e3", -500.0), ("2.5e-3m", 2.5e-6)];
+
+        for (tgt_str, tgt_val) in test_vals.iter() {
+            let pair = SpiceParser::parse(Rule::value, tgt_str)
+                .unwrap()
+                .next()
+                .unwrap();
+            assert_eq!(&parse_value(pair, &Scope::default()).unwrap(), tgt_val);
         }
     }
 
@@ -813,7 +2938,7 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            assert_eq!(&parse_value(pair), tgt_val);
+            assert_eq!(&parse_value(pair, &Scope::default()).unwrap(), tgt_val);
         }
     }
 }
@@ -1,33 +1,397 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 
 use crate::device::Stamp;
 use crate::node::GND;
 
-pub fn check_elems(elems: &[Box<dyn Stamp>]) {
-    check_duplicate_names(elems);
-    check_gnd(elems);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    // Simulates, but probably not what was meant
+    Warning,
+    // Can't be simulated, or only thanks to the gmin shunts
+    Error,
 }
 
-fn check_duplicate_names(elems: &[Box<dyn Stamp>]) {
-    let names = elems.iter().map(|x| x.get_name()).collect::<HashSet<_>>();
-    assert_eq!(names.len(), elems.len(), "Duplicate elems found!");
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    // Elements involved, in netlist order
+    pub elems: Vec<String>,
+    pub message: String,
+}
+
+impl Finding {
+    fn error(elems: Vec<String>, message: String) -> Self {
+        Finding {
+            severity: Severity::Error,
+            elems,
+            message,
+        }
+    }
+
+    fn warning(elems: Vec<String>, message: String) -> Self {
+        Finding {
+            severity: Severity::Warning,
+            elems,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
 }
 
-fn check_gnd(elems: &[Box<dyn Stamp>]) {
+// Lints the flattened netlist, errors first
+pub fn check_elems(elems: &[Box<dyn Stamp>]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    findings.extend(check_duplicate_names(elems));
+    findings.extend(check_gnd(elems));
+    findings.extend(check_ctrl_sources(elems));
+    findings.extend(check_values(elems));
+    findings.extend(check_voltage_loops(elems));
+    findings.extend(check_dc_paths(elems));
+    findings.extend(check_dangling_nodes(elems));
+
+    findings.sort_by_key(|f| f.severity == Severity::Warning);
+    findings
+}
+
+// Element type from the first letter of its name, inside subcircuits too
+fn kind(elem: &dyn Stamp) -> char {
+    let name = elem.get_name();
+    let local = name.rsplit('.').next().unwrap_or(name);
+
+    local.chars().next().map_or(' ', |c| c.to_ascii_uppercase())
+}
+
+// Pairs of nodes that conduct at DC, in the order `get_nodes` has them
+fn dc_branches(elem: &dyn Stamp) -> Vec<(usize, usize)> {
+    match kind(elem) {
+        'R' | 'L' | 'V' | 'D' => vec![(0, 1)],
+        // The output is a voltage source, the control input only senses
+        'E' | 'H' => vec![(0, 1)],
+        'Q' => vec![(0, 1), (1, 2)],
+        // The gate is insulated
+        'M' => vec![(0, 2)],
+        // Capacitors and current sources, F and G included
+        _ => Vec::new(),
+    }
+}
+
+// Branches whose voltage is set whatever their current, in a loop of only those
+// the currents are undetermined
+fn is_voltage_branch(elem: &dyn Stamp) -> bool {
+    matches!(kind(elem), 'V' | 'L' | 'E' | 'H')
+}
+
+fn is_current_source(elem: &dyn Stamp) -> bool {
+    matches!(kind(elem), 'I' | 'F' | 'G')
+}
+
+fn check_duplicate_names(elems: &[Box<dyn Stamp>]) -> Vec<Finding> {
+    let mut seen = HashSet::new();
+    let mut reported = HashSet::new();
+
     elems
+        .iter()
+        .map(|e| e.get_name())
+        .filter(|name| !seen.insert(*name) && reported.insert(*name))
+        .map(|name| {
+            Finding::error(
+                vec![String::from(name)],
+                format!("Duplicate element {}", name),
+            )
+        })
+        .collect()
+}
+
+fn check_gnd(elems: &[Box<dyn Stamp>]) -> Option<Finding> {
+    let has_gnd = elems
         .iter()
         .flat_map(|e| e.get_nodes().iter())
-        .position(|n| n == &GND)
-        .expect("GND node not found!");
+        .any(|n| n == GND);
+
+    match has_gnd {
+        true => None,
+        false => Some(Finding::error(
+            Vec::new(),
+            format!("Ground node {} not found", GND),
+        )),
+    }
+}
+
+fn check_ctrl_sources(elems: &[Box<dyn Stamp>]) -> Vec<Finding> {
+    let names = elems.iter().map(|x| x.get_name()).collect::<HashSet<_>>();
+
+    elems
+        .iter()
+        .filter_map(|elem| {
+            let ctrl = elem.get_ctrl_source()?;
+            if names.contains(ctrl) {
+                return None;
+            }
+            Some(Finding::error(
+                vec![String::from(elem.get_name())],
+                format!(
+                    "Controlling source {} of {} not found",
+                    ctrl,
+                    elem.get_name()
+                ),
+            ))
+        })
+        .collect()
+}
+
+// A zero resistor divides by zero, zero capacitors and inductors are just an
+// open and a short. Negative values simulate but are rarely meant.
+fn check_values(elems: &[Box<dyn Stamp>]) -> Vec<Finding> {
+    elems
+        .iter()
+        .filter(|e| matches!(kind(e.as_ref()), 'R' | 'C' | 'L'))
+        .filter_map(|e| {
+            let val = e.get_value()?;
+            let names = vec![String::from(e.get_name())];
+
+            match kind(e.as_ref()) {
+                'R' if val == 0.0 => Some(Finding::error(
+                    names,
+                    format!("{} has a zero resistance", e.get_name()),
+                )),
+                _ if val == 0.0 => Some(Finding::warning(
+                    names,
+                    format!("{} has a zero value", e.get_name()),
+                )),
+                _ if val < 0.0 => Some(Finding::warning(
+                    names,
+                    format!("{} has a negative value", e.get_name()),
+                )),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// Loops made only of voltage sources and inductors. Each branch that would close
+// one is reported with the path it closes.
+fn check_voltage_loops(elems: &[Box<dyn Stamp>]) -> Vec<Finding> {
+    let mut adjacent: HashMap<&str, Vec<(&str, usize)>> = HashMap::new();
+    let mut findings = Vec::new();
+
+    for (i, elem) in elems.iter().enumerate() {
+        if !is_voltage_branch(elem.as_ref()) {
+            continue;
+        }
+        let nodes = elem.get_nodes();
+        let (a, b) = (nodes[0].as_str(), nodes[1].as_str());
+
+        if let Some(path) = find_path(&adjacent, a, b) {
+            let mut loop_elems = path;
+            loop_elems.push(i);
+            loop_elems.sort();
+
+            let names = loop_elems
+                .iter()
+                .map(|&j| String::from(elems[j].get_name()))
+                .collect::<Vec<_>>();
+            let message = format!(
+                "Loop of voltage sources and inductors: {}",
+                names.join(", ")
+            );
+            findings.push(Finding::error(names, message));
+            continue;
+        }
+
+        adjacent.entry(a).or_default().push((b, i));
+        adjacent.entry(b).or_default().push((a, i));
+    }
+
+    findings
+}
+
+// Elements on a path from `from` to `to`, if there is one
+fn find_path(
+    adjacent: &HashMap<&str, Vec<(&str, usize)>>,
+    from: &str,
+    to: &str,
+) -> Option<Vec<usize>> {
+    if from == to {
+        return Some(Vec::new());
+    }
+
+    let mut prev: HashMap<&str, (&str, usize)> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+
+    while let Some(node) = queue.pop_front() {
+        for &(next, elem) in adjacent.get(node).into_iter().flatten() {
+            if next == from || prev.contains_key(next) {
+                continue;
+            }
+            prev.insert(next, (node, elem));
+
+            if next == to {
+                let mut path = Vec::new();
+                let mut curr = to;
+                while curr != from {
+                    let (p, e) = prev[curr];
+                    path.push(e);
+                    curr = p;
+                }
+                return Some(path);
+            }
+            queue.push_back(next);
+        }
+    }
+
+    None
+}
+
+// Groups of nodes without a DC path to ground. Cut off by capacitors they only
+// simulate thanks to the gmin shunts, cut off by current sources the current
+// has nowhere to go.
+fn check_dc_paths(elems: &[Box<dyn Stamp>]) -> Vec<Finding> {
+    let mut groups = NodeGroups::default();
+    groups.add(GND);
+    for elem in elems.iter() {
+        let nodes = elem.get_nodes();
+        for n in nodes.iter() {
+            groups.add(n);
+        }
+        for (a, b) in dc_branches(elem.as_ref()) {
+            groups.join(&nodes[a], &nodes[b]);
+        }
+    }
+
+    let gnd = groups.find(GND);
+    let mut floating: Vec<usize> = Vec::new();
+    for node in groups.names.iter() {
+        let root = groups.find(node);
+        if root != gnd && !floating.contains(&root) {
+            floating.push(root);
+        }
+    }
+
+    floating
+        .into_iter()
+        .map(|root| {
+            let nodes = groups
+                .names
+                .iter()
+                .filter(|n| groups.find(n) == root)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            // Elements with nodes both inside and outside the group
+            let cut = elems
+                .iter()
+                .filter(|e| {
+                    let inside = e.get_nodes().iter().filter(|n| nodes.contains(n)).count();
+                    inside > 0 && inside < e.get_nodes().len()
+                })
+                .collect::<Vec<_>>();
+            let names = cut
+                .iter()
+                .map(|e| String::from(e.get_name()))
+                .collect::<Vec<_>>();
+
+            let plural = if nodes.len() > 1 { "s" } else { "" };
+            if cut.iter().any(|e| is_current_source(e.as_ref())) {
+                let message = format!(
+                    "Node{} {} only connected through current sources and capacitors: {}",
+                    plural,
+                    nodes.join(", "),
+                    names.join(", ")
+                );
+                Finding::error(names, message)
+            } else {
+                let message = format!(
+                    "Node{} {} without a DC path to ground",
+                    plural,
+                    nodes.join(", ")
+                );
+                Finding::warning(names, message)
+            }
+        })
+        .collect()
+}
+
+// Nodes a single element connects to, e.g. a typo in a node name
+fn check_dangling_nodes(elems: &[Box<dyn Stamp>]) -> Vec<Finding> {
+    let mut connections: Vec<(&str, Vec<usize>)> = Vec::new();
+
+    for (i, elem) in elems.iter().enumerate() {
+        for node in elem.get_nodes().iter().filter(|n| *n != GND) {
+            match connections.iter_mut().find(|(n, _)| n == node) {
+                Some((_, idxs)) => idxs.push(i),
+                None => connections.push((node, vec![i])),
+            }
+        }
+    }
+
+    connections
+        .into_iter()
+        .filter(|(_, idxs)| idxs.len() == 1)
+        .map(|(node, idxs)| {
+            let name = elems[idxs[0]].get_name();
+            Finding::warning(
+                vec![String::from(name)],
+                format!("Node {} is only connected to {}", node, name),
+            )
+        })
+        .collect()
+}
+
+// Union-find over node names, kept in the order they were first seen
+#[derive(Default)]
+struct NodeGroups {
+    names: Vec<String>,
+    idx: HashMap<String, usize>,
+    parent: Vec<usize>,
+}
+
+impl NodeGroups {
+    fn add(&mut self, name: &str) {
+        if !self.idx.contains_key(name) {
+            self.idx.insert(String::from(name), self.names.len());
+            self.parent.push(self.names.len());
+            self.names.push(String::from(name));
+        }
+    }
+
+    fn find(&self, name: &str) -> usize {
+        let mut i = self.idx[name];
+        while self.parent[i] != i {
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn join(&mut self, a: &str, b: &str) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        // The lower index stays the root, so ground stays its group's
+        self.parent[ra.max(rb)] = ra.min(rb);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::circuit::Circuit;
     use crate::device;
+    use crate::parser::dialect::Dialect;
+    use crate::parser::parse_spice_file;
+
+    fn messages(findings: &[Finding]) -> Vec<&str> {
+        findings.iter().map(|f| f.message.as_str()).collect()
+    }
 
     #[test]
-    #[should_panic(expected = "Duplicate elems found!")]
     fn test_duplicate_names_fail() {
         let elems: Vec<Box<dyn Stamp>> = vec![
             Box::new(device::res::Res {
@@ -42,7 +406,10 @@ mod tests {
             }),
         ];
 
-        check_duplicate_names(&elems);
+        assert_eq!(
+            messages(&check_duplicate_names(&elems)),
+            ["Duplicate element R1"]
+        );
     }
 
     #[test]
@@ -60,11 +427,10 @@ mod tests {
             }),
         ];
 
-        check_duplicate_names(&elems);
+        assert!(check_duplicate_names(&elems).is_empty());
     }
 
     #[test]
-    #[should_panic(expected = "GND node not found!")]
     fn test_no_gnd_fail() {
         let elems: Vec<Box<dyn Stamp>> = vec![Box::new(device::res::Res {
             name: String::from("R1"),
@@ -72,7 +438,10 @@ mod tests {
             val: 1e3,
         })];
 
-        check_gnd(&elems);
+        let finding = check_gnd(&elems).unwrap();
+
+        assert_eq!(finding.severity, Severity::Error);
+        assert_eq!(finding.to_string(), "error: Ground node 0 not found");
     }
 
     #[test]
@@ -83,6 +452,195 @@ mod tests {
             val: 1e3,
         })];
 
-        check_gnd(&elems);
+        assert!(check_gnd(&elems).is_none());
+    }
+
+    #[test]
+    fn test_no_elems() {
+        let findings = check_elems(&[]);
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].to_string(), "error: Ground node 0 not found");
+    }
+
+    #[test]
+    fn test_ctrl_source_missing() {
+        let elems: Vec<Box<dyn Stamp>> = vec![Box::new(device::cccs::CCCS {
+            name: String::from("F1"),
+            nodes: vec![String::from("0"), String::from("1")],
+            ctrl: String::from("V1"),
+            val: 2.0,
+        })];
+
+        let findings = check_ctrl_sources(&elems);
+
+        assert_eq!(
+            messages(&findings),
+            ["Controlling source V1 of F1 not found"]
+        );
+        assert_eq!(findings[0].elems, ["F1"]);
+    }
+
+    #[test]
+    fn test_ctrl_source_succeed() {
+        let elems: Vec<Box<dyn Stamp>> = vec![
+            Box::new(device::ccvs::CCVS {
+                name: String::from("H1"),
+                nodes: vec![String::from("0"), String::from("1")],
+                ctrl: String::from("V1"),
+                val: 1e3,
+            }),
+            Box::new(device::vdd::Vdd {
+                name: String::from("V1"),
+                nodes: vec![String::from("0"), String::from("2")],
+                val: 1.0,
+                ac_mag: 0.0,
+                ac_phase: 0.0,
+                tran_fn: None,
+            }),
+        ];
+
+        assert!(check_ctrl_sources(&elems).is_empty());
+    }
+
+    #[test]
+    fn test_values() {
+        let (elems, _) = Circuit::new()
+            .resistor("R1", "1", "0", 0.0)
+            .resistor("R2", "1", "0", -50.0)
+            .capacitor("C1", "1", "0", 0.0)
+            .inductor("X1.L1", "1", "0", 1e-6)
+            .build();
+
+        let findings = check_values(&elems);
+
+        assert_eq!(
+            messages(&findings),
+            [
+                "R1 has a zero resistance",
+                "R2 has a negative value",
+                "C1 has a zero value"
+            ]
+        );
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert_eq!(findings[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_voltage_loop() {
+        let (elems, _) = Circuit::new()
+            .vsource("V1", "1", "0", 1.0)
+            .resistor("R1", "1", "2", 1e3)
+            .inductor("L1", "1", "3", 1e-6)
+            .vcvs("E1", ["3", "0", "2", "0"], 2.0)
+            .build();
+
+        let findings = check_voltage_loops(&elems);
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].elems, ["V1", "L1", "E1"]);
+        assert_eq!(
+            findings[0].message,
+            "Loop of voltage sources and inductors: V1, L1, E1"
+        );
+    }
+
+    #[test]
+    fn test_no_voltage_loop() {
+        let (elems, _) = Circuit::new()
+            .vsource("V1", "1", "0", 1.0)
+            .inductor("L1", "1", "2", 1e-6)
+            .resistor("R1", "2", "0", 1e3)
+            .build();
+
+        assert!(check_voltage_loops(&elems).is_empty());
+    }
+
+    #[test]
+    fn test_no_dc_path_behind_capacitor() {
+        let (elems, _) = Circuit::new()
+            .vsource("V1", "1", "0", 1.0)
+            .capacitor("C1", "1", "2", 1e-9)
+            .resistor("R1", "2", "3", 1e3)
+            .nmos("M1", ["0", "3", "0"], Default::default())
+            .build();
+
+        let findings = check_dc_paths(&elems);
+
+        assert_eq!(
+            messages(&findings),
+            ["Nodes 2, 3 without a DC path to ground"]
+        );
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert_eq!(findings[0].elems, ["C1", "M1"]);
+    }
+
+    #[test]
+    fn test_current_source_cutset() {
+        let (elems, _) = Circuit::new()
+            .isource("I1", "0", "1", 1e-3)
+            .capacitor("C1", "1", "0", 1e-9)
+            .build();
+
+        let findings = check_dc_paths(&elems);
+
+        assert_eq!(
+            messages(&findings),
+            ["Node 1 only connected through current sources and capacitors: I1, C1"]
+        );
+        assert_eq!(findings[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_dangling_node() {
+        let (elems, _) = Circuit::new()
+            .vsource("V1", "1", "0", 1.0)
+            .resistor("R1", "1", "2", 1e3)
+            .resistor("R2", "1", "0", 1e3)
+            .build();
+
+        assert_eq!(
+            messages(&check_dangling_nodes(&elems)),
+            ["Node 2 is only connected to R1"]
+        );
+    }
+
+    #[test]
+    fn test_topology_netlist() {
+        let (elems, _) = parse_spice_file("test/topology.sp", Dialect::Native).unwrap();
+
+        let findings = check_elems(&elems);
+
+        assert_eq!(
+            messages(&findings),
+            [
+                "R12 has a zero resistance",
+                "Loop of voltage sources and inductors: L20, V20",
+                "Node 3 only connected through current sources and capacitors: I03, C30",
+                "C14 has a negative value",
+                "Nodes 4, 5 without a DC path to ground",
+                "Node 5 is only connected to R45"
+            ]
+        );
+        assert_eq!(findings[4].elems, ["C14"]);
+    }
+
+    #[test]
+    fn test_check_elems_errors_first() {
+        let (elems, _) = Circuit::new()
+            .vsource("V1", "1", "0", 1.0)
+            .resistor("R1", "1", "2", 1e3)
+            .vsource("V2", "1", "0", 1.0)
+            .build();
+
+        let findings = check_elems(&elems);
+
+        assert_eq!(
+            messages(&findings),
+            [
+                "Loop of voltage sources and inductors: V1, V2",
+                "Node 2 is only connected to R1"
+            ]
+        );
     }
 }
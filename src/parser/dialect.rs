@@ -0,0 +1,94 @@
+// Netlist flavours that read the same text differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    // Case sensitive scale factors with `M` for mega, diodes and current sources
+    // written with their terminals swapped
+    #[default]
+    Native,
+    // SPICE3/ngspice: the first line is a title and scale factors are case
+    // insensitive, so `M` is milli and mega is `MEG`
+    Spice3,
+}
+
+impl Dialect {
+    // Multiplier for the letters trailing a number. Whatever follows the scale
+    // factor is a unit, e.g. the `V` in `10mV`, and doesn't change the value.
+    pub fn scale(&self, suffix: &str) -> f64 {
+        let lower = suffix.to_lowercase();
+
+        if lower.starts_with("meg") {
+            return 1e6;
+        }
+        if lower.starts_with("mil") {
+            return 25.4e-6;
+        }
+
+        match self {
+            Dialect::Native => {
+                const PREFIXES: &[(&str, f64)] = &[
+                    ("T", 1e12),
+                    ("G", 1e9),
+                    ("M", 1e6),
+                    ("k", 1e3),
+                    ("h", 1e2),
+                    ("da", 1e1),
+                    ("d", 1e-1),
+                    ("c", 1e-2),
+                    ("m", 1e-3),
+                    ("u", 1e-6),
+                    ("n", 1e-9),
+                    ("p", 1e-12),
+                    ("f", 1e-15),
+                ];
+
+                PREFIXES
+                    .iter()
+                    .find(|(p, _)| suffix.starts_with(p))
+                    .map_or(1.0, |(_, mult)| *mult)
+            }
+            Dialect::Spice3 => match lower.chars().next() {
+                Some('t') => 1e12,
+                Some('g') => 1e9,
+                Some('k') => 1e3,
+                Some('m') => 1e-3,
+                Some('u') => 1e-6,
+                Some('n') => 1e-9,
+                Some('p') => 1e-12,
+                Some('f') => 1e-15,
+                _ => 1.0,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_native() {
+        let d = Dialect::Native;
+
+        assert_eq!(d.scale("M"), 1e6);
+        assert_eq!(d.scale("m"), 1e-3);
+        assert_eq!(d.scale("da"), 1e1);
+        assert_eq!(d.scale("mV"), 1e-3);
+        assert_eq!(d.scale("V"), 1.0);
+        assert_eq!(d.scale("Meg"), 1e6);
+    }
+
+    #[test]
+    fn test_scale_spice3() {
+        let d = Dialect::Spice3;
+
+        assert_eq!(d.scale("M"), 1e-3);
+        assert_eq!(d.scale("MEG"), 1e6);
+        assert_eq!(d.scale("meghz"), 1e6);
+        assert_eq!(d.scale("mil"), 25.4e-6);
+        assert_eq!(d.scale("K"), 1e3);
+        assert_eq!(d.scale("pF"), 1e-12);
+        assert_eq!(d.scale("F"), 1e-15);
+        assert_eq!(d.scale("V"), 1.0);
+        assert_eq!(d.scale("h"), 1.0);
+    }
+}
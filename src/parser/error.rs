@@ -0,0 +1,280 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use pest::error::{ErrorVariant, InputLocation};
+use pest::iterators::Pair;
+use pest::Position;
+
+use crate::parser::Rule;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub col: usize,
+    pub source: String,
+    pub message: String,
+}
+
+pub type ParseResult<T> = Result<T, ParseError>;
+
+impl ParseError {
+    // Points at `pair`. The file is left for the caller to fill in with `in_file`,
+    // since pairs don't know which netlist they were parsed from.
+    pub fn at(pair: &Pair<Rule>, message: String) -> Self {
+        let (line, col) = pair.line_col();
+        let source = pair.as_span().start_pos().line_of().trim_end();
+
+        ParseError {
+            file: PathBuf::new(),
+            line,
+            col,
+            source: String::from(source),
+            message,
+        }
+    }
+
+    // Errors about a whole file rather than a line in it, e.g. it can't be read
+    pub fn file(file: &Path, message: String) -> Self {
+        ParseError {
+            file: file.to_path_buf(),
+            line: 0,
+            col: 0,
+            source: String::new(),
+            message,
+        }
+    }
+
+    // Needs `pest::set_error_detail(true)` to point at the token that was actually
+    // wrong, otherwise pest only reports the last rule it tried, e.g. the line start
+    pub fn from_pest(file: &Path, text: &str, err: pest::error::Error<Rule>) -> Self {
+        let mut pos = match err.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+        let positives = match &err.variant {
+            ErrorVariant::ParsingError { positives, .. } => positives.clone(),
+            ErrorVariant::CustomError { .. } => Vec::new(),
+        };
+        let mut expected = positives.iter().map(describe).collect::<Vec<_>>();
+
+        // Only look ahead within the line, pest also tries the next one for a `+`
+        // continuation whenever it could have skipped whitespace
+        if let Some(attempts) = err.parse_attempts() {
+            let ahead = text.get(pos..attempts.max_position).unwrap_or("");
+            if (attempts.max_position > pos || expected.is_empty()) && !ahead.contains('\n') {
+                pos = attempts.max_position;
+                expected = attempts
+                    .expected_tokens()
+                    .iter()
+                    .filter_map(|t| describe_token(&t.to_string()))
+                    .collect();
+            }
+        }
+
+        let position = Position::new(text, pos).unwrap();
+        let (line, col) = position.line_col();
+        let source = position.line_of().trim_end();
+
+        let before = source.chars().take(col - 1).collect::<String>();
+        let found = source
+            .chars()
+            .skip(col - 1)
+            .take_while(|c| !c.is_whitespace())
+            .collect::<String>();
+
+        let mut unique = Vec::new();
+        for e in expected {
+            if !unique.contains(&e) {
+                unique.push(e);
+            }
+        }
+
+        // Running out of input means a block or the netlist itself wasn't closed
+        if text[pos..].trim().is_empty() && positives.contains(&Rule::node) {
+            unique = match positives.contains(&Rule::command) {
+                true => vec![String::from("`.end`")],
+                false => vec![String::from("`.ends`")],
+            };
+        }
+
+        let mut message = match (unique.is_empty(), found.as_str()) {
+            (false, _) => format!("expected {}", join_alternatives(&unique)),
+            (true, "") => String::from("unexpected end of line"),
+            (true, found) => format!("unexpected `{}`", found),
+        };
+        if let Some(prev) = before.split_whitespace().last() {
+            message = format!("{} after `{}`", message, prev);
+        }
+
+        ParseError {
+            file: file.to_path_buf(),
+            line,
+            col,
+            source: String::from(source),
+            message,
+        }
+    }
+
+    // Sets the file unless an inner parser already knew better, e.g. for
+    // subcircuit bodies defined in a different file than their instances
+    pub fn in_file(mut self, file: &Path) -> Self {
+        if self.file.as_os_str().is_empty() {
+            self.file = file.to_path_buf();
+        }
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "error: {}", self.message)?;
+
+        if self.line == 0 {
+            return write!(f, " --> {}", self.file.display());
+        }
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(
+            f,
+            "{}--> {}:{}:{}",
+            gutter,
+            self.file.display(),
+            self.line,
+            self.col
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source)?;
+        write!(f, "{} | {}^", gutter, " ".repeat(self.col - 1))
+    }
+}
+
+fn describe(rule: &Rule) -> String {
+    let desc = match rule {
+        Rule::value | Rule::literal | Rule::number | Rule::brace_expr => "value",
+        Rule::dc_value => "DC value",
+        Rule::ac_value => "AC value",
+        Rule::fn_value => "source function",
+        Rule::pwl_point => "time and value",
+        Rule::pwl_option => "`r=` or `td=`",
+        Rule::expr => "expression",
+        Rule::name | Rule::x_arg | Rule::subckt_port => "node name",
+        Rule::node => "element",
+        // Nothing on the line could start anything
+        Rule::file | Rule::lib_file | Rule::netlist => "element or dot command",
+        Rule::command
+        | Rule::model_card
+        | Rule::subckt
+        | Rule::param_cmd
+        | Rule::include_cmd
+        | Rule::lib_cmd
+        | Rule::lib_start
+        | Rule::lib_end => "dot command",
+        Rule::v_name => "voltage source name",
+        Rule::model_name => "model name",
+        Rule::model_type => "model type",
+        Rule::param_name => "parameter name",
+        Rule::subckt_name => "subcircuit name",
+        Rule::suffix => "scale factor",
+        Rule::sweep_type => "`dec`, `oct` or `lin`",
+        Rule::dc_sweep | Rule::temp_var | Rule::param_var => "sweep",
+        Rule::option_assign => "option",
+        Rule::option_word => "option value",
+        Rule::file_path => "file path",
+        Rule::section_name => "library section name",
+        Rule::EOI => "end of file",
+        _ => return format!("{:?}", rule).replace('_', " "),
+    };
+
+    String::from(desc)
+}
+
+// Tokens come formatted by pest, e.g. `C` or `0..9`
+fn describe_token(token: &str) -> Option<String> {
+    match token.trim() {
+        // Comments are allowed almost anywhere, suggesting them is just noise
+        "" | "BUILTIN_RULE" | "*" | "$" => None,
+        "0..9" | "1..9" => Some(String::from("digit")),
+        t => Some(format!("`{}`", t)),
+    }
+}
+
+fn join_alternatives(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [init @ .., last] => format!("{} or {}", init.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SpiceParser;
+    use pest::Parser;
+
+    fn syntax_error(netlist: &str) -> ParseError {
+        pest::set_error_detail(true);
+        let err = SpiceParser::parse(Rule::file, netlist).unwrap_err();
+        ParseError::from_pest(Path::new("test.sp"), netlist, err)
+    }
+
+    #[test]
+    fn test_from_pest_missing_value() {
+        let err = syntax_error("V1 1 0 1V\nR1 1 0 R=\n.end\n");
+
+        assert_eq!(err.line, 2);
+        assert_eq!(err.col, 10);
+        assert_eq!(err.source, "R1 1 0 R=");
+        assert_eq!(err.message, "expected value after `R=`");
+    }
+
+    #[test]
+    fn test_from_pest_wrong_token() {
+        let err = syntax_error("V1 1 0 1V\nV2 1 0 SIN 1 2 3\n.end\n");
+
+        assert_eq!((err.line, err.col), (2, 12));
+        assert_eq!(err.message, "expected `(` after `SIN`");
+    }
+
+    #[test]
+    fn test_from_pest_missing_end() {
+        let err = syntax_error("V1 1 0 1V\n");
+
+        assert_eq!(err.line, 2);
+        assert_eq!(err.message, "expected `.end`");
+    }
+
+    #[test]
+    fn test_from_pest_missing_ends() {
+        let err = syntax_error(".subckt a x\nR1 x 0 R=1k\n");
+
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "expected `.ends`");
+    }
+
+    #[test]
+    fn test_at() {
+        let pair = SpiceParser::parse(Rule::r_node, "R1 1 0 R={x}")
+            .unwrap()
+            .next()
+            .unwrap();
+        let value = pair.into_inner().nth(3).unwrap();
+        let err = ParseError::at(&value, String::from("undefined parameter x"));
+
+        assert_eq!((err.line, err.col), (1, 10));
+        assert_eq!(err.source, "R1 1 0 R={x}");
+        assert_eq!(err.file, PathBuf::new());
+        assert_eq!(err.in_file(Path::new("a.sp")).file, Path::new("a.sp"));
+    }
+
+    #[test]
+    fn test_display() {
+        let err = syntax_error("V1 1 0 1V\nR1 1 0 R=\n.end\n");
+
+        assert_eq!(
+            err.to_string(),
+            "error: expected value after `R=`\n --> test.sp:2:10\n  |\n2 | R1 1 0 R=\n  |          ^"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use pest::iterators::Pair;
+use pest::pratt_parser::{Assoc, Op, PrattParser};
+
+use crate::parser::dialect::Dialect;
+use crate::parser::{parse_literal, Rule};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Param(String),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+fn pratt_parser() -> PrattParser<Rule> {
+    PrattParser::new()
+        .op(Op::infix(Rule::add, Assoc::Left) | Op::infix(Rule::sub, Assoc::Left))
+        .op(Op::infix(Rule::mul, Assoc::Left) | Op::infix(Rule::div, Assoc::Left))
+        .op(Op::prefix(Rule::neg))
+        .op(Op::infix(Rule::pow, Assoc::Right))
+}
+
+pub fn parse_expr(expr: Pair<Rule>, dialect: Dialect) -> Expr {
+    build_expr(expr, &pratt_parser(), dialect)
+}
+
+fn build_expr(expr: Pair<Rule>, pratt: &PrattParser<Rule>, dialect: Dialect) -> Expr {
+    pratt
+        .map_primary(|primary| match primary.as_rule() {
+            Rule::literal => Expr::Num(parse_literal(primary, dialect)),
+            Rule::param_name => Expr::Param(primary.as_str().to_lowercase()),
+            Rule::expr => build_expr(primary, pratt, dialect),
+            Rule::func_call => {
+                let mut call_details = primary.into_inner();
+                let name = call_details.next().unwrap().as_str().to_lowercase();
+                let args = call_details
+                    .map(|a| build_expr(a, pratt, dialect))
+                    .collect();
+                Expr::Call(name, args)
+            }
+            _ => unreachable!(),
+        })
+        .map_prefix(|op, rhs| match op.as_rule() {
+            Rule::neg => Expr::Neg(Box::new(rhs)),
+            _ => unreachable!(),
+        })
+        .map_infix(|lhs, op, rhs| {
+            let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+            match op.as_rule() {
+                Rule::add => Expr::Add(lhs, rhs),
+                Rule::sub => Expr::Sub(lhs, rhs),
+                Rule::mul => Expr::Mul(lhs, rhs),
+                Rule::div => Expr::Div(lhs, rhs),
+                Rule::pow => Expr::Pow(lhs, rhs),
+                _ => unreachable!(),
+            }
+        })
+        .parse(expr.into_inner())
+}
+
+impl Expr {
+    pub fn eval(&self, lookup: &mut dyn FnMut(&str) -> Result<f64, String>) -> Result<f64, String> {
+        let val = match self {
+            Expr::Num(x) => *x,
+            Expr::Param(name) => lookup(name)?,
+            Expr::Neg(x) => -x.eval(lookup)?,
+            Expr::Add(a, b) => a.eval(lookup)? + b.eval(lookup)?,
+            Expr::Sub(a, b) => a.eval(lookup)? - b.eval(lookup)?,
+            Expr::Mul(a, b) => a.eval(lookup)? * b.eval(lookup)?,
+            Expr::Div(a, b) => match (a.eval(lookup)?, b.eval(lookup)?) {
+                (_, 0.0) => return Err(String::from("division by zero")),
+                (x, y) => x / y,
+            },
+            Expr::Pow(a, b) => a.eval(lookup)?.powf(b.eval(lookup)?),
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| a.eval(lookup))
+                    .collect::<Result<Vec<_>, _>>()?;
+                call(name, &args)?
+            }
+        };
+
+        // An element built from it would only make the results nonsense
+        match val.is_finite() {
+            true => Ok(val),
+            false => Err(format!("expression evaluates to {}", val)),
+        }
+    }
+}
+
+fn call(name: &str, args: &[f64]) -> Result<f64, String> {
+    let unary: Option<fn(f64) -> f64> = match name {
+        "sqrt" => Some(f64::sqrt),
+        "exp" => Some(f64::exp),
+        "ln" | "log" => Some(f64::ln),
+        "log10" => Some(f64::log10),
+        "sin" => Some(f64::sin),
+        "cos" => Some(f64::cos),
+        "tan" => Some(f64::tan),
+        "atan" => Some(f64::atan),
+        "abs" => Some(f64::abs),
+        "floor" => Some(f64::floor),
+        "ceil" => Some(f64::ceil),
+        _ => None,
+    };
+    let binary: Option<fn(f64, f64) -> f64> = match name {
+        "pow" => Some(f64::powf),
+        "min" => Some(f64::min),
+        "max" => Some(f64::max),
+        "atan2" => Some(f64::atan2),
+        _ => None,
+    };
+
+    match (unary, binary, args) {
+        (Some(f), _, [x]) => Ok(f(*x)),
+        (_, Some(f), [x, y]) => Ok(f(*x, *y)),
+        (Some(_), _, _) => Err(format!("function {} expects 1 argument", name)),
+        (_, Some(_), _) => Err(format!("function {} expects 2 arguments", name)),
+        _ => Err(format!("unknown function {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SpiceParser;
+    use pest::Parser;
+
+    const EPS: f64 = 1e-12;
+
+    fn eval_str(expr: &str) -> Result<f64, String> {
+        let pair = SpiceParser::parse(Rule::expr, expr)
+            .unwrap()
+            .next()
+            .unwrap();
+        parse_expr(pair, Dialect::Native).eval(&mut |name| match name {
+            "r" => Ok(1e3),
+            "f0" => Ok(1e6),
+            "pi" => Ok(std::f64::consts::PI),
+            _ => Err(format!("undefined parameter {}", name)),
+        })
+    }
+
+    #[test]
+    fn test_eval_literals() {
+        assert_eq!(eval_str("2.2k"), Ok(2.2e3));
+        assert_eq!(eval_str("-1.5"), Ok(-1.5));
+    }
+
+    #[test]
+    fn test_eval_precedence() {
+        assert_eq!(eval_str("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(eval_str("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(eval_str("8 / 4 / 2"), Ok(1.0));
+        assert_eq!(eval_str("2 ^ 3 ^ 2"), Ok(512.0));
+        assert_eq!(eval_str("-2 ^ 2"), Ok(-4.0));
+        assert_eq!(eval_str("3 - -2"), Ok(5.0));
+    }
+
+    #[test]
+    fn test_eval_params() {
+        assert_eq!(eval_str("2*R"), Ok(2e3));
+
+        let c = eval_str("1/(2*pi*f0*r)").unwrap();
+        assert!((c - 1.0 / (2.0 * std::f64::consts::PI * 1e9)).abs() < EPS);
+    }
+
+    #[test]
+    fn test_eval_functions() {
+        assert_eq!(eval_str("sqrt(16)"), Ok(4.0));
+        assert_eq!(eval_str("max(r, 2k)"), Ok(2e3));
+        assert_eq!(eval_str("abs(-3) + pow(2, 10)"), Ok(1027.0));
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        assert_eq!(
+            eval_str("2*rl"),
+            Err(String::from("undefined parameter rl"))
+        );
+        assert_eq!(
+            eval_str("sqrt(1, 2)"),
+            Err(String::from("function sqrt expects 1 argument"))
+        );
+        assert_eq!(
+            eval_str("foo(1)"),
+            Err(String::from("unknown function foo"))
+        );
+    }
+
+    #[test]
+    fn test_eval_not_finite() {
+        assert_eq!(eval_str("1/(r-1k)"), Err(String::from("division by zero")));
+        assert_eq!(
+            eval_str("sqrt(-1)"),
+            Err(String::from("expression evaluates to NaN"))
+        );
+        assert_eq!(
+            eval_str("10^400"),
+            Err(String::from("expression evaluates to inf"))
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use pest::iterators::Pair;
+
+use crate::device::{diode, nmos, npn};
+use crate::parser::error::{ParseError, ParseResult};
+use crate::parser::scope::Scope;
+use crate::parser::{parse_value, Rule};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Model {
+    Diode(diode::model::Params),
+    NPN(npn::model::Params),
+    NMOS(nmos::model::Params),
+}
+
+pub type ModelTable = HashMap<String, Model>;
+
+// Models available to every netlist without a `.model` card
+pub fn builtin_models() -> ModelTable {
+    let d_model = diode::model::Params {
+        is: 1.0e-12,
+        n: 1.0,
+        ..Default::default()
+    };
+    let q_model = npn::model::Params {
+        is: 1.98e-14,
+        bf: 99.0,
+        br: 0.02 / 0.98,
+        nf: 1.0,
+        nr: 1.0,
+        ..Default::default()
+    };
+    let t_model = nmos::model::Params {
+        kp: 0.5e-3,
+        vto: 0.6,
+        lambda: 0.01,
+    };
+
+    HashMap::from([
+        (String::from("d_model"), Model::Diode(d_model)),
+        (String::from("q_model"), Model::NPN(q_model)),
+        (String::from("t_model"), Model::NMOS(t_model)),
+    ])
+}
+
+pub fn parse_model_card(card: Pair<Rule>, scope: &Scope) -> ParseResult<(String, Model)> {
+    let mut card_details = card.into_inner();
+
+    let name = card_details.next().unwrap().as_str();
+    let mtype = card_details.next().unwrap();
+
+    let mut model = match mtype.as_str().to_lowercase().as_str() {
+        "d" => Model::Diode(diode::model::Params::default()),
+        "npn" => Model::NPN(npn::model::Params::default()),
+        "nmos" => Model::NMOS(nmos::model::Params::default()),
+        _ => {
+            let msg = format!("Unsupported type {} for model {}", mtype.as_str(), name);
+            return Err(ParseError::at(&mtype, msg));
+        }
+    };
+
+    for param in card_details {
+        let mut param_details = param.into_inner();
+        let param_name = param_details.next().unwrap();
+        let value = parse_value(param_details.next().unwrap(), scope)?;
+
+        let key = param_name.as_str();
+        let (known, valid_names) = match &mut model {
+            Model::Diode(p) => (p.set(key, value), diode::model::Params::NAMES),
+            Model::NPN(p) => (p.set(key, value), npn::model::Params::NAMES),
+            Model::NMOS(p) => (p.set(key, value), nmos::model::Params::NAMES),
+        };
+
+        if !known {
+            let msg = format!(
+                "Unknown parameter {} in model {}, expected one of: {}",
+                key,
+                name,
+                valid_names.join(", ")
+            );
+            return Err(ParseError::at(&param_name, msg));
+        }
+    }
+
+    Ok((name.to_lowercase(), model))
+}
+
+pub fn get_model<'a>(models: &'a ModelTable, name: &Pair<Rule>) -> ParseResult<&'a Model> {
+    models
+        .get(&name.as_str().to_lowercase())
+        .ok_or_else(|| ParseError::at(name, format!("Model {} not found", name.as_str())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SpiceParser;
+    use pest::Parser;
+
+    fn parse(card: &str) -> ParseResult<(String, Model)> {
+        let pair = SpiceParser::parse(Rule::model_card, card)
+            .unwrap()
+            .next()
+            .unwrap();
+        parse_model_card(pair, &Scope::default())
+    }
+
+    #[test]
+    fn parse_model_card_diode() {
+        let (name, model) = parse(".model D1N4148 D (IS=2.5n N=1.8)").unwrap();
+
+        assert_eq!(name, "d1n4148");
+        assert_eq!(
+            model,
+            Model::Diode(diode::model::Params {
+                is: 2.5e-9,
+                n: 1.8,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_model_card_defaults() {
+        let (_, model) = parse(".MODEL fast npn (bf=200)").unwrap();
+
+        assert_eq!(
+            model,
+            Model::NPN(npn::model::Params {
+                bf: 200.0,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_model_card_no_parens() {
+        let (_, model) = parse(".model small nmos kp=1m vto=0.7").unwrap();
+
+        assert_eq!(
+            model,
+            Model::NMOS(nmos::model::Params {
+                kp: 1e-3,
+                vto: 0.7,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_model_card_unknown_param() {
+        let err = parse(".model small nmos (vt0=0.7)").unwrap_err();
+
+        assert!(err
+            .message
+            .starts_with("Unknown parameter vt0 in model small"));
+        assert_eq!(err.col, 20);
+    }
+
+    #[test]
+    fn parse_model_card_unknown_type() {
+        let err = parse(".model q2 pnp (bf=50)").unwrap_err();
+
+        assert_eq!(err.message, "Unsupported type pnp for model q2");
+        assert_eq!(err.col, 11);
+    }
+}
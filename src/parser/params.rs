@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use pest::iterators::Pair;
+
+use crate::parser::dialect::Dialect;
+use crate::parser::error::{ParseError, ParseResult};
+use crate::parser::expr::{self, Expr};
+use crate::parser::Rule;
+
+#[derive(Debug)]
+pub struct ParamDef<'i> {
+    pub expr: Expr,
+    pub assign: Pair<'i, Rule>,
+    pub file: &'i Path,
+}
+
+pub type ParamDefs<'i> = HashMap<String, ParamDef<'i>>;
+
+pub fn parse_param_cmd<'i>(
+    cmd: Pair<'i, Rule>,
+    file: &'i Path,
+    dialect: Dialect,
+) -> Vec<(String, ParamDef<'i>)> {
+    cmd.into_inner()
+        .map(|assign| {
+            let mut assign_details = assign.clone().into_inner();
+            let name = assign_details.next().unwrap().as_str().to_lowercase();
+            let expr = expr::parse_expr(assign_details.next().unwrap(), dialect);
+            (name, ParamDef { expr, assign, file })
+        })
+        .collect()
+}
+
+// Evaluates every `.param` once the whole netlist is known, so definitions can
+// reference parameters declared further down the file. `overrides` replace the
+// definitions of the parameters a `.dc` sweeps, everything depending on them follows.
+pub fn resolve_params(
+    defs: &ParamDefs,
+    overrides: &HashMap<String, f64>,
+) -> ParseResult<HashMap<String, f64>> {
+    let mut values = HashMap::from([
+        (String::from("pi"), std::f64::consts::PI),
+        (String::from("e"), std::f64::consts::E),
+    ]);
+    values.retain(|name, _| !defs.contains_key(name));
+    values.extend(overrides.clone());
+
+    let mut names = defs.keys().collect::<Vec<_>>();
+    names.sort_by_key(|n| (defs[*n].file, defs[*n].assign.line_col(), *n));
+
+    for name in names {
+        resolve(name, defs, &mut values, &mut Vec::new())?;
+    }
+
+    Ok(values)
+}
+
+fn resolve(
+    name: &str,
+    defs: &ParamDefs,
+    values: &mut HashMap<String, f64>,
+    stack: &mut Vec<String>,
+) -> ParseResult<f64> {
+    if let Some(val) = values.get(name) {
+        return Ok(*val);
+    }
+
+    let def = &defs[name];
+
+    if stack.iter().any(|n| n == name) {
+        stack.push(String::from(name));
+        let msg = format!("circular parameter reference {}", stack.join(" -> "));
+        return Err(ParseError::at(&def.assign, msg).in_file(def.file));
+    }
+
+    // Errors in the parameters this one depends on are reported where they happen
+    let mut dep_error = None;
+
+    stack.push(String::from(name));
+    let val = def.expr.eval(&mut |dep| {
+        if values.contains_key(dep) || defs.contains_key(dep) {
+            resolve(dep, defs, values, stack).map_err(|e| {
+                dep_error = Some(e);
+                String::new()
+            })
+        } else {
+            Err(format!(
+                "undefined parameter {} in definition of {}",
+                dep, name
+            ))
+        }
+    });
+    let val = val.map_err(|msg| {
+        dep_error.unwrap_or_else(|| ParseError::at(&def.assign, msg).in_file(def.file))
+    })?;
+    stack.pop();
+
+    values.insert(String::from(name), val);
+    Ok(val)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SpiceParser;
+    use pest::Parser;
+
+    fn parse_defs<'i>(cmds: &[&'i str]) -> ParamDefs<'i> {
+        let mut defs = ParamDefs::new();
+        for cmd in cmds {
+            let pair = SpiceParser::parse(Rule::param_cmd, cmd)
+                .unwrap()
+                .next()
+                .unwrap();
+            defs.extend(parse_param_cmd(pair, Path::new("test.sp"), Dialect::Native));
+        }
+        defs
+    }
+
+    #[test]
+    fn test_parse_param_cmd() {
+        let pair = SpiceParser::parse(Rule::param_cmd, ".param Rload=1k f0={2*pi*1M}")
+            .unwrap()
+            .next()
+            .unwrap();
+        let params = parse_param_cmd(pair, Path::new("test.sp"), Dialect::Native);
+
+        assert_eq!(params.len(), 2);
+        assert_eq!(params[0].0, "rload");
+        assert_eq!(params[0].1.expr, Expr::Num(1e3));
+        assert_eq!(params[1].0, "f0");
+        assert_eq!(params[1].1.assign.line_col(), (1, 17));
+    }
+
+    #[test]
+    fn test_resolve_out_of_order() {
+        let defs = parse_defs(&[".param r2={2*r1}", ".param r1=1k"]);
+        let values = resolve_params(&defs, &HashMap::new()).unwrap();
+
+        assert_eq!(values["r1"], 1e3);
+        assert_eq!(values["r2"], 2e3);
+    }
+
+    #[test]
+    fn test_resolve_overrides() {
+        let defs = parse_defs(&[".param r2={2*r1}", ".param r1=1k"]);
+        let values = resolve_params(&defs, &HashMap::from([(String::from("r1"), 5e3)])).unwrap();
+
+        assert_eq!(values["r1"], 5e3);
+        assert_eq!(values["r2"], 1e4);
+    }
+
+    #[test]
+    fn test_resolve_constants() {
+        let defs = parse_defs(&[".param w=2*pi"]);
+        let values = resolve_params(&defs, &HashMap::new()).unwrap();
+
+        assert_eq!(values["w"], 2.0 * std::f64::consts::PI);
+    }
+
+    #[test]
+    fn test_resolve_circular() {
+        let defs = parse_defs(&[".param a={b+1}", ".param b={a*2}"]);
+        let err = resolve_params(&defs, &HashMap::new()).unwrap_err();
+
+        assert_eq!(err.message, "circular parameter reference a -> b -> a");
+        assert_eq!(err.source, ".param a={b+1}");
+        assert_eq!(err.file, Path::new("test.sp"));
+    }
+
+    #[test]
+    fn test_resolve_undefined() {
+        let defs = parse_defs(&[".param a={c+1}"]);
+        let err = resolve_params(&defs, &HashMap::new()).unwrap_err();
+
+        assert_eq!(err.message, "undefined parameter c in definition of a");
+    }
+
+    #[test]
+    fn test_resolve_undefined_in_dependency() {
+        let defs = parse_defs(&[".param a={b}", ".param b={c}"]);
+        let err = resolve_params(&defs, &HashMap::new()).unwrap_err();
+
+        assert_eq!(err.message, "undefined parameter c in definition of b");
+        assert_eq!(err.source, ".param b={c}");
+    }
+}
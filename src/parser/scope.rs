@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::node::GND;
+use crate::parser::dialect::Dialect;
+
+// Naming context used while flattening subcircuit instances
+#[derive(Debug, Default, Clone)]
+pub struct Scope {
+    prefix: String,
+    ports: HashMap<String, String>,
+    params: HashMap<String, f64>,
+    globals: Rc<HashMap<String, f64>>,
+    subckts: Vec<String>,
+    pub dialect: Dialect,
+    // Directory of the netlist being read, data files are relative to it
+    pub dir: PathBuf,
+}
+
+impl Scope {
+    pub fn new(globals: HashMap<String, f64>, dialect: Dialect) -> Self {
+        Scope {
+            globals: Rc::new(globals),
+            dialect,
+            ..Default::default()
+        }
+    }
+
+    // Top level scope sharing the same `.param` values
+    pub fn global(&self) -> Self {
+        Scope {
+            globals: Rc::clone(&self.globals),
+            dialect: self.dialect,
+            ..Default::default()
+        }
+    }
+
+    // Global parameters and the given ones, for subcircuit defaults that refer to
+    // the defaults before them
+    pub fn with_params(&self, params: &HashMap<String, f64>) -> Self {
+        Scope {
+            params: params.iter().map(|(k, v)| (k.to_lowercase(), *v)).collect(),
+            ..self.global()
+        }
+    }
+
+    pub fn instance(
+        &self,
+        inst_name: &str,
+        subckt_name: &str,
+        ports: HashMap<String, String>,
+        params: HashMap<String, f64>,
+    ) -> Self {
+        let mut subckts = self.subckts.clone();
+        subckts.push(subckt_name.to_lowercase());
+
+        Scope {
+            prefix: format!("{}.", inst_name),
+            ports,
+            params: params
+                .into_iter()
+                .map(|(k, v)| (k.to_lowercase(), v))
+                .collect(),
+            globals: Rc::clone(&self.globals),
+            subckts,
+            dialect: self.dialect,
+            dir: self.dir.clone(),
+        }
+    }
+
+    // Same names and parameters, reading data files next to `file`
+    pub fn in_file(&self, file: &Path) -> Self {
+        Scope {
+            dir: file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            ..self.clone()
+        }
+    }
+
+    pub fn is_expanding(&self, subckt_name: &str) -> bool {
+        self.subckts.contains(&subckt_name.to_lowercase())
+    }
+
+    pub fn elem_name(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    pub fn node_name(&self, node: &str) -> String {
+        if node == GND {
+            String::from(GND)
+        } else if let Some(outer) = self.ports.get(node) {
+            outer.clone()
+        } else {
+            format!("{}{}", self.prefix, node)
+        }
+    }
+
+    pub fn param(&self, name: &str) -> Option<f64> {
+        let name = name.to_lowercase();
+
+        self.params
+            .get(&name)
+            .or_else(|| self.globals.get(&name))
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_scope() -> Scope {
+        Scope::new(
+            HashMap::from([(String::from("gain"), 1.0), (String::from("vdd"), 3.3)]),
+            Dialect::Spice3,
+        )
+        .instance(
+            "X1",
+            "amp",
+            HashMap::from([(String::from("in"), String::from("5"))]),
+            HashMap::from([(String::from("Gain"), 10.0)]),
+        )
+    }
+
+    #[test]
+    fn test_top_level_names() {
+        let scope = Scope::default();
+
+        assert_eq!(scope.elem_name("R1"), "R1");
+        assert_eq!(scope.node_name("3"), "3");
+    }
+
+    #[test]
+    fn test_instance_names() {
+        let scope = test_scope();
+
+        assert_eq!(scope.elem_name("R3"), "X1.R3");
+        assert_eq!(scope.node_name("net5"), "X1.net5");
+        assert_eq!(scope.node_name("in"), "5");
+        assert_eq!(scope.node_name("0"), "0");
+    }
+
+    #[test]
+    fn test_nested_instance_names() {
+        let scope = test_scope();
+        let inner = scope.instance(
+            &scope.elem_name("X2"),
+            "stage",
+            HashMap::from([(String::from("a"), scope.node_name("net5"))]),
+            HashMap::new(),
+        );
+
+        assert_eq!(inner.elem_name("R1"), "X1.X2.R1");
+        assert_eq!(inner.node_name("a"), "X1.net5");
+        assert_eq!(inner.node_name("b"), "X1.X2.b");
+        assert!(inner.is_expanding("AMP"));
+        assert!(inner.is_expanding("stage"));
+        assert_eq!(inner.dialect, Dialect::Spice3);
+    }
+
+    #[test]
+    fn test_in_file() {
+        let scope = test_scope().in_file(Path::new("test/lib/diode.inc"));
+
+        assert_eq!(scope.dir, Path::new("test/lib"));
+        assert_eq!(scope.elem_name("R3"), "X1.R3");
+        assert_eq!(scope.param("gain"), Some(10.0));
+    }
+
+    #[test]
+    fn test_param() {
+        let scope = test_scope();
+
+        assert_eq!(scope.param("gain"), Some(10.0));
+        assert_eq!(scope.param("VDD"), Some(3.3));
+        assert_eq!(scope.global().param("gain"), Some(1.0));
+    }
+
+    #[test]
+    fn test_param_undefined() {
+        assert_eq!(Scope::default().param("gain"), None);
+    }
+}
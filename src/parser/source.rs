@@ -0,0 +1,400 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pest::iterators::{Pair, Pairs};
+use pest::Parser;
+
+use crate::parser::dialect::Dialect;
+use crate::parser::error::{ParseError, ParseResult};
+use crate::parser::{Rule, SpiceParser};
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+// Every file reachable from the top level netlist, loaded up front so parsed
+// lines can borrow from them for the rest of the parse. Sections of a `.lib`
+// file nothing selects are never followed.
+#[derive(Debug)]
+pub struct Sources {
+    files: Vec<SourceFile>,
+    includes: Vec<Vec<Include>>,
+    dialect: Dialect,
+}
+
+// An `.include` or `.lib` statement and the `.lib` section it's written in
+#[derive(Debug)]
+struct Include {
+    in_section: Option<String>,
+    path: PathBuf,
+    section: Option<String>,
+    at: ParseError,
+}
+
+#[derive(Debug)]
+pub struct Line<'a> {
+    pub pair: Pair<'a, Rule>,
+    pub file: &'a Path,
+}
+
+// Syntax errors reported per file before giving up on it
+const MAX_SYNTAX_ERRORS: usize = 20;
+
+impl Sources {
+    pub fn load(path: &str, dialect: Dialect) -> Result<Self, Vec<ParseError>> {
+        let path = PathBuf::from(path);
+        let text = fs::read_to_string(&path)
+            .map_err(|e| vec![ParseError::file(&path, format!("cannot read file: {}", e))])?;
+
+        pest::set_error_detail(true);
+
+        let mut sources = Sources {
+            files: Vec::new(),
+            includes: Vec::new(),
+            dialect,
+        };
+        let mut errors = Vec::new();
+        let idx = sources.add(path, text, &mut errors);
+        sources.reach(idx, None, &mut Vec::new(), &mut errors);
+
+        match errors.is_empty() {
+            true => Ok(sources),
+            false => Err(errors),
+        }
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    fn add(&mut self, path: PathBuf, text: String, errors: &mut Vec<ParseError>) -> usize {
+        let idx = self.files.len();
+        self.files.push(SourceFile { path, text });
+
+        let file = &self.files[idx];
+        let mut includes = Vec::new();
+
+        // Blank out each line pest chokes on and try again, so all the syntax
+        // errors in a file are reported together
+        let mut text = file.text.clone();
+        for _ in 0..MAX_SYNTAX_ERRORS {
+            match parse_file(idx, &text, self.dialect) {
+                Ok(pairs) => {
+                    let mut in_section = None;
+                    for pair in pairs {
+                        match pair.as_rule() {
+                            Rule::lib_start => {
+                                let name = pair.into_inner().next().unwrap().as_str();
+                                in_section = Some(name.to_lowercase());
+                            }
+                            Rule::lib_end => in_section = None,
+                            _ => {
+                                if let Some(path) = include_path(&pair, &file.path) {
+                                    includes.push(Include {
+                                        in_section: in_section.clone(),
+                                        path,
+                                        section: lib_section(&pair),
+                                        at: ParseError::at(&pair, String::new())
+                                            .in_file(&file.path),
+                                    });
+                                }
+                            }
+                        }
+                    }
+                    break;
+                }
+                Err(e) => {
+                    let mut err = ParseError::from_pest(&file.path, &text, e);
+                    // Most likely a title, a native netlist doesn't have one
+                    if idx == 0 && err.line == 1 && self.dialect == Dialect::Native {
+                        err.message = format!(
+                            "{}; SPICE3 netlists start with a title, run with --spice3",
+                            err.message
+                        );
+                    }
+                    let blanked = blank_line(&text, err.line);
+                    errors.push(err);
+
+                    if blanked == text {
+                        break;
+                    }
+                    text = blanked;
+                }
+            }
+        }
+
+        self.includes.push(includes);
+        idx
+    }
+
+    // Loads the files included from `section` of file `idx`, and from theirs in turn
+    fn reach(
+        &mut self,
+        idx: usize,
+        section: Option<String>,
+        seen: &mut Vec<(usize, Option<String>)>,
+        errors: &mut Vec<ParseError>,
+    ) {
+        let key = (idx, section);
+        if seen.contains(&key) {
+            return;
+        }
+        let section = key.1.clone();
+        seen.push(key);
+
+        let includes = self.includes[idx]
+            .iter()
+            .filter(|inc| inc.in_section == section)
+            .map(|inc| (inc.path.clone(), inc.section.clone(), inc.at.clone()))
+            .collect::<Vec<_>>();
+
+        for (inc_path, inc_section, at) in includes {
+            let inc_idx = match self.find(&inc_path) {
+                Some(i) => i,
+                None => match fs::read_to_string(&inc_path) {
+                    Ok(text) => self.add(inc_path, text, errors),
+                    Err(e) => {
+                        let msg =
+                            format!("cannot read included file {}: {}", inc_path.display(), e);
+                        errors.push(ParseError { message: msg, ..at });
+                        continue;
+                    }
+                },
+            };
+
+            self.reach(inc_idx, inc_section, seen, errors);
+        }
+    }
+
+    fn find(&self, path: &Path) -> Option<usize> {
+        let canonical = path.canonicalize().ok();
+
+        self.files
+            .iter()
+            .position(|f| f.path == path || f.path.canonicalize().ok() == canonical)
+    }
+
+    // Flattens the netlist in file order, expanding `.include` and `.lib` statements
+    pub fn lines(&self) -> ParseResult<Vec<Line<'_>>> {
+        let mut lines = Vec::new();
+        self.expand(0, None, &mut Vec::new(), &mut lines)?;
+
+        Ok(lines)
+    }
+
+    fn expand<'a>(
+        &'a self,
+        idx: usize,
+        section: Option<&str>,
+        stack: &mut Vec<(usize, Option<String>)>,
+        lines: &mut Vec<Line<'a>>,
+    ) -> ParseResult<()> {
+        let file = &self.files[idx];
+        stack.push((idx, section.map(str::to_lowercase)));
+
+        let mut current_section: Option<String> = None;
+
+        // Every file already parsed cleanly in `load`
+        for pair in parse_file(idx, &file.text, self.dialect).unwrap() {
+            match pair.as_rule() {
+                Rule::lib_start => {
+                    let name = pair.into_inner().next().unwrap().as_str();
+                    current_section = Some(name.to_lowercase());
+                    continue;
+                }
+                Rule::lib_end => {
+                    current_section = None;
+                    continue;
+                }
+                _ => (),
+            }
+
+            if current_section.as_deref() != section.map(str::to_lowercase).as_deref() {
+                continue;
+            }
+
+            match pair.as_rule() {
+                Rule::include_cmd | Rule::lib_cmd => {
+                    let inc_path = include_path(&pair, &file.path).unwrap();
+                    let inc_idx = self.find(&inc_path).unwrap();
+                    let inc_section = lib_section(&pair);
+                    let key = (inc_idx, inc_section.clone());
+
+                    if stack.contains(&key) {
+                        let chain = stack
+                            .iter()
+                            .chain(std::iter::once(&key))
+                            .map(|(i, _)| self.files[*i].path.display().to_string())
+                            .collect::<Vec<_>>();
+                        let msg = format!("include cycle {}", chain.join(" -> "));
+                        return Err(ParseError::at(&pair, msg).in_file(&file.path));
+                    }
+
+                    self.expand(inc_idx, inc_section.as_deref(), stack, lines)?;
+                }
+                Rule::EOI => (),
+                _ => lines.push(Line {
+                    pair,
+                    file: &file.path,
+                }),
+            }
+        }
+
+        stack.pop();
+        Ok(())
+    }
+}
+
+// Included files are plain lists of lines, only the top level netlist can have a title
+fn parse_file(
+    idx: usize,
+    text: &str,
+    dialect: Dialect,
+) -> Result<Pairs<'_, Rule>, pest::error::Error<Rule>> {
+    let rule = match (idx, dialect) {
+        (0, Dialect::Native) => Rule::file,
+        (0, Dialect::Spice3) => Rule::titled_file,
+        _ => Rule::lib_file,
+    };
+
+    let file = SpiceParser::parse(rule, text)?.next().unwrap(); // unwrap `file` rule, never fails
+
+    match rule {
+        Rule::titled_file => Ok(file.into_inner().nth(1).unwrap().into_inner()),
+        _ => Ok(file.into_inner()),
+    }
+}
+
+fn include_path(pair: &Pair<Rule>, including_file: &Path) -> Option<PathBuf> {
+    if !matches!(pair.as_rule(), Rule::include_cmd | Rule::lib_cmd) {
+        return None;
+    }
+
+    let path = pair.clone().into_inner().next().unwrap().as_str();
+    let path = path.trim_matches(|c| c == '"' || c == '\'');
+
+    Some(
+        including_file
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(path),
+    )
+}
+
+// The section a `.lib` statement selects, `.include` takes a file's unsectioned lines
+fn lib_section(pair: &Pair<Rule>) -> Option<String> {
+    match pair.as_rule() {
+        Rule::lib_cmd => Some(pair.clone().into_inner().nth(1)?.as_str().to_lowercase()),
+        _ => None,
+    }
+}
+
+fn blank_line(text: &str, line: usize) -> String {
+    text.split('\n')
+        .enumerate()
+        .map(|(i, l)| match i + 1 == line {
+            true => l.replace(|c: char| c != '\r', " "),
+            false => String::from(l),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .filter(|l| l.pair.as_rule() == Rule::node)
+            .map(|l| {
+                let node = l.pair.clone().into_inner().next().unwrap();
+                node.into_inner().next().unwrap().as_str().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_include() {
+        let sources = Sources::load("test/include.sp", Dialect::Native).unwrap();
+        let lines = sources.lines().unwrap();
+
+        assert_eq!(names(&lines), ["V1", "R1", "R2", "D1"]);
+        assert_eq!(lines[1].file, Path::new("test/lib/divider.inc"));
+        assert_eq!(lines[1].pair.line_col().0, 3);
+    }
+
+    #[test]
+    fn test_lib_sections() {
+        let sources = Sources::load("test/lib_corner.sp", Dialect::Native).unwrap();
+        let lines = sources.lines().unwrap();
+
+        let models = lines
+            .iter()
+            .filter(|l| l.pair.as_rule() == Rule::model_card)
+            .map(|l| l.pair.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(models, [".model dcorner D (IS=1p N=1.2)"]);
+    }
+
+    #[test]
+    fn test_lib_unused_section() {
+        let sources = Sources::load("test/lib_unused.sp", Dialect::Native).unwrap();
+        let lines = sources.lines().unwrap();
+
+        assert!(lines
+            .iter()
+            .any(|l| l.pair.as_str() == ".model dpart D (IS=10f N=1)"));
+    }
+
+    #[test]
+    fn test_lib_missing_in_selected_section() {
+        let errors = Sources::load("test/lib_missing.sp", Dialect::Native).unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].file, Path::new("test/lib/partial.lib"));
+        assert!(errors[0]
+            .message
+            .starts_with("cannot read included file test/lib/fast_models.inc"));
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let sources = Sources::load("test/include_cycle.sp", Dialect::Native).unwrap();
+        let err = sources.lines().unwrap_err();
+
+        assert_eq!(err.file, Path::new("test/lib/cycle_b.inc"));
+        assert_eq!(err.line, 2);
+        assert!(err.message.starts_with("include cycle"));
+    }
+
+    #[test]
+    fn test_include_missing() {
+        let errors = Sources::load("test/include_missing.sp", Dialect::Native).unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].file, Path::new("test/include_missing.sp"));
+        assert_eq!(errors[0].line, 3);
+        assert!(errors[0]
+            .message
+            .starts_with("cannot read included file test/nothere.inc"));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let errors = Sources::load("test/syntax_errors.sp", Dialect::Native).unwrap_err();
+        let lines = errors.iter().map(|e| e.line).collect::<Vec<_>>();
+
+        assert_eq!(lines, [4, 6]);
+        assert_eq!(errors[0].message, "expected value after `R=`");
+    }
+
+    #[test]
+    fn test_blank_line() {
+        assert_eq!(blank_line("a\nbc\r\nd", 2), "a\n  \r\nd");
+    }
+}
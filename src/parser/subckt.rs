@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use pest::iterators::Pair;
+
+use crate::parser::Rule;
+
+#[derive(Debug)]
+pub struct Subckt<'i> {
+    pub name: String,
+    pub ports: Vec<String>,
+    pub params: Vec<(String, Pair<'i, Rule>)>,
+    pub body: Vec<Pair<'i, Rule>>,
+    pub file: &'i Path,
+}
+
+pub type SubcktTable<'i> = HashMap<String, Subckt<'i>>;
+
+pub fn parse_subckt<'i>(def: Pair<'i, Rule>, file: &'i Path) -> Subckt<'i> {
+    let mut def_details = def.into_inner();
+
+    let name = def_details.next().unwrap().as_str();
+
+    let mut ports = Vec::new();
+    let mut params = Vec::new();
+    let mut body = Vec::new();
+
+    for item in def_details {
+        match item.as_rule() {
+            Rule::subckt_port => ports.push(String::from(item.as_str())),
+            Rule::subckt_params => params.extend(parse_params(item)),
+            Rule::node => body.push(item),
+            Rule::subckt_name => (),
+            _ => unreachable!(),
+        }
+    }
+
+    Subckt {
+        name: String::from(name),
+        ports,
+        params,
+        body,
+        file,
+    }
+}
+
+pub fn parse_params(params: Pair<Rule>) -> Vec<(String, Pair<Rule>)> {
+    params
+        .into_inner()
+        .map(|p| {
+            let mut p_details = p.into_inner();
+            let name = p_details.next().unwrap().as_str().to_lowercase();
+            (name, p_details.next().unwrap())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SpiceParser;
+    use pest::Parser;
+
+    #[test]
+    fn parse_subckt_generic() {
+        let def = SpiceParser::parse(
+            Rule::subckt,
+            ".subckt divider in out params: ratio=2 load=1k\nR1 in out R=1k\nR2 out 0 R=1k\n.ends divider",
+        )
+        .unwrap()
+        .next()
+        .unwrap();
+
+        let subckt = parse_subckt(def, Path::new("test.sp"));
+
+        assert_eq!(subckt.name, "divider");
+        assert_eq!(subckt.ports, ["in", "out"]);
+        assert_eq!(subckt.params.len(), 2);
+        assert_eq!(subckt.params[0].0, "ratio");
+        assert_eq!(subckt.params[1].0, "load");
+        assert_eq!(subckt.body.len(), 2);
+    }
+
+    #[test]
+    fn parse_subckt_no_params() {
+        let def = SpiceParser::parse(Rule::subckt, ".SUBCKT buf a b\nR1 a b R=1\n.ENDS")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let subckt = parse_subckt(def, Path::new("test.sp"));
+
+        assert_eq!(subckt.name, "buf");
+        assert_eq!(subckt.ports, ["a", "b"]);
+        assert!(subckt.params.is_empty());
+        assert_eq!(subckt.body.len(), 1);
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::ops::{Index, IndexMut};
+
+use ndarray::prelude::*;
+use num_complex::ComplexFloat;
+
+// Matrix assembled from (row, col, value) triplets. Stamps index it like a
+// dense matrix, entries that were never touched read as zero.
+#[derive(Debug, Clone)]
+pub struct SparseMatrix<T> {
+    nrows: usize,
+    ncols: usize,
+    triplets: Vec<(usize, usize, T)>,
+    positions: HashMap<(usize, usize), usize>,
+    zero: T,
+}
+
+// Compressed sparse column storage, rows sorted within each column
+#[derive(Debug, Clone, PartialEq)]
+pub struct CscMatrix<T> {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub col_ptr: Vec<usize>,
+    pub row_idx: Vec<usize>,
+    pub values: Vec<T>,
+}
+
+impl<T: ComplexFloat> SparseMatrix<T> {
+    pub fn zeros((nrows, ncols): (usize, usize)) -> Self {
+        SparseMatrix {
+            nrows,
+            ncols,
+            triplets: Vec::new(),
+            positions: HashMap::new(),
+            zero: T::zero(),
+        }
+    }
+
+    pub fn nrows(&self) -> usize {
+        self.nrows
+    }
+
+    // Entries ever touched, stamps only add to them
+    pub fn nnz(&self) -> usize {
+        self.triplets.len()
+    }
+
+    pub fn map<U: ComplexFloat>(&self, f: impl Fn(T) -> U) -> SparseMatrix<U> {
+        SparseMatrix {
+            nrows: self.nrows,
+            ncols: self.ncols,
+            triplets: self
+                .triplets
+                .iter()
+                .map(|&(i, j, v)| (i, j, f(v)))
+                .collect(),
+            positions: self.positions.clone(),
+            zero: U::zero(),
+        }
+    }
+
+    pub fn dot(&self, x: &Array1<T>) -> Array1<T> {
+        let mut y = Array1::from_elem(self.nrows, T::zero());
+
+        for &(i, j, v) in self.triplets.iter() {
+            y[i] = y[i] + v * x[j];
+        }
+
+        y
+    }
+
+    pub fn to_dense(&self) -> Array2<T> {
+        let mut a = Array2::from_elem((self.nrows, self.ncols), T::zero());
+
+        for &(i, j, v) in self.triplets.iter() {
+            a[(i, j)] = v;
+        }
+
+        a
+    }
+
+    #[cfg(test)]
+    pub fn to_csc(&self) -> CscMatrix<T> {
+        self.to_csc_slots().0
+    }
+
+    // Also where each entry went, for `scatter` to copy new values into the same pattern
+    pub fn to_csc_slots(&self) -> (CscMatrix<T>, Vec<usize>) {
+        let mut col_ptr = vec![0; self.ncols + 1];
+        for &(_, j, _) in self.triplets.iter() {
+            col_ptr[j + 1] += 1;
+        }
+        for j in 0..self.ncols {
+            col_ptr[j + 1] += col_ptr[j];
+        }
+
+        let mut order = (0..self.triplets.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&k| (self.triplets[k].1, self.triplets[k].0));
+
+        let mut slots = vec![0; order.len()];
+        for (slot, &k) in order.iter().enumerate() {
+            slots[k] = slot;
+        }
+
+        let csc = CscMatrix {
+            nrows: self.nrows,
+            ncols: self.ncols,
+            col_ptr,
+            row_idx: order.iter().map(|&k| self.triplets[k].0).collect(),
+            values: order.iter().map(|&k| self.triplets[k].2).collect(),
+        };
+
+        (csc, slots)
+    }
+
+    pub fn scatter(&self, slots: &[usize], values: &mut [T]) {
+        for (&(_, _, v), &slot) in self.triplets.iter().zip(slots) {
+            values[slot] = v;
+        }
+    }
+
+    // Values of `base` for its entries, zero for the ones added since. `self`
+    // has to have started as a clone of a matrix with the same entries as `base`.
+    pub fn reset_to(&mut self, base: &SparseMatrix<T>) {
+        debug_assert!(self
+            .triplets
+            .iter()
+            .zip(base.triplets.iter())
+            .all(|(a, b)| (a.0, a.1) == (b.0, b.1)));
+
+        let n = base.triplets.len();
+        for (t, b) in self.triplets[..n].iter_mut().zip(base.triplets.iter()) {
+            t.2 = b.2;
+        }
+        for t in self.triplets[n..].iter_mut() {
+            t.2 = T::zero();
+        }
+    }
+}
+
+impl<T: ComplexFloat> Index<(usize, usize)> for SparseMatrix<T> {
+    type Output = T;
+
+    fn index(&self, (i, j): (usize, usize)) -> &T {
+        assert!(i < self.nrows && j < self.ncols, "index out of bounds");
+
+        match self.positions.get(&(i, j)) {
+            Some(&k) => &self.triplets[k].2,
+            None => &self.zero,
+        }
+    }
+}
+
+impl<T: ComplexFloat> IndexMut<(usize, usize)> for SparseMatrix<T> {
+    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut T {
+        assert!(i < self.nrows && j < self.ncols, "index out of bounds");
+
+        let k = *self.positions.entry((i, j)).or_insert_with(|| {
+            self.triplets.push((i, j, T::zero()));
+            self.triplets.len() - 1
+        });
+
+        &mut self.triplets[k].2
+    }
+}
+
+impl<T: ComplexFloat> PartialEq for SparseMatrix<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_dense() == other.to_dense()
+    }
+}
+
+impl<T: ComplexFloat> PartialEq<Array2<T>> for SparseMatrix<T> {
+    fn eq(&self, other: &Array2<T>) -> bool {
+        self.to_dense() == other
+    }
+}
+
+impl<T: ComplexFloat> CscMatrix<T> {
+    // Columns given as (row, value) lists
+    pub fn from_columns(nrows: usize, cols: Vec<Vec<(usize, T)>>) -> Self {
+        let mut col_ptr = vec![0];
+        let mut row_idx = Vec::new();
+        let mut values = Vec::new();
+
+        for col in cols.iter() {
+            for &(i, v) in col.iter() {
+                row_idx.push(i);
+                values.push(v);
+            }
+            col_ptr.push(row_idx.len());
+        }
+
+        CscMatrix {
+            nrows,
+            ncols: cols.len(),
+            col_ptr,
+            row_idx,
+            values,
+        }
+    }
+
+    pub fn col(&self, j: usize) -> impl Iterator<Item = (usize, T)> + '_ {
+        let range = self.col_ptr[j]..self.col_ptr[j + 1];
+
+        self.row_idx[range.clone()]
+            .iter()
+            .copied()
+            .zip(self.values[range].iter().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index() {
+        let mut a = SparseMatrix::zeros((2, 3));
+        a[(0, 2)] += 1.0;
+        a[(0, 2)] += 2.0;
+        a[(1, 0)] -= 4.0;
+
+        assert_eq!(a[(0, 2)], 3.0);
+        assert_eq!(a[(1, 1)], 0.0);
+        assert_eq!(a.to_csc().values.len(), 2);
+        assert_eq!(a, array![[0.0, 0.0, 3.0], [-4.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_out_of_bounds() {
+        let mut a = SparseMatrix::zeros((2, 2));
+        a[(2, 0)] = 1.0;
+    }
+
+    #[test]
+    fn test_eq_ignores_stored_zeros() {
+        let mut a: SparseMatrix<f64> = SparseMatrix::zeros((2, 2));
+        a[(0, 1)] += 1.0;
+        a[(0, 1)] -= 1.0;
+
+        assert_eq!(a, SparseMatrix::zeros((2, 2)));
+    }
+
+    #[test]
+    fn test_dot() {
+        let mut a = SparseMatrix::zeros((2, 2));
+        a[(0, 0)] = 1.0;
+        a[(0, 1)] = 2.0;
+        a[(1, 1)] = 4.0;
+
+        assert_eq!(a.dot(&array![1.0, 2.0]), array![5.0, 8.0]);
+    }
+
+    #[test]
+    fn test_to_csc() {
+        let mut a = SparseMatrix::zeros((3, 3));
+        a[(2, 1)] = 3.0;
+        a[(0, 0)] = 1.0;
+        a[(0, 1)] = 2.0;
+        a[(1, 2)] = 4.0;
+
+        let csc = a.to_csc();
+
+        assert_eq!(csc.col_ptr, [0, 1, 3, 4]);
+        assert_eq!(csc.row_idx, [0, 0, 2, 1]);
+        assert_eq!(csc.values, [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(csc.col(1).collect::<Vec<_>>(), [(0, 2.0), (2, 3.0)]);
+    }
+
+    #[test]
+    fn test_scatter() {
+        let mut a = SparseMatrix::zeros((2, 2));
+        a[(1, 0)] = 3.0;
+        a[(0, 0)] = 1.0;
+        let (mut csc, slots) = a.to_csc_slots();
+
+        a[(1, 0)] = 5.0;
+        a.scatter(&slots, &mut csc.values);
+
+        assert_eq!(csc.values, [1.0, 5.0]);
+    }
+
+    #[test]
+    fn test_reset_to() {
+        let mut base = SparseMatrix::zeros((2, 2));
+        base[(0, 0)] = 1.0;
+        let mut a = base.clone();
+        a[(0, 0)] += 2.0;
+        a[(1, 1)] = 4.0;
+
+        base[(0, 0)] = 7.0;
+        a.reset_to(&base);
+
+        assert_eq!(a, array![[7.0, 0.0], [0.0, 0.0]]);
+        assert_eq!(a.nnz(), 2);
+    }
+
+    #[test]
+    fn test_map() {
+        let mut a = SparseMatrix::zeros((1, 1));
+        a[(0, 0)] = 2.0;
+
+        let b = a.map(|v| num_complex::Complex64::new(0.0, v));
+
+        assert_eq!(b[(0, 0)], num_complex::Complex64::new(0.0, 2.0));
+    }
+}
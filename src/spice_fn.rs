@@ -3,6 +3,7 @@ pub enum SpiceFn {
     Pulse(PulseParams),
     Sine(SineParams),
     Exp(ExpParams),
+    Pwl(PwlParams),
 }
 
 impl SpiceFn {
@@ -11,6 +12,20 @@ impl SpiceFn {
             SpiceFn::Sine(p) => p.eval(t),
             SpiceFn::Pulse(p) => p.eval(t),
             SpiceFn::Exp(p) => p.eval(t),
+            SpiceFn::Pwl(p) => p.eval(t),
+        }
+    }
+
+    // First discontinuity of the waveform or its slope strictly after `t`
+    pub fn next_breakpoint(&self, t: &f64) -> Option<f64> {
+        match &self {
+            SpiceFn::Sine(p) => (p.delay > *t).then_some(p.delay),
+            SpiceFn::Pulse(p) => p.next_corner(t),
+            SpiceFn::Exp(p) => [p.rise_delay, p.fall_delay]
+                .into_iter()
+                .filter(|b| b > t)
+                .reduce(f64::min),
+            SpiceFn::Pwl(p) => p.next_corner(t),
         }
     }
 }
@@ -20,11 +35,20 @@ pub struct SineParams {
     pub offset: f64,
     pub amplitude: f64,
     pub freq: f64,
+    pub delay: f64,
+    pub damping: f64,
 }
 
 impl SineParams {
     fn eval(&self, t: &f64) -> f64 {
-        self.offset + self.amplitude * (2.0 * std::f64::consts::PI * self.freq * t).sin()
+        if *t < self.delay {
+            return self.offset;
+        }
+
+        let t = t - self.delay;
+        let envelope = (-t * self.damping).exp();
+
+        self.offset + self.amplitude * envelope * (2.0 * std::f64::consts::PI * self.freq * t).sin()
     }
 }
 
@@ -67,6 +91,22 @@ impl PulseParams {
         }
     }
 
+    fn next_corner(&self, t: &f64) -> Option<f64> {
+        let corners = [
+            self.delay,
+            self.delay + self.t_rise,
+            self.delay + self.pulse_width - self.t_fall,
+            self.delay + self.pulse_width,
+        ];
+        let period_start = (t / self.period).floor() * self.period;
+
+        [period_start, period_start + self.period]
+            .into_iter()
+            .flat_map(|start| corners.map(|c| start + c))
+            .filter(|b| b > t)
+            .reduce(f64::min)
+    }
+
     fn eval(&self, t: &f64) -> f64 {
         let state = self.get_state(t);
 
@@ -123,3 +163,173 @@ impl ExpParams {
         }
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct PwlParams {
+    // (time, value) corners, in increasing time
+    pub points: Vec<(f64, f64)>,
+    pub delay: f64,
+    // Time the waveform loops back to after its last corner
+    pub repeat: Option<f64>,
+}
+
+impl PwlParams {
+    fn t_last(&self) -> f64 {
+        self.points.last().unwrap().0
+    }
+
+    // Time into the first pass of the waveform
+    fn local_time(&self, t: &f64) -> f64 {
+        let t = t - self.delay;
+        let t_last = self.t_last();
+
+        match self.repeat {
+            Some(r) if t > t_last && t_last > r => r + (t - t_last) % (t_last - r),
+            _ => t,
+        }
+    }
+
+    fn eval(&self, t: &f64) -> f64 {
+        let t = self.local_time(t);
+        let idx = self.points.partition_point(|(t_i, _)| *t_i <= t);
+
+        match idx {
+            0 => self.points[0].1,
+            i if i == self.points.len() => self.points[i - 1].1,
+            i => {
+                let (t0, v0) = self.points[i - 1];
+                let (t1, v1) = self.points[i];
+                v0 + (v1 - v0) * (t - t0) / (t1 - t0)
+            }
+        }
+    }
+
+    // First corner strictly after `t`, for the transient to land on
+    pub fn next_corner(&self, t: &f64) -> Option<f64> {
+        let t_rel = t - self.delay;
+
+        if let Some((t_c, _)) = self.points.iter().find(|(t_c, _)| *t_c > t_rel) {
+            return Some(t_c + self.delay);
+        }
+
+        let r = self.repeat?;
+        let t_last = self.t_last();
+        let period = t_last - r;
+        if period <= 0.0 {
+            return None;
+        }
+
+        let n_periods = ((t_rel - t_last) / period).floor();
+        let start = t_last + n_periods * period;
+        let local = r + (t_rel - start);
+
+        self.points
+            .iter()
+            .map(|(t_c, _)| *t_c)
+            .find(|t_c| *t_c > r && *t_c > local)
+            .map(|t_c| start + (t_c - r) + self.delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pulse_next_breakpoint() {
+        let pulse = SpiceFn::Pulse(PulseParams {
+            v1: 0.0,
+            v2: 1.0,
+            delay: 1.0,
+            t_rise: 1.0,
+            t_fall: 1.0,
+            pulse_width: 4.0,
+            period: 10.0,
+        });
+
+        assert_eq!(pulse.next_breakpoint(&0.0), Some(1.0));
+        assert_eq!(pulse.next_breakpoint(&1.0), Some(2.0));
+        assert_eq!(pulse.next_breakpoint(&2.5), Some(4.0));
+        assert_eq!(pulse.next_breakpoint(&4.0), Some(5.0));
+        assert_eq!(pulse.next_breakpoint(&5.0), Some(11.0));
+        assert_eq!(pulse.next_breakpoint(&23.0), Some(24.0));
+    }
+
+    #[test]
+    fn test_exp_next_breakpoint() {
+        let exp = SpiceFn::Exp(ExpParams {
+            v1: 0.0,
+            v2: 1.0,
+            rise_delay: 1.0,
+            rise_tau: 1.0,
+            fall_delay: 5.0,
+            fall_tau: 1.0,
+        });
+
+        assert_eq!(exp.next_breakpoint(&0.0), Some(1.0));
+        assert_eq!(exp.next_breakpoint(&1.0), Some(5.0));
+        assert_eq!(exp.next_breakpoint(&5.0), None);
+    }
+
+    #[test]
+    fn test_sine_next_breakpoint() {
+        let sine = SpiceFn::Sine(SineParams {
+            offset: 0.0,
+            amplitude: 1.0,
+            freq: 1.0,
+            delay: 2.0,
+            damping: 0.0,
+        });
+
+        assert_eq!(sine.next_breakpoint(&0.0), Some(2.0));
+        assert_eq!(sine.next_breakpoint(&2.0), None);
+    }
+
+    fn test_pwl(repeat: Option<f64>) -> PwlParams {
+        PwlParams {
+            points: vec![(0.0, 0.0), (1.0, 2.0), (2.0, 2.0), (3.0, 0.0)],
+            delay: 0.5,
+            repeat,
+        }
+    }
+
+    #[test]
+    fn test_pwl_eval() {
+        let pwl = test_pwl(None);
+
+        assert_eq!(pwl.eval(&0.0), 0.0);
+        assert_eq!(pwl.eval(&1.0), 1.0);
+        assert_eq!(pwl.eval(&2.0), 2.0);
+        assert_eq!(pwl.eval(&3.0), 1.0);
+        assert_eq!(pwl.eval(&10.0), 0.0);
+    }
+
+    #[test]
+    fn test_pwl_eval_repeat() {
+        let pwl = test_pwl(Some(1.0));
+
+        // Loops over 1..3 once past the last corner at 3.5
+        assert_eq!(pwl.eval(&4.0), 2.0);
+        assert_eq!(pwl.eval(&5.0), 1.0);
+        assert_eq!(pwl.eval(&6.5), 2.0);
+    }
+
+    #[test]
+    fn test_pwl_next_corner() {
+        let pwl = test_pwl(None);
+
+        assert_eq!(pwl.next_corner(&0.0), Some(0.5));
+        assert_eq!(pwl.next_corner(&0.5), Some(1.5));
+        assert_eq!(pwl.next_corner(&3.0), Some(3.5));
+        assert_eq!(pwl.next_corner(&3.5), None);
+    }
+
+    #[test]
+    fn test_pwl_next_corner_repeat() {
+        let pwl = test_pwl(Some(1.0));
+
+        assert_eq!(pwl.next_corner(&3.5), Some(4.5));
+        assert_eq!(pwl.next_corner(&4.5), Some(5.5));
+        assert_eq!(pwl.next_corner(&6.0), Some(6.5));
+    }
+}